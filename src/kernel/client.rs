@@ -0,0 +1,457 @@
+use anyhow::{Context, Result, anyhow, bail};
+use bytes::Bytes;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use zeromq::prelude::*;
+use zeromq::util::PeerIdentity;
+use zeromq::{DealerSocket, ReqSocket, SocketOptions, SubSocket, ZmqMessage};
+
+use super::connection::ConnectionInfo;
+use super::message::{Message, Signer};
+use crate::notebook_util::{Cell, CodeCell, MimeBundle, MultilineString, Notebook, Output};
+
+/// How long [`KernelClient::connect`] waits for the kernel's sockets.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The kernel sockets a message can travel on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Requests and replies, e.g. `execute_request`.
+    Shell,
+    /// Broadcasts: outputs, status and the code being run.
+    IoPub,
+    /// Requests *from* the kernel, e.g. for `input()`.
+    Stdin,
+    /// Like shell, but served even while the kernel is busy.
+    Control,
+}
+
+/// A connection to a running Jupyter kernel.
+///
+/// Each socket is served by a task on a small runtime owned by the client;
+/// everything the kernel sends arrives in one queue that is read with
+/// [`KernelClient::recv_timeout`] or [`KernelClient::try_recv`], so callers
+/// never have to be async.
+pub struct KernelClient {
+    info: ConnectionInfo,
+    session: String,
+    runtime: Runtime,
+    shell: UnboundedSender<Message>,
+    stdin: UnboundedSender<Message>,
+    control: UnboundedSender<Message>,
+    events: mpsc::Receiver<(Channel, Message)>,
+}
+
+impl KernelClient {
+    /// Connects to the shell, iopub, stdin and control sockets of the
+    /// kernel described by `info`.
+    ///
+    /// Returns an error if the connection info is unsupported or the kernel
+    /// doesn't answer within 30 seconds.
+    pub fn connect(info: ConnectionInfo) -> Result<Self> {
+        info.check()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .context("Failed to start the kernel client runtime")?;
+        let session = uuid::Uuid::new_v4().simple().to_string();
+        let signer = Signer::new(&info.key);
+        let (events_tx, events) = mpsc::channel();
+
+        let connect = async {
+            // The kernel routes stdin requests by identity, so shell and
+            // stdin must share one.
+            let identity = PeerIdentity::new();
+            let mut options = SocketOptions::default();
+            options.peer_identity(identity.clone());
+            let mut shell = DealerSocket::with_options(options);
+            shell.connect(&info.endpoint(info.shell_port)).await?;
+            let mut options = SocketOptions::default();
+            options.peer_identity(identity);
+            let mut stdin = DealerSocket::with_options(options);
+            stdin.connect(&info.endpoint(info.stdin_port)).await?;
+            let mut control = DealerSocket::new();
+            control.connect(&info.endpoint(info.control_port)).await?;
+            let mut iopub = SubSocket::new();
+            iopub.connect(&info.endpoint(info.iopub_port)).await?;
+            iopub.subscribe("").await?;
+            Ok::<_, zeromq::ZmqError>((shell, stdin, control, iopub))
+        };
+        let sockets =
+            runtime.block_on(async { tokio::time::timeout(CONNECT_TIMEOUT, connect).await });
+        let (shell, stdin, control, mut iopub) = sockets
+            .map_err(|_| anyhow!("Timed out connecting to the kernel at {}", info.ip))?
+            .context("Failed to connect to the kernel")?;
+
+        let spawn_dealer = |channel, mut socket: DealerSocket| {
+            let (tx, mut rx) = unbounded_channel::<Message>();
+            let (signer, events) = (signer.clone(), events_tx.clone());
+            runtime.spawn(async move {
+                loop {
+                    tokio::select! {
+                        outgoing = rx.recv() => {
+                            let Some(message) = outgoing else { break };
+                            let Ok(frames) = message.to_frames(&signer) else { continue };
+                            if socket.send(to_zmq(frames)).await.is_err() {
+                                break;
+                            }
+                        }
+                        incoming = socket.recv() => {
+                            let Ok(frames) = incoming else { break };
+                            // Unsigned or garbled messages are dropped, like
+                            // jupyter_client does.
+                            if let Ok(message) = Message::from_frames(from_zmq(frames), &signer)
+                                && events.send((channel, message)).is_err()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                }
+            });
+            tx
+        };
+        let shell = spawn_dealer(Channel::Shell, shell);
+        let stdin = spawn_dealer(Channel::Stdin, stdin);
+        let control = spawn_dealer(Channel::Control, control);
+        runtime.spawn(async move {
+            while let Ok(frames) = iopub.recv().await {
+                if let Ok(message) = Message::from_frames(from_zmq(frames), &signer)
+                    && events_tx.send((Channel::IoPub, message)).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            info,
+            session,
+            runtime,
+            shell,
+            stdin,
+            control,
+            events,
+        })
+    }
+
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    /// The session id stamped on every message this client sends.
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Sends `message` to the kernel.
+    ///
+    /// Returns an error for [`Channel::IoPub`], which is receive-only, or if
+    /// the connection is gone.
+    pub fn send(&self, channel: Channel, message: Message) -> Result<()> {
+        let sender = match channel {
+            Channel::Shell => &self.shell,
+            Channel::Stdin => &self.stdin,
+            Channel::Control => &self.control,
+            Channel::IoPub => bail!("Cannot send on the iopub channel"),
+        };
+        sender
+            .send(message)
+            .map_err(|_| anyhow!("The kernel connection is closed"))
+    }
+
+    /// Sends a new `msg_type` request and returns its `msg_id`, which
+    /// replies and outputs carry in their parent header.
+    pub fn request(&self, channel: Channel, msg_type: &str, content: Value) -> Result<String> {
+        let message = Message::new(msg_type, &self.session, content);
+        let msg_id = message.header.msg_id.clone();
+        self.send(channel, message)?;
+        Ok(msg_id)
+    }
+
+    /// Waits up to `timeout` for the next message from the kernel.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<(Channel, Message)> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next message from the kernel if one is waiting.
+    pub fn try_recv(&self) -> Option<(Channel, Message)> {
+        self.events.try_recv().ok()
+    }
+
+    /// Asks for the kernel's `kernel_info_reply`, retrying until iopub is
+    /// flowing too, so no output of the next request is lost to the
+    /// subscription still being set up.
+    ///
+    /// Returns an error if the kernel doesn't answer within `timeout`.
+    pub fn kernel_info(&self, timeout: Duration) -> Result<Value> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            let msg_id = self.request(Channel::Shell, "kernel_info_request", json!({}))?;
+            let retry = (Instant::now() + Duration::from_secs(1)).min(deadline);
+            let (mut reply, mut iopub) = (None, false);
+            while let Some((channel, message)) =
+                self.recv_timeout(retry.saturating_duration_since(Instant::now()))
+            {
+                match channel {
+                    Channel::IoPub => iopub = true,
+                    Channel::Shell if message.parent_id() == Some(msg_id.as_str()) => {
+                        reply = Some(message.content)
+                    }
+                    _ => {}
+                }
+                if let (Some(reply), true) = (&reply, iopub) {
+                    return Ok(reply.clone());
+                }
+            }
+        }
+        bail!("Timed out waiting for the kernel to start")
+    }
+
+    /// Sends an `execute_request` for `code` and returns its `msg_id`.
+    ///
+    /// With `allow_stdin`, the kernel may answer `input()` with an
+    /// `input_request` on the stdin channel, which must be replied to;
+    /// without, `input()` raises an error.
+    pub fn execute(&self, code: &str, allow_stdin: bool) -> Result<String> {
+        self.request(
+            Channel::Shell,
+            "execute_request",
+            json!({
+                "code": code,
+                "silent": false,
+                "store_history": true,
+                "user_expressions": {},
+                "allow_stdin": allow_stdin,
+                "stop_on_error": true,
+            }),
+        )
+    }
+
+    /// Runs `cell`, replacing its outputs and execution count with what the
+    /// kernel sends back, and returns the `execute_reply`.
+    ///
+    /// Blocks until the kernel is idle again. Returns an error if that takes
+    /// longer than `timeout`.
+    pub fn execute_cell(&self, cell: &mut CodeCell, timeout: Duration) -> Result<Message> {
+        cell.outputs.clear();
+        cell.execution_count = None;
+        let msg_id = self.execute(cell.source.as_str(), false)?;
+        let deadline = Instant::now() + timeout;
+        let (mut reply, mut idle, mut clear_pending) = (None, false, false);
+        let mut displays = Displays::default();
+        while reply.is_none() || !idle {
+            let (channel, message) = self
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .context("Timed out waiting for the kernel")?;
+            if message.parent_id() != Some(msg_id.as_str()) {
+                continue;
+            }
+            match channel {
+                Channel::Shell if message.msg_type() == "execute_reply" => {
+                    if let Some(count) = message.content["execution_count"].as_u64() {
+                        cell.execution_count = Some(count as u32);
+                    }
+                    reply = Some(message);
+                }
+                Channel::IoPub if message.msg_type() == "status" => {
+                    idle = message.content["execution_state"] == "idle";
+                }
+                Channel::IoPub => cell.apply_iopub(&message, &mut clear_pending, &mut displays),
+                _ => {}
+            }
+        }
+        Ok(reply.unwrap())
+    }
+
+    /// Checks on the heartbeat socket that the kernel still answers.
+    pub fn is_alive(&self, timeout: Duration) -> bool {
+        let endpoint = self.info.endpoint(self.info.hb_port);
+        let ping = async {
+            let mut socket = ReqSocket::new();
+            socket.connect(&endpoint).await?;
+            socket.send(ZmqMessage::from("ping")).await?;
+            socket.recv().await
+        };
+        matches!(
+            self.runtime
+                .block_on(async { tokio::time::timeout(timeout, ping).await }),
+            Ok(Ok(reply)) if reply.get(0).is_some_and(|frame| frame.as_ref() == b"ping")
+        )
+    }
+}
+
+impl CodeCell {
+    /// Applies an iopub message sent on behalf of this cell: outputs are
+    /// appended, `clear_output` empties them and `execute_input` sets the
+    /// execution count.
+    ///
+    /// Stream text is added to the last output when it comes from the same
+    /// stream, see [`CodeCell::push_stream`]. `clear_pending` carries a
+    /// `clear_output(wait=True)` over to the next output, which replaces the
+    /// old ones without the cell flickering empty in between. `displays`
+    /// keeps track of the displays the outputs show.
+    pub fn apply_iopub(
+        &mut self,
+        message: &Message,
+        clear_pending: &mut bool,
+        displays: &mut Displays,
+    ) {
+        let cell_id = self.id.clone().unwrap_or_default();
+        match message.msg_type() {
+            "execute_input" => {
+                if let Some(count) = message.content["execution_count"].as_u64() {
+                    self.execution_count = Some(count as u32);
+                }
+                return;
+            }
+            "clear_output" if message.content["wait"] == true => {
+                *clear_pending = true;
+                return;
+            }
+            "clear_output" => {
+                self.outputs.clear();
+                displays.forget_cell(&cell_id);
+                *clear_pending = false;
+                return;
+            }
+            "update_display_data" => {
+                displays.update_cell(self, message);
+                return;
+            }
+            _ => {}
+        }
+        let Some(output) = message.to_output() else {
+            return;
+        };
+        if std::mem::take(clear_pending) {
+            self.outputs.clear();
+            displays.forget_cell(&cell_id);
+        }
+        if let Output::Stream { name, text, .. } = &output {
+            self.push_stream(name, text);
+        } else {
+            self.outputs.push(output);
+            if let Some(display_id) = message.display_id() {
+                displays.add(display_id, &cell_id, self.outputs.len() - 1);
+            }
+        }
+    }
+
+    /// Writes `text` to the stream `name`, adding it to the last output if
+    /// that is the same stream, see
+    /// [`crate::notebook_util::MultilineString::push_stream`].
+    pub fn push_stream(&mut self, name: &str, text: &str) {
+        if let Some(Output::Stream {
+            name: last_name,
+            text: last_text,
+            ..
+        }) = self.outputs.last_mut()
+            && last_name == name
+        {
+            last_text.push_stream(text);
+            return;
+        }
+        let mut output = MultilineString::default();
+        output.push_stream(text);
+        self.outputs.push(Output::Stream {
+            name: name.to_string(),
+            text: output,
+            other: json!({}),
+        });
+    }
+}
+
+/// The outputs showing each display, by the display id a kernel gave it in
+/// `transient`, for its later `update_display_data` messages.
+///
+/// nbformat doesn't store display ids, so they are kept here, next to the
+/// notebook. Outputs are found by the id of their cell and their index in
+/// it.
+#[derive(Debug, Default)]
+pub struct Displays {
+    outputs: HashMap<String, Vec<(String, usize)>>,
+}
+
+impl Displays {
+    /// Remembers that output `index` of the cell `cell_id` shows the
+    /// display `display_id`.
+    fn add(&mut self, display_id: &str, cell_id: &str, index: usize) {
+        self.outputs
+            .entry(display_id.to_string())
+            .or_default()
+            .push((cell_id.to_string(), index));
+    }
+
+    /// Forgets the displays the cell `cell_id` showed, once its outputs are
+    /// cleared.
+    pub fn forget_cell(&mut self, cell_id: &str) {
+        self.outputs.retain(|_, outputs| {
+            outputs.retain(|(id, _)| id != cell_id);
+            !outputs.is_empty()
+        });
+    }
+
+    /// Applies an `update_display_data` message to every output showing its
+    /// display, in whichever cell of `notebook` it is. Returns whether there
+    /// were any.
+    pub fn update(&self, notebook: &mut Notebook, message: &Message) -> bool {
+        let mut updated = false;
+        for cell in &mut notebook.cells {
+            if let Cell::Code(cell) = cell {
+                updated |= self.update_cell(cell, message);
+            }
+        }
+        updated
+    }
+
+    /// Applies an `update_display_data` message to the outputs of `cell`
+    /// showing its display. Returns whether there were any.
+    fn update_cell(&self, cell: &mut CodeCell, message: &Message) -> bool {
+        let Some(outputs) = message
+            .display_id()
+            .and_then(|display_id| self.outputs.get(display_id))
+        else {
+            return false;
+        };
+        let Ok(new_data) = serde_json::from_value::<MimeBundle>(message.content["data"].clone())
+        else {
+            return false;
+        };
+        let cell_id = cell.id.as_deref().unwrap_or_default();
+        let mut updated = false;
+        for (_, index) in outputs.iter().filter(|(id, _)| id == cell_id) {
+            if let Some(
+                Output::DisplayData { data, metadata, .. }
+                | Output::ExecuteResult { data, metadata, .. },
+            ) = cell.outputs.get_mut(*index)
+            {
+                *data = new_data.clone();
+                *metadata = match &message.content["metadata"] {
+                    Value::Null => json!({}),
+                    new_metadata => new_metadata.clone(),
+                };
+                updated = true;
+            }
+        }
+        updated
+    }
+}
+
+fn to_zmq(frames: Vec<Vec<u8>>) -> ZmqMessage {
+    let frames: Vec<Bytes> = frames.into_iter().map(Bytes::from).collect();
+    ZmqMessage::try_from(frames).expect("messages have at least one frame")
+}
+
+fn from_zmq(message: ZmqMessage) -> Vec<Vec<u8>> {
+    message
+        .into_vec()
+        .into_iter()
+        .map(|frame| frame.to_vec())
+        .collect()
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::ops::Range;
+
+use super::client::{Channel, KernelClient};
+use super::message::Message;
+
+/// A way to complete the code at the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub text: String,
+    /// The bytes of the code the completion replaces.
+    pub range: Range<usize>,
+    /// What the completion is, e.g. `function` or `module`, when known.
+    pub kind: Option<String>,
+    pub signature: Option<String>,
+}
+
+impl Completion {
+    /// Replaces the completion's range of `code` with it. Returns the new
+    /// code and the cursor right after the inserted text.
+    pub fn apply(&self, code: &str) -> (String, usize) {
+        let start = floor_char_boundary(code, self.range.start);
+        let end = floor_char_boundary(code, self.range.end).max(start);
+        let code = format!("{}{}{}", &code[..start], self.text, &code[end..]);
+        (code, start + self.text.len())
+    }
+}
+
+/// Reads the completions of a `complete_reply` for `code`.
+///
+/// The type hints of IPython's `_jupyter_types_experimental` metadata are
+/// used when present; they also give each completion its own range.
+pub fn parse_complete_reply(content: &Value, code: &str) -> Vec<Completion> {
+    if content["status"] != "ok" {
+        return vec![];
+    }
+    let byte = |position: &Value| byte_offset(code, position.as_u64().unwrap_or(0) as usize);
+    let text = |value: &Value| {
+        value
+            .as_str()
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+    };
+    if let Some(hints) = content["metadata"]["_jupyter_types_experimental"].as_array() {
+        return hints
+            .iter()
+            .filter_map(|hint| {
+                Some(Completion {
+                    text: hint["text"].as_str()?.to_string(),
+                    range: byte(&hint["start"])..byte(&hint["end"]),
+                    kind: text(&hint["type"]).filter(|kind| kind != "<unknown>"),
+                    signature: text(&hint["signature"]),
+                })
+            })
+            .collect();
+    }
+    let range = byte(&content["cursor_start"])..byte(&content["cursor_end"]);
+    content["matches"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|text| {
+            Some(Completion {
+                text: text.as_str()?.to_string(),
+                range: range.clone(),
+                kind: None,
+                signature: None,
+            })
+        })
+        .collect()
+}
+
+/// The kernel's answer to a completion request.
+pub struct CompletionReply {
+    /// The code and cursor the completions are for.
+    pub code: String,
+    pub cursor: usize,
+    pub completions: Vec<Completion>,
+}
+
+/// A completion request waiting for its reply.
+struct Pending {
+    msg_id: String,
+    code: String,
+    cursor: usize,
+}
+
+/// Asks the kernel for completions. Like [`super::ExecutionQueue`], it
+/// never blocks: the caller feeds it the kernel's messages with
+/// [`Completer::handle`] and picks up the reply with
+/// [`Completer::take_reply`].
+#[derive(Default)]
+pub struct Completer {
+    pending: Option<Pending>,
+    reply: Option<CompletionReply>,
+}
+
+impl Completer {
+    /// Asks for the completions of `code` at byte offset `cursor`. A
+    /// request still waiting for its reply is dropped.
+    pub fn request(&mut self, client: &KernelClient, code: &str, cursor: usize) -> Result<()> {
+        let cursor = floor_char_boundary(code, cursor);
+        let msg_id = client.request(
+            Channel::Shell,
+            "complete_request",
+            // the protocol counts unicode code points
+            json!({ "code": code, "cursor_pos": code[..cursor].chars().count() }),
+        )?;
+        self.pending = Some(Pending {
+            msg_id,
+            code: code.to_string(),
+            cursor,
+        });
+        self.reply = None;
+        Ok(())
+    }
+
+    /// Whether a request waits for its reply.
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Drops the request waiting for its reply and the reply not picked up.
+    pub fn cancel(&mut self) {
+        self.pending = None;
+        self.reply = None;
+    }
+
+    /// Applies a message from the kernel to the waiting request.
+    ///
+    /// Returns whether the message belonged to it.
+    pub fn handle(&mut self, channel: Channel, message: &Message) -> bool {
+        let Some(pending) = &self.pending else {
+            return false;
+        };
+        if message.parent_id() != Some(pending.msg_id.as_str()) {
+            return false;
+        }
+        if channel == Channel::Shell && message.msg_type() == "complete_reply" {
+            let pending = self.pending.take().unwrap();
+            self.reply = Some(CompletionReply {
+                completions: parse_complete_reply(&message.content, &pending.code),
+                code: pending.code,
+                cursor: pending.cursor,
+            });
+        }
+        true
+    }
+
+    /// The reply to the last request, once it arrived.
+    pub fn take_reply(&mut self) -> Option<CompletionReply> {
+        self.reply.take()
+    }
+}
+
+/// The byte offset of the code point at index `position` of `code`.
+fn byte_offset(code: &str, position: usize) -> usize {
+    code.char_indices()
+        .nth(position)
+        .map_or(code.len(), |(offset, _)| offset)
+}
+
+fn floor_char_boundary(code: &str, index: usize) -> usize {
+    let mut index = index.min(code.len());
+    while !code.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
@@ -0,0 +1,97 @@
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// The contents of a Jupyter connection file: where a kernel listens and the
+/// key its messages are signed with.
+///
+/// Keys this struct doesn't model (e.g. `kernel_name`) are kept in `other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    #[serde(default = "default_transport")]
+    pub transport: String,
+    #[serde(default = "default_ip")]
+    pub ip: String,
+    pub shell_port: u16,
+    pub iopub_port: u16,
+    pub stdin_port: u16,
+    pub control_port: u16,
+    pub hb_port: u16,
+    /// HMAC key; an empty key disables signing.
+    #[serde(default)]
+    pub key: String,
+    #[serde(default = "default_signature_scheme")]
+    pub signature_scheme: String,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+fn default_transport() -> String {
+    "tcp".to_string()
+}
+
+fn default_ip() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_signature_scheme() -> String {
+    "hmac-sha256".to_string()
+}
+
+impl ConnectionInfo {
+    /// Reads a connection file such as `kernel-1234.json` from Jupyter's
+    /// runtime directory.
+    ///
+    /// Returns an error if the file can't be read or isn't a connection file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let info: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse connection file: {}", path.display()))?;
+        info.check()?;
+        Ok(info)
+    }
+
+    /// Writes the connection file a kernel is started with.
+    ///
+    /// On unix the file is readable by the user only (mode 0600), as it
+    /// holds the key that lets anyone run code in the kernel.
+    pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let content =
+            serde_json::to_string_pretty(self).context("Failed to serialize connection info")?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let write = |options: &fs::OpenOptions| -> std::io::Result<()> {
+            let mut file = options.open(path)?;
+            // `mode` only applies to new files
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+            file.write_all(content.as_bytes())
+        };
+        write(&options)
+            .with_context(|| format!("Failed to write connection file: {}", path.display()))
+    }
+
+    /// Rejects transports and signature schemes nbterm can't speak.
+    pub fn check(&self) -> Result<()> {
+        if self.transport != "tcp" {
+            bail!("Unsupported kernel transport: {}", self.transport);
+        }
+        if !self.key.is_empty() && self.signature_scheme != "hmac-sha256" {
+            bail!("Unsupported signature scheme: {}", self.signature_scheme);
+        }
+        Ok(())
+    }
+
+    /// The ZeroMQ endpoint of one of the kernel's ports, e.g.
+    /// `tcp://127.0.0.1:53794`.
+    pub fn endpoint(&self, port: u16) -> String {
+        format!("{}://{}:{}", self.transport, self.ip, port)
+    }
+}
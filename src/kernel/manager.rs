@@ -0,0 +1,322 @@
+use anyhow::{Context, Result, bail};
+use serde_json::json;
+use std::fs;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::client::{Channel, KernelClient};
+use super::connection::ConnectionInfo;
+use super::spec::{KernelSpec, runtime_dir};
+
+/// How long a kernel gets to answer its first `kernel_info_request`.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a kernel gets to exit after a `shutdown_request` before it is
+/// killed.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A kernel process started by nbterm, with the connection file it was
+/// started with and, once connected, a client talking to it.
+///
+/// The process is killed and the connection file removed when the manager
+/// is dropped; [`KernelManager::shutdown`] asks the kernel to exit first.
+///
+/// An interactive frontend uses [`KernelManager::start_in_background`] and
+/// [`KernelManager::poll_connect`], so that it never waits for the kernel;
+/// restarting and [`KernelManager::shutdown_in_background`] don't either.
+pub struct KernelManager {
+    pub spec: KernelSpec,
+    pub info: ConnectionInfo,
+    connection_file: PathBuf,
+    working_dir: PathBuf,
+    child: Child,
+    client: Option<KernelClient>,
+    /// The client being connected on another thread, until it answered.
+    connecting: Option<mpsc::Receiver<Result<KernelClient>>>,
+}
+
+impl KernelManager {
+    /// Starts the kernel of `spec` in `working_dir` and waits until it
+    /// answers.
+    pub fn start(spec: KernelSpec, working_dir: &Path) -> Result<Self> {
+        let mut manager = Self::launch(spec, working_dir)?;
+        manager.connect()?;
+        Ok(manager)
+    }
+
+    /// Starts the kernel of `spec` in `working_dir` and connects to it on
+    /// another thread, see [`KernelManager::poll_connect`].
+    pub fn start_in_background(spec: KernelSpec, working_dir: &Path) -> Result<Self> {
+        let mut manager = Self::launch(spec, working_dir)?;
+        manager.connect_in_background();
+        Ok(manager)
+    }
+
+    /// Starts the kernel process of `spec` with a newly generated connection
+    /// file, without connecting to it.
+    ///
+    /// Returns an error if the connection file can't be written or the
+    /// process can't be started.
+    pub fn launch(spec: KernelSpec, working_dir: &Path) -> Result<Self> {
+        let dir = runtime_dir()
+            .context("No Jupyter runtime directory, set JUPYTER_RUNTIME_DIR or HOME")?;
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        // like jupyter_client, keep the runtime directory to the user
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder
+            .create(&dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let connection_file = dir.join(format!("kernel-{id}.json"));
+        let [shell_port, iopub_port, stdin_port, control_port, hb_port] = free_ports()?;
+        let info = ConnectionInfo {
+            transport: "tcp".to_string(),
+            ip: "127.0.0.1".to_string(),
+            shell_port,
+            iopub_port,
+            stdin_port,
+            control_port,
+            hb_port,
+            key: uuid::Uuid::new_v4().to_string(),
+            signature_scheme: "hmac-sha256".to_string(),
+            other: [("kernel_name".to_string(), json!(spec.name))]
+                .into_iter()
+                .collect(),
+        };
+        info.save_to_file(&connection_file)?;
+        let child = spawn(&spec, &connection_file, working_dir)?;
+        Ok(Self {
+            spec,
+            info,
+            connection_file,
+            working_dir: working_dir.to_path_buf(),
+            child,
+            client: None,
+            connecting: None,
+        })
+    }
+
+    /// Connects a client to the kernel and waits for its `kernel_info_reply`.
+    pub fn connect(&mut self) -> Result<&KernelClient> {
+        self.connecting = None;
+        let client = connect(self.info.clone(), &self.spec.display_name)?;
+        Ok(self.client.insert(client))
+    }
+
+    /// Connects a client to the kernel on another thread. The client is
+    /// picked up by [`KernelManager::poll_connect`] once the kernel
+    /// answered.
+    pub fn connect_in_background(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        let (info, name) = (self.info.clone(), self.spec.display_name.clone());
+        thread::spawn(move || {
+            let _ = tx.send(connect(info, &name));
+        });
+        self.client = None;
+        self.connecting = Some(rx);
+    }
+
+    /// Picks up the client connected in the background, if it is ready.
+    ///
+    /// Returns an error if the kernel didn't answer in time.
+    pub fn poll_connect(&mut self) -> Result<()> {
+        let Some(connecting) = &self.connecting else {
+            return Ok(());
+        };
+        let client = match connecting.try_recv() {
+            Ok(client) => client,
+            Err(mpsc::TryRecvError::Empty) => return Ok(()),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(anyhow::anyhow!("Connecting to the kernel failed"))
+            }
+        };
+        self.connecting = None;
+        self.client = Some(client?);
+        Ok(())
+    }
+
+    /// Whether a client is being connected in the background.
+    pub fn is_connecting(&self) -> bool {
+        self.connecting.is_some()
+    }
+
+    /// The client talking to the kernel, once connected.
+    pub fn client(&self) -> Option<&KernelClient> {
+        self.client.as_ref()
+    }
+
+    pub fn connection_file(&self) -> &Path {
+        &self.connection_file
+    }
+
+    /// Whether the kernel process is still running.
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    /// Interrupts the code the kernel is running, with SIGINT or, for kernels
+    /// whose spec asks for it, an `interrupt_request`.
+    pub fn interrupt(&mut self) -> Result<()> {
+        if self.spec.interrupts_by_message() {
+            let client = self
+                .client
+                .as_ref()
+                .context("The kernel is not connected")?;
+            client.request(Channel::Control, "interrupt_request", json!({}))?;
+            return Ok(());
+        }
+        if !self.is_running() {
+            bail!("The kernel is not running");
+        }
+        interrupt_process(&self.child)
+    }
+
+    /// Starts the kernel again with the same connection file, reconnecting
+    /// in the background if it was connected.
+    ///
+    /// The new process gets fresh ports, so it can start right away while
+    /// the old one shuts down on another thread.
+    pub fn restart(&mut self) -> Result<()> {
+        let reconnect = self.client.is_some() || self.connecting.is_some();
+        let mut info = self.info.clone();
+        [
+            info.shell_port,
+            info.iopub_port,
+            info.stdin_port,
+            info.control_port,
+            info.hb_port,
+        ] = free_ports()?;
+        info.save_to_file(&self.connection_file)?;
+        let child = spawn(&self.spec, &self.connection_file, &self.working_dir)?;
+        let mut old = std::mem::replace(&mut self.child, child);
+        let client = self.client.take();
+        thread::spawn(move || stop(&mut old, client, true));
+        self.info = info;
+        self.connecting = None;
+        if reconnect {
+            self.connect_in_background();
+        }
+        Ok(())
+    }
+
+    /// Asks the kernel to exit, kills it if it doesn't in time and removes
+    /// its connection file.
+    pub fn shutdown(mut self) -> Result<()> {
+        stop(&mut self.child, self.client.take(), false);
+        Ok(())
+    }
+
+    /// Shuts the kernel down like [`KernelManager::shutdown`], on another
+    /// thread. Joining the handle waits for the kernel to be gone.
+    pub fn shutdown_in_background(self) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let _ = self.shutdown();
+        })
+    }
+}
+
+impl Drop for KernelManager {
+    fn drop(&mut self) {
+        if self.is_running() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+        let _ = fs::remove_file(&self.connection_file);
+    }
+}
+
+/// Connects a client to the kernel described by `info` and waits for its
+/// `kernel_info_reply`.
+fn connect(info: ConnectionInfo, name: &str) -> Result<KernelClient> {
+    let client = KernelClient::connect(info)?;
+    client
+        .kernel_info(STARTUP_TIMEOUT)
+        .with_context(|| format!("Kernel {name} did not start"))?;
+    Ok(client)
+}
+
+/// Sends a `shutdown_request` through `client`, if connected, and waits for
+/// the process to exit, killing it after [`SHUTDOWN_TIMEOUT`].
+fn stop(child: &mut Child, client: Option<KernelClient>, restart: bool) {
+    let is_running = |child: &mut Child| matches!(child.try_wait(), Ok(None));
+    if let Some(client) = client {
+        let _ = client.request(
+            Channel::Control,
+            "shutdown_request",
+            json!({ "restart": restart }),
+        );
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while is_running(child) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+    if is_running(child) {
+        let _ = child.kill();
+    }
+    let _ = child.wait();
+}
+
+/// Starts the kernel command of `spec`, filling in its placeholders.
+///
+/// The kernel gets its own process group, so a Ctrl-C meant for nbterm
+/// doesn't interrupt it, and no terminal I/O, which belongs to the TUI.
+fn spawn(spec: &KernelSpec, connection_file: &Path, working_dir: &Path) -> Result<Child> {
+    let argv: Vec<String> = spec
+        .argv
+        .iter()
+        .map(|arg| {
+            arg.replace("{connection_file}", &connection_file.to_string_lossy())
+                .replace("{resource_dir}", &spec.resource_dir.to_string_lossy())
+        })
+        .collect();
+    let Some((program, args)) = argv.split_first() else {
+        bail!("Kernelspec {} has an empty argv", spec.name);
+    };
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .envs(&spec.env)
+        .current_dir(working_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command
+        .spawn()
+        .with_context(|| format!("Failed to start kernel {}: {}", spec.name, program))
+}
+
+/// Distinct ports on localhost nothing listens on right now.
+fn free_ports<const N: usize>() -> Result<[u16; N]> {
+    let listeners = (0..N)
+        .map(|_| TcpListener::bind("127.0.0.1:0"))
+        .collect::<std::io::Result<Vec<_>>>()
+        .context("Failed to find a free port")?;
+    let mut ports = [0; N];
+    for (port, listener) in ports.iter_mut().zip(&listeners) {
+        *port = listener.local_addr()?.port();
+    }
+    Ok(ports)
+}
+
+#[cfg(unix)]
+fn interrupt_process(child: &Child) -> Result<()> {
+    // SAFETY: `kill` has no memory-safety preconditions; the pid belongs to
+    // a child that hasn't been waited for, so it can't have been reused.
+    if unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to interrupt the kernel");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn interrupt_process(_child: &Child) -> Result<()> {
+    bail!("Interrupting kernels by signal is not supported on this platform")
+}
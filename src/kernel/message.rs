@@ -0,0 +1,217 @@
+use anyhow::{Context, Result, bail};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::Sha256;
+
+use crate::notebook_util::Output;
+
+/// Version of the messaging protocol nbterm speaks.
+pub const PROTOCOL_VERSION: &str = "5.3";
+
+/// Separates the routing identities from the message proper.
+pub const DELIMITER: &[u8] = b"<IDS|MSG>";
+
+/// The header every message carries.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub msg_id: String,
+    pub session: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub date: String,
+    pub msg_type: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+impl Header {
+    pub fn new(msg_type: &str, session: &str) -> Self {
+        Self {
+            msg_id: uuid::Uuid::new_v4().simple().to_string(),
+            session: session.to_string(),
+            username: "nbterm".to_string(),
+            date: chrono::Utc::now().to_rfc3339(),
+            msg_type: msg_type.to_string(),
+            version: PROTOCOL_VERSION.to_string(),
+        }
+    }
+}
+
+/// A message of the Jupyter messaging protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// ZeroMQ routing prefix; only set on messages received by a ROUTER.
+    pub identities: Vec<Vec<u8>>,
+    pub header: Header,
+    /// Header of the request this message answers, if any.
+    pub parent_header: Option<Header>,
+    pub metadata: Value,
+    pub content: Value,
+    pub buffers: Vec<Vec<u8>>,
+}
+
+impl Message {
+    pub fn new(msg_type: &str, session: &str, content: Value) -> Self {
+        Self {
+            identities: vec![],
+            header: Header::new(msg_type, session),
+            parent_header: None,
+            metadata: json!({}),
+            content,
+            buffers: vec![],
+        }
+    }
+
+    /// Creates a message answering `self`, routed back to the same peer.
+    pub fn reply(&self, msg_type: &str, content: Value) -> Self {
+        Self {
+            identities: self.identities.clone(),
+            header: Header::new(msg_type, &self.header.session),
+            parent_header: Some(self.header.clone()),
+            metadata: json!({}),
+            content,
+            buffers: vec![],
+        }
+    }
+
+    pub fn msg_type(&self) -> &str {
+        &self.header.msg_type
+    }
+
+    /// The `msg_id` of the request this message answers.
+    pub fn parent_id(&self) -> Option<&str> {
+        self.parent_header
+            .as_ref()
+            .map(|header| header.msg_id.as_str())
+    }
+
+    /// Encodes the message as ZeroMQ frames: identities, delimiter,
+    /// signature, header, parent header, metadata, content and buffers.
+    pub fn to_frames(&self, signer: &Signer) -> Result<Vec<Vec<u8>>> {
+        let parts = [
+            serde_json::to_vec(&self.header)?,
+            match &self.parent_header {
+                Some(header) => serde_json::to_vec(header)?,
+                None => b"{}".to_vec(),
+            },
+            serde_json::to_vec(&self.metadata)?,
+            serde_json::to_vec(&self.content)?,
+        ];
+        let mut frames = self.identities.clone();
+        frames.push(DELIMITER.to_vec());
+        frames.push(signer.sign(&parts).into_bytes());
+        frames.extend(parts);
+        frames.extend(self.buffers.iter().cloned());
+        Ok(frames)
+    }
+
+    /// Decodes and verifies a message received as ZeroMQ frames.
+    ///
+    /// Returns an error if the frames are malformed or the signature doesn't
+    /// match.
+    pub fn from_frames(mut frames: Vec<Vec<u8>>, signer: &Signer) -> Result<Self> {
+        let delimiter = frames
+            .iter()
+            .position(|frame| frame == DELIMITER)
+            .context("Message has no <IDS|MSG> delimiter")?;
+        if frames.len() < delimiter + 6 {
+            bail!(
+                "Message has {} frames, expected at least 6",
+                frames.len() - delimiter
+            );
+        }
+        let buffers = frames.split_off(delimiter + 6);
+        let parts = frames.split_off(delimiter + 2);
+        let signature = frames.pop().unwrap_or_default();
+        frames.pop();
+        signer.verify(&parts, &signature)?;
+
+        let parent: Value =
+            serde_json::from_slice(&parts[1]).context("Failed to parse parent header")?;
+        Ok(Self {
+            identities: frames,
+            header: serde_json::from_slice(&parts[0]).context("Failed to parse header")?,
+            parent_header: match parent {
+                Value::Object(map) if map.is_empty() => None,
+                parent => {
+                    Some(serde_json::from_value(parent).context("Failed to parse parent header")?)
+                }
+            },
+            metadata: serde_json::from_slice(&parts[2]).context("Failed to parse metadata")?,
+            content: serde_json::from_slice(&parts[3]).context("Failed to parse content")?,
+            buffers,
+        })
+    }
+
+    /// Turns an iopub `stream`, `execute_result`, `display_data` or `error`
+    /// message into the notebook output it stands for.
+    ///
+    /// The `transient` key (holding the `display_id`) is dropped, as nbformat
+    /// doesn't store it, see [`Message::display_id`].
+    pub fn to_output(&self) -> Option<Output> {
+        let mut content = match (self.msg_type(), &self.content) {
+            ("stream" | "execute_result" | "display_data" | "error", Value::Object(content)) => {
+                content.clone()
+            }
+            _ => return None,
+        };
+        content.remove("transient");
+        content.insert("output_type".to_string(), json!(self.msg_type()));
+        if self.msg_type() != "stream" && self.msg_type() != "error" {
+            content.entry("metadata").or_insert_with(|| json!({}));
+        }
+        serde_json::from_value(Value::Object(content)).ok()
+    }
+
+    /// The id of the display a `display_data` or `update_display_data`
+    /// message shows, if the kernel gave it one.
+    pub fn display_id(&self) -> Option<&str> {
+        self.content["transient"]["display_id"].as_str()
+    }
+}
+
+/// Signs and verifies messages with the connection file's HMAC key.
+#[derive(Clone)]
+pub struct Signer {
+    mac: Option<Hmac<Sha256>>,
+}
+
+impl Signer {
+    /// A signer for `key`; an empty key means messages are not signed.
+    pub fn new(key: &str) -> Self {
+        Self {
+            mac: (!key.is_empty()).then(|| {
+                Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length")
+            }),
+        }
+    }
+
+    /// Hex digest of the header, parent header, metadata and content frames.
+    pub fn sign(&self, parts: &[Vec<u8>]) -> String {
+        match &self.mac {
+            Some(mac) => {
+                let mut mac = mac.clone();
+                for part in parts {
+                    mac.update(part);
+                }
+                hex::encode(mac.finalize().into_bytes())
+            }
+            None => String::new(),
+        }
+    }
+
+    fn verify(&self, parts: &[Vec<u8>], signature: &[u8]) -> Result<()> {
+        let Some(mac) = &self.mac else {
+            return Ok(());
+        };
+        let mut mac = mac.clone();
+        for part in parts {
+            mac.update(part);
+        }
+        let signature = hex::decode(signature).context("Message signature is not hex")?;
+        mac.verify_slice(&signature)
+            .map_err(|_| anyhow::anyhow!("Message signature does not match"))
+    }
+}
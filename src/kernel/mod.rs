@@ -0,0 +1,17 @@
+pub mod client;
+pub mod completion;
+pub mod connection;
+pub mod manager;
+pub mod message;
+pub mod queue;
+pub mod spec;
+pub mod variables;
+
+pub use client::{Channel, Displays, KernelClient};
+pub use completion::{Completer, Completion};
+pub use connection::ConnectionInfo;
+pub use manager::KernelManager;
+pub use message::{Header, Message, Signer};
+pub use queue::{CellState, ExecutionQueue};
+pub use spec::KernelSpec;
+pub use variables::{Variable, VariableInspector};
@@ -0,0 +1,308 @@
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::time::Instant;
+
+use super::client::{Channel, Displays, KernelClient};
+use super::message::Message;
+use crate::notebook_util::{Cell, CodeCell, Notebook};
+
+/// Where a cell is in the execution queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellState {
+    Queued,
+    Running,
+    Done,
+    Errored,
+}
+
+impl fmt::Display for CellState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CellState::Queued => "queued",
+            CellState::Running => "running",
+            CellState::Done => "done",
+            CellState::Errored => "errored",
+        })
+    }
+}
+
+/// The cell being run and what the kernel has reported about it so far.
+struct Running {
+    cell_id: String,
+    msg_id: String,
+    /// `status` of the `execute_reply`, once received.
+    status: Option<String>,
+    /// Whether iopub reported the kernel idle again.
+    idle: bool,
+    /// A `clear_output(wait=True)` waits for the next output.
+    clear_pending: bool,
+}
+
+/// A kernel's request for a line of input, e.g. from `input()`.
+pub struct InputRequest {
+    pub prompt: String,
+    /// Whether the input is a password (`getpass()`) and must not be shown.
+    pub password: bool,
+    /// When the kernel asked.
+    pub since: Instant,
+    /// The `input_request`, which the `input_reply` answers.
+    message: Message,
+}
+
+/// Runs code cells one after the other on a kernel.
+///
+/// Cells are tracked by id, so edits made while they wait or run don't
+/// send outputs to the wrong cell. The queue never blocks: the caller
+/// feeds it the kernel's messages with [`ExecutionQueue::handle`] and lets
+/// it send the next cell with [`ExecutionQueue::start_next`].
+pub struct ExecutionQueue {
+    pending: VecDeque<String>,
+    running: Option<Running>,
+    states: HashMap<String, CellState>,
+    /// Waiting for the answer to an `input_request` of the running cell.
+    input: Option<InputRequest>,
+    /// The displays the cells' outputs show, for `update_display_data`.
+    displays: Displays,
+    /// Drop the cells still queued when one fails. On by default.
+    pub stop_on_error: bool,
+    /// Let cells ask for input, see [`ExecutionQueue::answer_input`]. Off by
+    /// default, so `input()` fails rather than waiting for an answer nobody
+    /// gives.
+    pub allow_stdin: bool,
+}
+
+impl Default for ExecutionQueue {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            running: None,
+            states: HashMap::new(),
+            input: None,
+            displays: Displays::default(),
+            stop_on_error: true,
+            allow_stdin: false,
+        }
+    }
+}
+
+impl ExecutionQueue {
+    /// A queue for a frontend that answers input requests.
+    pub fn interactive() -> Self {
+        Self {
+            allow_stdin: true,
+            ..Self::default()
+        }
+    }
+
+    /// Queues the code cells in `range` of `notebook`, skipping those
+    /// already waiting or running.
+    ///
+    /// Cells without an id get one, see [`Notebook::ensure_cell_ids`].
+    pub fn enqueue(&mut self, notebook: &mut Notebook, range: Range<usize>) {
+        notebook.ensure_cell_ids();
+        for cell in notebook.cells.get(range).unwrap_or_default() {
+            let (Cell::Code(_), Some(id)) = (cell, cell.id()) else {
+                continue;
+            };
+            if matches!(
+                self.states.get(id),
+                Some(CellState::Queued | CellState::Running)
+            ) {
+                continue;
+            }
+            self.pending.push_back(id.to_string());
+            self.states.insert(id.to_string(), CellState::Queued);
+        }
+    }
+
+    /// The state of the cell with id `cell_id`, if it was ever queued.
+    pub fn state(&self, cell_id: &str) -> Option<CellState> {
+        self.states.get(cell_id).copied()
+    }
+
+    /// The id of the cell being run.
+    pub fn running(&self) -> Option<&str> {
+        self.running
+            .as_ref()
+            .map(|running| running.cell_id.as_str())
+    }
+
+    /// How many cells wait for their turn.
+    pub fn queued(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The running cell's request for input, if it waits for an answer.
+    pub fn input_request(&self) -> Option<&InputRequest> {
+        self.input.as_ref()
+    }
+
+    /// Answers the running cell's request for input with `value`.
+    ///
+    /// The prompt and answer are echoed to the cell's output like a
+    /// terminal would, with passwords masked.
+    pub fn answer_input(
+        &mut self,
+        notebook: &mut Notebook,
+        client: &KernelClient,
+        value: &str,
+    ) -> Result<()> {
+        let Some(request) = self.input.take() else {
+            return Ok(());
+        };
+        client.send(
+            Channel::Stdin,
+            request
+                .message
+                .reply("input_reply", serde_json::json!({ "value": value })),
+        )?;
+        if let Some(cell) = self.running().and_then(|id| code_cell(notebook, id)) {
+            let echo = if request.password {
+                "········"
+            } else {
+                value
+            };
+            cell.push_stream("stdout", &format!("{}{echo}\n", request.prompt));
+        }
+        Ok(())
+    }
+
+    /// Drops the running cell's request for input without answering it. The
+    /// kernel keeps waiting until it is interrupted.
+    pub fn cancel_input(&mut self) -> Option<InputRequest> {
+        self.input.take()
+    }
+
+    /// Whether a cell is running or waiting to.
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some() || !self.pending.is_empty()
+    }
+
+    /// Drops the cells waiting for their turn.
+    pub fn clear(&mut self) {
+        for id in self.pending.drain(..) {
+            self.states.remove(&id);
+        }
+    }
+
+    /// Forgets the running cell as well, e.g. after the kernel restarted.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.input = None;
+        if let Some(running) = self.running.take() {
+            self.states.insert(running.cell_id, CellState::Errored);
+        }
+    }
+
+    /// Sends the next queued cell to the kernel if none is running.
+    ///
+    /// Its outputs and execution count are cleared. Cells that were deleted
+    /// while queued are skipped.
+    pub fn start_next(&mut self, notebook: &mut Notebook, client: &KernelClient) -> Result<()> {
+        while self.running.is_none()
+            && let Some(cell_id) = self.pending.pop_front()
+        {
+            let Some(cell) = code_cell(notebook, &cell_id) else {
+                self.states.remove(&cell_id);
+                continue;
+            };
+            let msg_id = match client.execute(cell.source.as_str(), self.allow_stdin) {
+                Ok(msg_id) => msg_id,
+                Err(err) => {
+                    // the cell never ran, so it is no longer queued either
+                    self.states.remove(&cell_id);
+                    return Err(err);
+                }
+            };
+            cell.outputs.clear();
+            cell.execution_count = None;
+            self.displays.forget_cell(&cell_id);
+            self.states.insert(cell_id.clone(), CellState::Running);
+            self.running = Some(Running {
+                cell_id,
+                msg_id,
+                status: None,
+                idle: false,
+                clear_pending: false,
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies a message from the kernel to the running cell.
+    ///
+    /// `update_display_data` is applied to the whole notebook instead, as a
+    /// cell may update a display shown by another one, even after both ran.
+    ///
+    /// Returns whether the message was applied; the caller deals with any
+    /// other message.
+    pub fn handle(&mut self, notebook: &mut Notebook, channel: Channel, message: &Message) -> bool {
+        if channel == Channel::IoPub && message.msg_type() == "update_display_data" {
+            return self.displays.update(notebook, message);
+        }
+        let Some(running) = &mut self.running else {
+            return false;
+        };
+        if message.parent_id() != Some(running.msg_id.as_str()) {
+            return false;
+        }
+        let cell = code_cell(notebook, &running.cell_id);
+        match (channel, message.msg_type()) {
+            (Channel::Shell, "execute_reply") => {
+                running.status = message.content["status"].as_str().map(str::to_string);
+                if let (Some(cell), Some(count)) =
+                    (cell, message.content["execution_count"].as_u64())
+                {
+                    cell.execution_count = Some(count as u32);
+                }
+            }
+            (Channel::Stdin, "input_request") => {
+                self.input = Some(InputRequest {
+                    prompt: message.content["prompt"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    password: message.content["password"] == true,
+                    since: Instant::now(),
+                    message: message.clone(),
+                });
+            }
+            (Channel::IoPub, "status") => {
+                running.idle = message.content["execution_state"] == "idle";
+            }
+            (Channel::IoPub, _) => {
+                if let Some(cell) = cell {
+                    cell.apply_iopub(message, &mut running.clear_pending, &mut self.displays);
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(status) = &running.status
+            && running.idle
+        {
+            let failed = status != "ok";
+            let state = if failed {
+                CellState::Errored
+            } else {
+                CellState::Done
+            };
+            self.states.insert(running.cell_id.clone(), state);
+            self.running = None;
+            self.input = None;
+            if failed && self.stop_on_error {
+                self.clear();
+            }
+        }
+        true
+    }
+}
+
+fn code_cell<'a>(notebook: &'a mut Notebook, cell_id: &str) -> Option<&'a mut CodeCell> {
+    notebook.cells.iter_mut().find_map(|cell| match cell {
+        Cell::Code(cell) if cell.id.as_deref() == Some(cell_id) => Some(cell),
+        _ => None,
+    })
+}
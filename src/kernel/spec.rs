@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::notebook_util::{Kernelspec, NotebookMetadata};
+
+/// An installed kernel, as described by a `kernels/<name>/kernel.json` in
+/// one of the Jupyter data directories.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct KernelSpec {
+    /// The name of the kernel's directory, e.g. `python3`.
+    #[serde(skip)]
+    pub name: String,
+    /// The directory holding `kernel.json`, logos and the like.
+    #[serde(skip)]
+    pub resource_dir: PathBuf,
+    /// The command starting the kernel, with `{connection_file}` standing
+    /// for the connection file path.
+    pub argv: Vec<String>,
+    pub display_name: String,
+    pub language: String,
+    /// `signal` (the default) or `message` for kernels that want an
+    /// `interrupt_request` instead of SIGINT.
+    #[serde(default)]
+    pub interrupt_mode: Option<String>,
+    /// Environment variables set for the kernel process.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+impl KernelSpec {
+    /// Reads the kernelspec in `dir`, named after the directory.
+    ///
+    /// Returns an error if `dir/kernel.json` is missing or malformed.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let path = dir.join("kernel.json");
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let mut spec: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse kernelspec: {}", path.display()))?;
+        spec.name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        spec.resource_dir = dir.to_path_buf();
+        Ok(spec)
+    }
+
+    /// Whether the kernel wants an `interrupt_request` rather than a signal.
+    pub fn interrupts_by_message(&self) -> bool {
+        self.interrupt_mode.as_deref() == Some("message")
+    }
+
+    /// The `kernelspec` notebook metadata recording this kernel.
+    pub fn to_metadata(&self) -> Kernelspec {
+        Kernelspec {
+            name: self.name.clone(),
+            display_name: self.display_name.clone(),
+            language: Some(self.language.clone()),
+            other: serde_json::json!({}),
+        }
+    }
+}
+
+/// The directories Jupyter looks for data files in, in order of precedence:
+/// `$JUPYTER_PATH`, the user data directory, the active environment
+/// (`$CONDA_PREFIX` or `$VIRTUAL_ENV`) and the system-wide directories.
+pub fn jupyter_data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("JUPYTER_PATH")
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    dirs.extend(user_data_dir());
+    for prefix in ["CONDA_PREFIX", "VIRTUAL_ENV"] {
+        if let Some(prefix) = env::var_os(prefix) {
+            dirs.push(PathBuf::from(prefix).join("share").join("jupyter"));
+        }
+    }
+    if cfg!(windows) {
+        dirs.extend(env::var_os("PROGRAMDATA").map(|dir| PathBuf::from(dir).join("jupyter")));
+    } else {
+        dirs.push(PathBuf::from("/usr/local/share/jupyter"));
+        dirs.push(PathBuf::from("/usr/share/jupyter"));
+    }
+    dirs.dedup();
+    dirs
+}
+
+/// The user's Jupyter data directory: `$JUPYTER_DATA_DIR`, or the
+/// platform's default such as `~/.local/share/jupyter`.
+pub fn user_data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("JUPYTER_DATA_DIR") {
+        return Some(dir.into());
+    }
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("jupyter"));
+    }
+    let home = PathBuf::from(env::var_os("HOME")?);
+    Some(if cfg!(target_os = "macos") {
+        home.join("Library").join("Jupyter")
+    } else if let Some(data) = env::var_os("XDG_DATA_HOME") {
+        PathBuf::from(data).join("jupyter")
+    } else {
+        home.join(".local").join("share").join("jupyter")
+    })
+}
+
+/// The directory connection files are written to: `$JUPYTER_RUNTIME_DIR`,
+/// or `runtime` in the user data directory.
+///
+/// There is no fallback to a shared directory such as `/tmp`: connection
+/// files hold the key that lets anyone run code in the kernel.
+pub fn runtime_dir() -> Option<PathBuf> {
+    env::var_os("JUPYTER_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .or_else(|| user_data_dir().map(|dir| dir.join("runtime")))
+}
+
+/// All kernelspecs installed in the standard Jupyter data directories.
+pub fn find_kernelspecs() -> Vec<KernelSpec> {
+    kernelspecs_in(&jupyter_data_dirs())
+}
+
+/// The kernelspecs in the `kernels` directories of `data_dirs`, sorted by
+/// name. A kernel found in more than one directory is taken from the first.
+///
+/// Directories without a readable `kernel.json` are skipped.
+pub fn kernelspecs_in(data_dirs: &[PathBuf]) -> Vec<KernelSpec> {
+    let mut specs = BTreeMap::new();
+    for dir in data_dirs {
+        let Ok(entries) = fs::read_dir(dir.join("kernels")) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if let Ok(spec) = KernelSpec::from_dir(&entry.path()) {
+                specs.entry(spec.name.clone()).or_insert(spec);
+            }
+        }
+    }
+    specs.into_values().collect()
+}
+
+/// Picks the kernel a notebook was written for: the one named in its
+/// `kernelspec` metadata, or else the first one for its language.
+pub fn match_kernelspec<'a>(
+    specs: &'a [KernelSpec],
+    metadata: &NotebookMetadata,
+) -> Option<&'a KernelSpec> {
+    if let Some(kernelspec) = &metadata.kernelspec
+        && let Some(spec) = specs.iter().find(|spec| spec.name == kernelspec.name)
+    {
+        return Some(spec);
+    }
+    let language = metadata
+        .kernelspec
+        .as_ref()
+        .and_then(|kernelspec| kernelspec.language.as_deref())
+        .or(metadata
+            .language_info
+            .as_ref()
+            .map(|info| info.name.as_str()))?;
+    specs
+        .iter()
+        .find(|spec| spec.language.eq_ignore_ascii_case(language))
+}
@@ -0,0 +1,245 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::client::{Channel, KernelClient};
+use super::message::Message;
+
+/// A variable defined in the kernel, as the introspection snippets describe
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variable {
+    pub name: String,
+    /// The name of the value's type or class.
+    #[serde(rename = "type")]
+    pub type_name: String,
+    /// The dimensions of arrays and data frames, or the length of other
+    /// collections.
+    #[serde(default)]
+    pub shape: Option<Vec<u64>>,
+    /// The start of the value's representation.
+    pub repr: String,
+    /// The first items of a container, keyed by index or key.
+    #[serde(default)]
+    pub children: Vec<Variable>,
+}
+
+impl Variable {
+    /// The shape as shown in the variables pane, e.g. `3×4`.
+    pub fn shape_text(&self) -> Option<String> {
+        let shape = self.shape.as_ref()?;
+        Some(
+            shape
+                .iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join("×"),
+        )
+    }
+
+    /// The number of items, for sorting by size.
+    pub fn size(&self) -> u64 {
+        self.shape
+            .as_ref()
+            .map_or(0, |shape| shape.iter().product())
+    }
+}
+
+/// Defines `_nbterm_variables()` in Python kernels. It returns the user's
+/// variables, shown as JSON; the leading underscore keeps it out of the
+/// listing.
+const PYTHON: &str = r#"def _nbterm_variables():
+    import itertools, types
+    class Variables:
+        def __init__(self, variables):
+            self.variables = variables
+        def _repr_json_(self):
+            return self.variables
+        def __repr__(self):
+            return f"<{len(self.variables)} variables>"
+    def describe(name, value, depth):
+        try:
+            text = repr(value)
+        except Exception as err:
+            text = f"<repr failed: {err}>"
+        shape = getattr(value, "shape", None)
+        if not (isinstance(shape, tuple) and all(isinstance(n, int) for n in shape)):
+            try:
+                shape = (len(value),)
+            except Exception:
+                shape = None
+        items = []
+        if depth == 0 and isinstance(value, dict):
+            items = [(repr(key), item) for key, item in itertools.islice(value.items(), 100)]
+        elif depth == 0 and isinstance(value, (list, tuple, set, frozenset)):
+            items = [(str(index), item) for index, item in enumerate(itertools.islice(value, 100))]
+        return {
+            "name": name,
+            "type": type(value).__name__,
+            "shape": list(shape) if shape is not None else None,
+            "repr": text[:200],
+            "children": [describe(key, item, depth + 1) for key, item in items],
+        }
+    skipped = (types.ModuleType, types.FunctionType, types.BuiltinFunctionType, type)
+    return Variables([
+        describe(name, value, 0)
+        for name, value in list(globals().items())
+        if not name.startswith("_")
+        and name not in ("In", "Out", "exit", "quit", "get_ipython")
+        and not isinstance(value, skipped)
+    ])
+"#;
+
+/// Defines `.nbterm_variables()` in R kernels, which `ls()` hides. It
+/// returns the user's variables as JSON, with jsonlite (which IRkernel
+/// depends on).
+const R: &str = r#".nbterm_variables <- function() {
+  describe <- function(name, value, depth) {
+    text <- tryCatch(
+      paste(utils::capture.output(print(value)), collapse = "\n"),
+      error = function(e) paste("<print failed:", conditionMessage(e), ">")
+    )
+    shape <- if (!is.null(dim(value))) as.list(dim(value))
+      else if (is.atomic(value) || is.list(value)) list(length(value))
+    children <- list()
+    if (depth == 0 && is.list(value) && !is.data.frame(value)) {
+      keys <- names(value)
+      if (is.null(keys)) keys <- paste0("[[", seq_along(value), "]]")
+      children <- lapply(seq_len(min(length(value), 100)), function(i) {
+        describe(keys[[i]], value[[i]], depth + 1)
+      })
+    }
+    list(name = name, type = class(value)[[1]], shape = shape,
+         repr = substr(text, 1, 200), children = children)
+  }
+  values <- lapply(ls(envir = globalenv()), function(name) {
+    value <- get(name, envir = globalenv())
+    if (!is.function(value)) describe(name, value, 0)
+  })
+  jsonlite::toJSON(Filter(Negate(is.null), values), auto_unbox = TRUE, null = "null")
+}
+"#;
+
+/// The snippets that list the variables of a kernel for `language`, if
+/// there are any: code defining a helper, and the expression calling it,
+/// which evaluates to a JSON list of [`Variable`]s.
+pub fn inspection_code(language: &str) -> Option<(&'static str, &'static str)> {
+    match language.to_lowercase().as_str() {
+        "python" => Some((PYTHON, "_nbterm_variables()")),
+        "r" => Some((R, ".nbterm_variables()")),
+        _ => None,
+    }
+}
+
+/// The name of the user expression a refresh asks for.
+const EXPRESSION: &str = "variables";
+
+/// Keeps the list of a kernel's variables up to date.
+///
+/// Each refresh runs an introspection snippet silently, so it neither shows
+/// in the notebook nor counts as an execution, and reads the variables from
+/// a user expression of the reply. Like [`super::ExecutionQueue`], it never
+/// blocks: the caller feeds it the kernel's messages with
+/// [`VariableInspector::handle`].
+#[derive(Default)]
+pub struct VariableInspector {
+    /// The id of the refresh waiting for its reply.
+    pending: Option<String>,
+    /// The variables found by the last refresh.
+    pub variables: Vec<Variable>,
+    /// Why the last refresh failed, if it did.
+    pub error: Option<String>,
+}
+
+impl VariableInspector {
+    /// Asks a kernel for `language` for its variables, with the snippets
+    /// from [`inspection_code`].
+    pub fn refresh(&mut self, client: &KernelClient, language: &str) -> Result<()> {
+        match inspection_code(language) {
+            Some((code, expression)) => self.refresh_with(client, code, expression),
+            None => {
+                self.variables.clear();
+                self.error = Some(format!("Variables aren't supported for {language} kernels"));
+                Ok(())
+            }
+        }
+    }
+
+    /// Asks the kernel for its variables by running `code`, then evaluating
+    /// `expression`, which gives them as a JSON list of [`Variable`]s. A
+    /// refresh still waiting for its reply is dropped.
+    pub fn refresh_with(
+        &mut self,
+        client: &KernelClient,
+        code: &str,
+        expression: &str,
+    ) -> Result<()> {
+        let msg_id = client.request(
+            Channel::Shell,
+            "execute_request",
+            json!({
+                "code": code,
+                "silent": true,
+                "store_history": false,
+                "user_expressions": { EXPRESSION: expression },
+                "allow_stdin": false,
+                "stop_on_error": false,
+            }),
+        )?;
+        self.pending = Some(msg_id);
+        Ok(())
+    }
+
+    /// Whether a refresh waits for its reply.
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Applies a message from the kernel to the waiting refresh.
+    ///
+    /// Returns whether the message belonged to it.
+    pub fn handle(&mut self, channel: Channel, message: &Message) -> bool {
+        let Some(pending) = &self.pending else {
+            return false;
+        };
+        if message.parent_id() != Some(pending.as_str()) {
+            return false;
+        }
+        if channel == Channel::Shell && message.msg_type() == "execute_reply" {
+            self.pending = None;
+            match parse_reply(&message.content) {
+                Ok(variables) => {
+                    self.variables = variables;
+                    self.error = None;
+                }
+                Err(err) => self.error = Some(format!("{err:#}")),
+            }
+        }
+        true
+    }
+}
+
+/// Reads the variables from the user expression of an `execute_reply`.
+fn parse_reply(content: &Value) -> Result<Vec<Variable>> {
+    let error = |content: &Value| {
+        anyhow!(
+            "{}: {}",
+            content["ename"].as_str().unwrap_or_default(),
+            content["evalue"].as_str().unwrap_or_default()
+        )
+    };
+    if content["status"] != "ok" {
+        return Err(error(content));
+    }
+    let result = &content["user_expressions"][EXPRESSION];
+    if result["status"] != "ok" {
+        return Err(error(result));
+    }
+    // R kernels only show the JSON as text
+    let data = &result["data"];
+    match (&data["application/json"], data["text/plain"].as_str()) {
+        (Value::Null, Some(text)) => serde_json::from_str(text),
+        (json, _) => Vec::deserialize(json),
+    }
+    .context("The kernel listed its variables in an unexpected form")
+}
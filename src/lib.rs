@@ -1,4 +1,5 @@
 #![allow(dead_code)]
 
+pub mod kernel;
 pub mod notebook_util;
 pub mod tui;
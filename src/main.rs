@@ -1,7 +1,461 @@
+use anyhow::{Context, anyhow, bail};
+use clap::{Parser, Subcommand, ValueEnum};
+use nbterm::kernel::spec::{find_kernelspecs, match_kernelspec};
+use nbterm::kernel::{CellState, ExecutionQueue, KernelManager};
+use nbterm::notebook_util::export::{HtmlOptions, TextOptions};
+use nbterm::notebook_util::format::FormatRegistry;
+use nbterm::notebook_util::impls::write_file;
+use nbterm::notebook_util::{Notebook, convert, merge, validation};
 use nbterm::tui::NotebookApp;
+use nbterm::tui::settings::Settings;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
-fn main() -> anyhow::Result<()> {
-    let mut app = NotebookApp::default();
+/// Exit status of a check that found problems, e.g. an invalid notebook.
+const FAILURE: u8 = 1;
+/// Exit status of an error, e.g. a file that cannot be read, and of bad
+/// usage.
+const ERROR: u8 = 2;
+
+/// A terminal editor for Jupyter notebooks.
+#[derive(Parser)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit status: 0 on success, 1 if a check failed, 2 on errors."
+)]
+struct Cli {
+    /// Notebook to open in the editor; created on save if it doesn't exist.
+    file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a notebook to the terminal as text, with its outputs.
+    Cat {
+        file: PathBuf,
+        /// Leave out the outputs of code cells.
+        #[arg(long)]
+        no_outputs: bool,
+        #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+        color: ColorChoice,
+    },
+    /// Convert a notebook to another format.
+    ///
+    /// The formats are the notebook formats `ipynb`, `py:percent`, `md` and
+    /// `myst`, and the exports `html`, `script` (the code in the kernel's
+    /// language) and `markdown` (a document with outputs and images).
+    Convert {
+        file: PathBuf,
+        /// Format to convert to.
+        #[arg(long)]
+        to: String,
+        /// Output file, `-` for standard output; defaults to the input with
+        /// the extension of the format.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Leave out the source of code cells (`html` only).
+        #[arg(long)]
+        no_input: bool,
+    },
+    /// Clear outputs and UI metadata in place, using the project's
+    /// sanitize rules from `.nbterm.json`.
+    Strip {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Don't write anything; fail if a notebook would change.
+        #[arg(long)]
+        check: bool,
+    },
+    /// Check notebooks against the nbformat schema.
+    Validate {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Merge two versions of a notebook, as a git merge driver.
+    ///
+    /// Writes the result over OURS; conflicting changes become conflict
+    /// cells and make the merge fail. To use it for `.ipynb` files:
+    ///
+    ///   git config merge.nbterm.driver "nbterm merge-driver %O %A %B %P"
+    ///   echo "*.ipynb merge=nbterm" >> .gitattributes
+    #[command(verbatim_doc_comment)]
+    MergeDriver {
+        /// The common ancestor.
+        base: PathBuf,
+        /// Our version, overwritten with the result.
+        ours: PathBuf,
+        /// Their version.
+        theirs: PathBuf,
+        /// Path of the notebook in the repository, to tell its format.
+        path: Option<PathBuf>,
+    },
+    /// Execute every cell of a notebook and save the outputs.
+    ///
+    /// Execution stops at the first cell that raises an error or times out,
+    /// which makes the command fail; the outputs so far are saved all the
+    /// same.
+    Run {
+        file: PathBuf,
+        /// Where to save the executed notebook; defaults to in place.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Kernelspec to run on; defaults to the one in the notebook's
+        /// metadata.
+        #[arg(short, long)]
+        kernel: Option<String>,
+        /// Interrupt a cell that runs longer than this many seconds.
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        None => edit(cli.file),
+        Some(Command::Cat {
+            file,
+            no_outputs,
+            color,
+        }) => cat(&file, no_outputs, color),
+        Some(Command::Convert {
+            file,
+            to,
+            output,
+            no_input,
+        }) => convert(&file, &to, output, no_input),
+        Some(Command::Strip { files, check }) => strip(&files, check),
+        Some(Command::Validate { files }) => validate(&files),
+        Some(Command::MergeDriver {
+            base,
+            ours,
+            theirs,
+            path,
+        }) => merge_driver(&base, &ours, &theirs, path.as_deref()),
+        Some(Command::Run {
+            file,
+            output,
+            kernel,
+            timeout,
+        }) => run(
+            &file,
+            output,
+            kernel.as_deref(),
+            timeout.map(Duration::from_secs),
+        ),
+    };
+    result.unwrap_or_else(|err| {
+        eprintln!("nbterm: {err:#}");
+        ExitCode::from(ERROR)
+    })
+}
+
+fn edit(file: Option<PathBuf>) -> anyhow::Result<ExitCode> {
+    let mut app = match file {
+        Some(path) => NotebookApp::with_file(path)?,
+        None => NotebookApp::default(),
+    };
     app.run()?;
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+fn load(formats: &FormatRegistry, path: &Path) -> anyhow::Result<Notebook> {
+    let (notebook, _) = formats
+        .read_file(path)
+        .with_context(|| format!("Failed to load {}", path.display()))?;
+    Ok(notebook)
+}
+
+fn cat(file: &Path, no_outputs: bool, color: ColorChoice) -> anyhow::Result<ExitCode> {
+    let notebook = load(&FormatRegistry::default(), file)?;
+    let color = match color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+        }
+    };
+    let options = TextOptions {
+        color,
+        hide_outputs: no_outputs,
+    };
+    print(&notebook.to_text(&options))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn convert(
+    file: &Path,
+    to: &str,
+    output: Option<PathBuf>,
+    no_input: bool,
+) -> anyhow::Result<ExitCode> {
+    let formats = FormatRegistry::default();
+    let notebook = load(&formats, file)?;
+    let (text, extension) = match to {
+        "html" => {
+            let options = HtmlOptions {
+                hide_input: no_input,
+                ..HtmlOptions::default()
+            };
+            (notebook.to_html(&options), "html".to_string())
+        }
+        "script" => (
+            notebook.to_script(),
+            notebook
+                .script_extension()
+                .trim_start_matches('.')
+                .to_string(),
+        ),
+        "markdown" => {
+            let output = output.unwrap_or_else(|| file.with_extension("md"));
+            if output == Path::new("-") {
+                bail!("Markdown with outputs needs an output file for its images");
+            }
+            notebook.export_markdown_document(&output)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        name => {
+            let Some(format) = formats.by_name(name) else {
+                let names: Vec<&str> = formats.formats().map(|format| format.name()).collect();
+                bail!(
+                    "Unknown format `{name}`, expected one of: {}, html, script, markdown",
+                    names.join(", ")
+                );
+            };
+            let extension = format.extensions()[0].to_string();
+            (format.write(&notebook)?, extension)
+        }
+    };
+    match output {
+        Some(path) if path == Path::new("-") => print(&text)?,
+        output => {
+            let path = output.unwrap_or_else(|| file.with_extension(extension));
+            write_file(&path, &text)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn strip(files: &[PathBuf], check: bool) -> anyhow::Result<ExitCode> {
+    let formats = FormatRegistry::default();
+    let mut changed = false;
+    for path in files {
+        let (mut notebook, format) = formats
+            .read_file(path)
+            .with_context(|| format!("Failed to load {}", path.display()))?;
+        let absolute = std::path::absolute(path)?;
+        let rules = Settings::discover(absolute.parent().unwrap_or(&absolute))?.sanitize;
+        if notebook.sanitize(0..notebook.len(), &rules).is_none() {
+            continue;
+        }
+        changed = true;
+        if check {
+            println!("would strip {}", path.display());
+        } else {
+            format.write_file(&notebook, path)?;
+            println!("stripped {}", path.display());
+        }
+    }
+    Ok(if check && changed {
+        ExitCode::from(FAILURE)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn validate(files: &[PathBuf]) -> anyhow::Result<ExitCode> {
+    let formats = FormatRegistry::default();
+    let mut valid = true;
+    for path in files {
+        let problems: Vec<String> = if path.extension().is_some_and(|ext| ext == "ipynb") {
+            // Check the JSON as it is on disk, not as repaired by loading,
+            // but upgraded from v3 as loading does: v3 has no schema here.
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+            match serde_json::from_str(&content) {
+                Ok(mut value) => match convert::upgrade(&mut value) {
+                    Ok(()) => validation::validate(&value)
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                    Err(err) => vec![format!("{err:#}")],
+                },
+                Err(err) => vec![format!("not valid JSON: {err}")],
+            }
+        } else {
+            match formats.read_file(path) {
+                Ok((notebook, _)) => notebook
+                    .validate()?
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                Err(err) => vec![format!("{err:#}")],
+            }
+        };
+        if problems.is_empty() {
+            println!("{}: valid", path.display());
+        } else {
+            valid = false;
+            for problem in problems {
+                println!("{}: {problem}", path.display());
+            }
+        }
+    }
+    Ok(if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(FAILURE)
+    })
+}
+
+fn merge_driver(
+    base: &Path,
+    ours: &Path,
+    theirs: &Path,
+    path: Option<&Path>,
+) -> anyhow::Result<ExitCode> {
+    let formats = FormatRegistry::default();
+    // Git passes temporary files, so the format comes from the real path.
+    let content = std::fs::read_to_string(ours)
+        .with_context(|| format!("Failed to read file: {}", ours.display()))?;
+    let Some(format) = formats.for_path(path.unwrap_or(ours), Some(&content)) else {
+        bail!(
+            "Unknown notebook format: {}",
+            path.unwrap_or(ours).display()
+        );
+    };
+    let read = |file: &Path| {
+        std::fs::read_to_string(file)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| format.read(&content))
+            .with_context(|| format!("Failed to load {}", file.display()))
+    };
+    let merged = merge::merge(&read(base)?, &format.read(&content)?, &read(theirs)?);
+    format.write_file(&merged, ours)?;
+    let conflicts = merged.conflicts().len();
+    if conflicts == 0 {
+        return Ok(ExitCode::SUCCESS);
+    }
+    eprintln!(
+        "nbterm: {conflicts} conflicting cell(s) in {}",
+        path.unwrap_or(ours).display()
+    );
+    Ok(ExitCode::from(FAILURE))
+}
+
+fn run(
+    file: &Path,
+    output: Option<PathBuf>,
+    kernel: Option<&str>,
+    timeout: Option<Duration>,
+) -> anyhow::Result<ExitCode> {
+    let formats = FormatRegistry::default();
+    let (mut notebook, mut format) = formats
+        .read_file(file)
+        .with_context(|| format!("Failed to load {}", file.display()))?;
+    let specs = find_kernelspecs();
+    let spec = match kernel {
+        Some(name) => specs
+            .iter()
+            .find(|spec| spec.name == name)
+            .with_context(|| format!("No kernel named {name} is installed"))?,
+        None => match_kernelspec(&specs, &notebook.metadata)
+            .with_context(|| format!("No installed kernel matches {}", file.display()))?,
+    };
+    let absolute = std::path::absolute(file)?;
+    let mut kernel = KernelManager::start(spec.clone(), absolute.parent().unwrap_or(&absolute))?;
+
+    let mut queue = ExecutionQueue::default();
+    let cells = 0..notebook.len();
+    queue.enqueue(&mut notebook, cells);
+    // the running cell and when it started
+    let mut started: Option<(String, Instant)> = None;
+    let mut timed_out = None;
+    // what stopped the run, reported once the outputs so far are saved
+    let mut stopped = None;
+    loop {
+        let Some(client) = kernel.client() else {
+            stopped = Some(anyhow!("The kernel is not connected"));
+            break;
+        };
+        if let Err(err) = queue.start_next(&mut notebook, client) {
+            stopped = Some(err);
+            break;
+        }
+        let Some(running) = queue.running() else {
+            break;
+        };
+        if started.as_ref().is_none_or(|(id, _)| id != running) {
+            started = Some((running.to_string(), Instant::now()));
+        }
+        if let (Some(timeout), Some((id, since))) = (timeout, &started)
+            && since.elapsed() > timeout
+        {
+            timed_out = Some(id.clone());
+            let _ = kernel.interrupt();
+            queue.reset();
+            break;
+        }
+        match client.recv_timeout(Duration::from_millis(100)) {
+            Some((channel, message)) => {
+                queue.handle(&mut notebook, channel, &message);
+            }
+            None if !kernel.is_running() => {
+                stopped = Some(anyhow!("The kernel died"));
+                break;
+            }
+            None => {}
+        }
+    }
+    let shutdown = kernel.shutdown();
+
+    let path = output.unwrap_or_else(|| file.to_path_buf());
+    if let Some(output_format) = formats.for_path(&path, None) {
+        format = output_format;
+    }
+    format.write_file(&notebook, &path)?;
+    if let Some(err) = stopped {
+        return Err(err);
+    }
+    shutdown?;
+    if let Some(id) = timed_out {
+        let index = notebook.iter().position(|cell| cell.id() == Some(&id));
+        eprintln!(
+            "nbterm: cell {} timed out",
+            index.map_or(id, |index| index.to_string())
+        );
+        return Ok(ExitCode::from(FAILURE));
+    }
+    let failed = notebook
+        .iter()
+        .position(|cell| cell.id().and_then(|id| queue.state(id)) == Some(CellState::Errored));
+    match failed {
+        Some(index) => {
+            eprintln!("nbterm: cell {index} raised an error");
+            Ok(ExitCode::from(FAILURE))
+        }
+        None => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Writes to standard output, treating a closed pipe (as in `| head`) as
+/// success.
+fn print(text: &str) -> anyhow::Result<()> {
+    match std::io::stdout().lock().write_all(text.as_bytes()) {
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => Err(err.into()),
+        _ => Ok(()),
+    }
 }
@@ -0,0 +1,359 @@
+//! Conversion between nbformat versions.
+//!
+//! Notebooks are always edited as nbformat 4.5 in memory. [`upgrade`] brings
+//! v3 JSON into the v4 layout on load, and [`downgrade`] writes 4.5 JSON
+//! back out as an older 4.x minor or as v3 for older Jupyter installs.
+//!
+//! The rules follow `nbformat.v4.convert`.
+
+use super::types::{METADATA_KEY, NBFORMAT, NBFORMAT_MINOR};
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+
+/// Key, under [`METADATA_KEY`], marking a markdown cell that was a v3
+/// heading cell, to make it one again when downgrading to v3.
+const HEADING_KEY: &str = "v3_heading";
+
+/// v3 short output keys and the MIME types they became in v4.
+const MIME_KEYS: [(&str, &str); 8] = [
+    ("text", "text/plain"),
+    ("html", "text/html"),
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("jpeg", "image/jpeg"),
+    ("latex", "text/latex"),
+    ("json", "application/json"),
+    ("javascript", "application/javascript"),
+];
+
+/// Returns the `(nbformat, nbformat_minor)` declared by notebook JSON, or
+/// `None` if it declares no major version.
+pub fn detect_version(notebook: &Value) -> Option<(u64, u64)> {
+    let major = notebook.get("nbformat")?.as_u64()?;
+    let minor = notebook
+        .get("nbformat_minor")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    Some((major, minor))
+}
+
+/// Upgrades v3 notebook JSON in place to nbformat 4.0. v4 JSON is left
+/// as it is, whatever its minor version.
+///
+/// Notebooks without a version are left alone for validation to report.
+/// Versions other than 3 and 4 are rejected.
+pub fn upgrade(notebook: &mut Value) -> Result<()> {
+    match detect_version(notebook) {
+        None | Some((4, _)) => {}
+        Some((3, _)) => upgrade_v3(notebook),
+        Some((major, minor)) => bail!("Unsupported nbformat version {major}.{minor}"),
+    }
+    Ok(())
+}
+
+/// Downgrades v4 notebook JSON in place to `nbformat.nbformat_minor`.
+///
+/// Supported targets are 4.0 through 4.5 and 3.0.
+pub fn downgrade(notebook: &mut Value, nbformat: u8, nbformat_minor: u8) -> Result<()> {
+    match (nbformat, nbformat_minor) {
+        (NBFORMAT, minor) if minor <= NBFORMAT_MINOR => {
+            clear_upgrade_marks(notebook);
+            for cell in cells_mut(notebook) {
+                if minor < 5 {
+                    cell.remove("id");
+                }
+                if minor < 1 {
+                    cell.remove("attachments");
+                }
+            }
+            notebook["nbformat"] = json!(NBFORMAT);
+            notebook["nbformat_minor"] = json!(minor);
+        }
+        (3, 0) => downgrade_to_v3(notebook),
+        (major, minor) => bail!("Cannot downgrade a notebook to nbformat {major}.{minor}"),
+    }
+    Ok(())
+}
+
+/// Removes the marks [`upgrade`] leaves on v3 heading cells from notebook
+/// JSON, for everything but a downgrade to v3.
+pub(crate) fn clear_upgrade_marks(notebook: &mut Value) {
+    for cell in cells_mut(notebook) {
+        take_heading_mark(cell);
+    }
+}
+
+/// Removes the v3 heading mark from a cell, returning whether it had one.
+fn take_heading_mark(cell: &mut Map<String, Value>) -> bool {
+    let Some(metadata) = cell.get_mut("metadata").and_then(Value::as_object_mut) else {
+        return false;
+    };
+    let Some(ours) = metadata
+        .get_mut(METADATA_KEY)
+        .and_then(Value::as_object_mut)
+    else {
+        return false;
+    };
+    let marked = ours.remove(HEADING_KEY).is_some();
+    if ours.is_empty() {
+        metadata.remove(METADATA_KEY);
+    }
+    marked
+}
+
+fn cells_mut(notebook: &mut Value) -> impl Iterator<Item = &mut Map<String, Value>> {
+    notebook
+        .get_mut("cells")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+fn outputs_mut(cell: &mut Map<String, Value>) -> impl Iterator<Item = &mut Map<String, Value>> {
+    cell.get_mut("outputs")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+/// Joins a v3/v4 multiline value (string or list of strings) into one string.
+fn join_lines(value: Option<Value>) -> String {
+    match value {
+        Some(Value::String(s)) => s,
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+// === v3 -> v4 ===
+
+fn upgrade_v3(notebook: &mut Value) {
+    let Some(root) = notebook.as_object_mut() else {
+        return;
+    };
+    let mut cells = vec![];
+    if let Some(Value::Array(worksheets)) = root.remove("worksheets") {
+        for worksheet in worksheets {
+            if let Some(Value::Array(ws_cells)) = worksheet.get("cells") {
+                cells.extend(ws_cells.iter().cloned().map(upgrade_cell));
+            }
+        }
+    }
+    root.insert("cells".to_string(), Value::Array(cells));
+    if let Some(Value::Object(metadata)) = root.get_mut("metadata") {
+        metadata.remove("name");
+        metadata.remove("signature");
+    }
+    root.insert("nbformat".to_string(), json!(4));
+    root.insert("nbformat_minor".to_string(), json!(0));
+}
+
+fn upgrade_cell(mut cell: Value) -> Value {
+    let Some(object) = cell.as_object_mut() else {
+        return cell;
+    };
+    if !object.get("metadata").is_some_and(Value::is_object) {
+        object.insert("metadata".to_string(), json!({}));
+    }
+    match object.get("cell_type").and_then(Value::as_str) {
+        Some("code") => {
+            object.remove("language");
+            if let Some(collapsed) = object.remove("collapsed") {
+                object["metadata"]["collapsed"] = collapsed;
+            }
+            let source = join_lines(object.remove("input"));
+            object.insert("source".to_string(), json!(source));
+            let count = object.remove("prompt_number").unwrap_or(Value::Null);
+            object.insert("execution_count".to_string(), count);
+            let outputs = match object.remove("outputs") {
+                Some(Value::Array(outputs)) => outputs.into_iter().map(upgrade_output).collect(),
+                _ => vec![],
+            };
+            object.insert("outputs".to_string(), Value::Array(outputs));
+        }
+        Some("heading") => {
+            let level = object.remove("level").and_then(|l| l.as_u64()).unwrap_or(1);
+            let text = join_lines(object.remove("source"));
+            let text = text.lines().collect::<Vec<_>>().join(" ");
+            let source = format!("{} {text}", "#".repeat(level as usize));
+            object.insert("cell_type".to_string(), json!("markdown"));
+            object.insert("source".to_string(), json!(source));
+            object["metadata"][METADATA_KEY] = json!({ HEADING_KEY: true });
+        }
+        Some("html") => {
+            object.insert("cell_type".to_string(), json!("markdown"));
+        }
+        _ => {}
+    }
+    cell
+}
+
+fn upgrade_output(mut output: Value) -> Value {
+    let Some(object) = output.as_object_mut() else {
+        return output;
+    };
+    match object.get("output_type").and_then(Value::as_str) {
+        Some(kind @ ("pyout" | "display_data")) => {
+            let is_result = kind == "pyout";
+            let mut metadata = match object.remove("metadata") {
+                Some(Value::Object(metadata)) => metadata,
+                _ => Map::new(),
+            };
+            let count = object.remove("prompt_number").unwrap_or(Value::Null);
+            object.remove("output_type");
+            let mut data = std::mem::take(object);
+            to_mime_keys(&mut data);
+            to_mime_keys(&mut metadata);
+            if let Some(Value::String(json)) = data.get("application/json")
+                && let Ok(parsed) = serde_json::from_str::<Value>(json)
+            {
+                data.insert("application/json".to_string(), parsed);
+            }
+            if is_result {
+                object.insert("output_type".to_string(), json!("execute_result"));
+                object.insert("execution_count".to_string(), count);
+            } else {
+                object.insert("output_type".to_string(), json!("display_data"));
+            }
+            object.insert("data".to_string(), Value::Object(data));
+            object.insert("metadata".to_string(), Value::Object(metadata));
+        }
+        Some("pyerr") => {
+            object.insert("output_type".to_string(), json!("error"));
+        }
+        Some("stream") => {
+            let name = object.remove("stream").unwrap_or(json!("stdout"));
+            object.insert("name".to_string(), name);
+        }
+        _ => {}
+    }
+    output
+}
+
+fn to_mime_keys(map: &mut Map<String, Value>) {
+    for (short, mime) in MIME_KEYS {
+        if let Some(value) = map.remove(short) {
+            map.insert(mime.to_string(), value);
+        }
+    }
+}
+
+// === v4 -> v3 ===
+
+fn downgrade_to_v3(notebook: &mut Value) {
+    let language = notebook["metadata"]["language_info"]["name"]
+        .as_str()
+        .or(notebook["metadata"]["kernelspec"]["language"].as_str())
+        .unwrap_or("python")
+        .to_string();
+    let Some(root) = notebook.as_object_mut() else {
+        return;
+    };
+    let cells: Vec<Value> = match root.remove("cells") {
+        Some(Value::Array(cells)) => cells
+            .into_iter()
+            .map(|cell| downgrade_cell(cell, &language))
+            .collect(),
+        _ => vec![],
+    };
+    root.insert(
+        "worksheets".to_string(),
+        json!([{ "cells": cells, "metadata": {} }]),
+    );
+    match root.get_mut("metadata") {
+        Some(Value::Object(metadata)) => {
+            metadata.entry("name").or_insert(json!(""));
+        }
+        _ => {
+            root.insert("metadata".to_string(), json!({ "name": "" }));
+        }
+    }
+    root.insert("nbformat".to_string(), json!(3));
+    root.insert("nbformat_minor".to_string(), json!(0));
+}
+
+fn downgrade_cell(mut cell: Value, language: &str) -> Value {
+    let Some(object) = cell.as_object_mut() else {
+        return cell;
+    };
+    object.remove("id");
+    object.remove("attachments");
+    let heading = take_heading_mark(object);
+    match object.get("cell_type").and_then(Value::as_str) {
+        Some("code") => {
+            object.insert("language".to_string(), json!(language));
+            let source = join_lines(object.remove("source"));
+            object.insert("input".to_string(), json!(source));
+            let count = object.remove("execution_count").unwrap_or(Value::Null);
+            object.insert("prompt_number".to_string(), count);
+            let collapsed = object
+                .get_mut("metadata")
+                .and_then(Value::as_object_mut)
+                .and_then(|metadata| metadata.remove("collapsed"))
+                .unwrap_or(json!(false));
+            object.insert("collapsed".to_string(), collapsed);
+            for output in outputs_mut(object) {
+                downgrade_output(output);
+            }
+        }
+        Some("markdown") if heading => {
+            // A heading cell upgraded from v3 becomes one again, unless it
+            // was edited into more than a heading.
+            let source = join_lines(object.get("source").cloned());
+            if !source.contains('\n') && source.starts_with('#') {
+                let level = source.chars().take_while(|c| *c == '#').count();
+                let text = source[level..].trim_start();
+                object.insert("cell_type".to_string(), json!("heading"));
+                object.insert("source".to_string(), json!(text));
+                object.insert("level".to_string(), json!(level));
+            }
+        }
+        _ => {}
+    }
+    cell
+}
+
+fn downgrade_output(output: &mut Map<String, Value>) {
+    output.remove("transient");
+    match output.get("output_type").and_then(Value::as_str) {
+        Some(kind @ ("execute_result" | "display_data")) => {
+            if kind == "execute_result" {
+                output.insert("output_type".to_string(), json!("pyout"));
+                let count = output.remove("execution_count").unwrap_or(Value::Null);
+                output.insert("prompt_number".to_string(), count);
+            }
+            let mut data = match output.remove("data") {
+                Some(Value::Object(data)) => data,
+                _ => Map::new(),
+            };
+            if let Some(json) = data.get("application/json")
+                && !json.is_string()
+            {
+                data.insert("application/json".to_string(), json!(json.to_string()));
+            }
+            from_mime_keys(&mut data);
+            output.extend(data);
+            if let Some(Value::Object(metadata)) = output.get_mut("metadata") {
+                from_mime_keys(metadata);
+            }
+        }
+        Some("error") => {
+            output.insert("output_type".to_string(), json!("pyerr"));
+        }
+        Some("stream") => {
+            let name = output.remove("name").unwrap_or(json!("stdout"));
+            output.insert("stream".to_string(), name);
+        }
+        _ => {}
+    }
+}
+
+fn from_mime_keys(map: &mut Map<String, Value>) {
+    for (short, mime) in MIME_KEYS {
+        if let Some(value) = map.remove(mime) {
+            map.insert(short.to_string(), value);
+        }
+    }
+}
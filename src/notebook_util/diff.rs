@@ -0,0 +1,541 @@
+//! Semantic diffs between two versions of a notebook.
+//!
+//! Cells are aligned by id where both versions have the same one; the rest
+//! are paired by content, first identical sources and then the most
+//! similar cells of the same type. Paired cells that changed their order
+//! relative to the others are reported as moved. Sources are diffed line by
+//! line, outputs are summarized per MIME type (or stream name), and
+//! execution counts and cell ids are ignored.
+
+use super::types::*;
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+/// Least bigram similarity for two cells with different ids to be taken as
+/// versions of the same cell.
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// Largest table the line diff fills in; bigger changes are treated as a
+/// removal of the old lines and an addition of the new ones.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// How a cell or output changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// How a cell compares between the two notebooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellStatus {
+    Unchanged,
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One line of a line-level diff, without its line ending.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineDiff {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// A change in the outputs of one kind, e.g. `image/png` or `stderr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputChange {
+    /// A MIME type, a stream name, or `error`.
+    pub kind: String,
+    pub change: ChangeKind,
+}
+
+/// A cell of either notebook and how it changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellDiff {
+    /// Index in the old notebook, `None` for added cells.
+    pub old: Option<usize>,
+    /// Index in the new notebook, `None` for removed cells.
+    pub new: Option<usize>,
+    pub status: CellStatus,
+    /// The cell changed places relative to the cells around it.
+    pub moved: bool,
+    /// Old and new type, if the type changed.
+    pub type_change: Option<(CellType, CellType)>,
+    /// The whole source, marked line by line.
+    pub source: Vec<LineDiff>,
+    pub outputs: Vec<OutputChange>,
+    pub metadata_changed: bool,
+}
+
+/// The differences between two notebooks, cell by cell in the order of the
+/// new notebook, with removed cells where they used to be.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NotebookDiff {
+    pub cells: Vec<CellDiff>,
+    /// The notebook-level metadata changed.
+    pub metadata_changed: bool,
+}
+
+impl NotebookDiff {
+    /// Returns `true` if the notebooks have the same content.
+    pub fn is_empty(&self) -> bool {
+        !self.metadata_changed
+            && self
+                .cells
+                .iter()
+                .all(|cell| cell.status == CellStatus::Unchanged && !cell.moved)
+    }
+
+    /// The cells that were added, removed, modified or moved.
+    pub fn changes(&self) -> impl Iterator<Item = &CellDiff> {
+        self.cells
+            .iter()
+            .filter(|cell| cell.status != CellStatus::Unchanged || cell.moved)
+    }
+}
+
+impl Notebook {
+    /// Compares the notebook, as the old version, with `new`.
+    pub fn diff(&self, new: &Notebook) -> NotebookDiff {
+        let pairs = align(&self.cells, &new.cells);
+        let moved = moved_pairs(&pairs);
+
+        let mut old_of_new = vec![None; new.cells.len()];
+        let mut paired_old = vec![false; self.cells.len()];
+        let mut moved_old = vec![false; self.cells.len()];
+        for (&(old, new), &moved) in pairs.iter().zip(&moved) {
+            old_of_new[new] = Some((old, moved));
+            paired_old[old] = true;
+            moved_old[old] = moved;
+        }
+
+        // Removed cells go where they were: after the old cell before them,
+        // unless that one moved.
+        let mut cells = vec![];
+        let mut next_old = 0;
+        let mut removed_until = |cells: &mut Vec<CellDiff>, end: usize| {
+            while next_old < self.cells.len()
+                && (next_old < end || !paired_old[next_old] || moved_old[next_old])
+            {
+                if !paired_old[next_old] {
+                    cells.push(one_sided(&self.cells[next_old], Some(next_old), None));
+                }
+                next_old += 1;
+            }
+        };
+        removed_until(&mut cells, 0);
+        for (index, cell) in new.cells.iter().enumerate() {
+            match old_of_new[index] {
+                Some((old, moved)) => {
+                    let mut diff = diff_cell(&self.cells[old], cell);
+                    diff.old = Some(old);
+                    diff.new = Some(index);
+                    diff.moved = moved;
+                    cells.push(diff);
+                    if !moved {
+                        removed_until(&mut cells, old + 1);
+                    }
+                }
+                None => cells.push(one_sided(cell, None, Some(index))),
+            }
+        }
+        removed_until(&mut cells, self.cells.len());
+
+        NotebookDiff {
+            cells,
+            metadata_changed: self.metadata != new.metadata,
+        }
+    }
+}
+
+/// Pairs up the cells of both versions, as `(old, new)` index pairs sorted
+/// by old index.
+pub(crate) fn align(old: &[Cell], new: &[Cell]) -> Vec<(usize, usize)> {
+    let mut new_of_old = vec![None; old.len()];
+    let mut paired_new = vec![false; new.len()];
+
+    let ids: HashMap<&str, usize> = new
+        .iter()
+        .enumerate()
+        .filter_map(|(index, cell)| Some((cell.id()?, index)))
+        .collect();
+    for (index, cell) in old.iter().enumerate() {
+        if let Some(&other) = cell.id().and_then(|id| ids.get(id))
+            && !paired_new[other]
+        {
+            new_of_old[index] = Some(other);
+            paired_new[other] = true;
+        }
+    }
+
+    let mut identical: HashMap<(CellType, &str), VecDeque<usize>> = HashMap::new();
+    for (index, cell) in new.iter().enumerate().filter(|(i, _)| !paired_new[*i]) {
+        identical
+            .entry((cell.cell_type(), cell.source().as_str()))
+            .or_default()
+            .push_back(index);
+    }
+    for (index, cell) in old.iter().enumerate() {
+        if new_of_old[index].is_some() {
+            continue;
+        }
+        if let Some(other) = identical
+            .get_mut(&(cell.cell_type(), cell.source().as_str()))
+            .and_then(VecDeque::pop_front)
+        {
+            new_of_old[index] = Some(other);
+            paired_new[other] = true;
+        }
+    }
+
+    let mut candidates = vec![];
+    for (i, a) in old.iter().enumerate() {
+        if new_of_old[i].is_some() {
+            continue;
+        }
+        for (j, b) in new.iter().enumerate() {
+            if paired_new[j] || a.cell_type() != b.cell_type() {
+                continue;
+            }
+            let similarity = similarity(a.source(), b.source());
+            if similarity >= MIN_SIMILARITY {
+                candidates.push((similarity, i, j));
+            }
+        }
+    }
+    // Most similar first; among equals, cells that stayed closest.
+    candidates.sort_by(|a, b| {
+        b.0.total_cmp(&a.0)
+            .then(a.1.abs_diff(a.2).cmp(&b.1.abs_diff(b.2)))
+            .then(a.1.cmp(&b.1))
+    });
+    for (_, i, j) in candidates {
+        if new_of_old[i].is_none() && !paired_new[j] {
+            new_of_old[i] = Some(j);
+            paired_new[j] = true;
+        }
+    }
+
+    new_of_old
+        .into_iter()
+        .enumerate()
+        .filter_map(|(old, new)| Some((old, new?)))
+        .collect()
+}
+
+/// Marks the pairs that are not part of the longest run of pairs keeping
+/// their order, i.e. the cells that moved.
+pub(crate) fn moved_pairs(pairs: &[(usize, usize)]) -> Vec<bool> {
+    // Longest increasing subsequence of the new indices, by patience
+    // sorting: `tails[k]` is the pair ending the best run of length k + 1.
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; pairs.len()];
+    for (index, &(_, new)) in pairs.iter().enumerate() {
+        let length = tails.partition_point(|&tail| pairs[tail].1 < new);
+        previous[index] = length.checked_sub(1).map(|k| tails[k]);
+        if length == tails.len() {
+            tails.push(index);
+        } else {
+            tails[length] = index;
+        }
+    }
+    let mut moved = vec![true; pairs.len()];
+    let mut current = tails.last().copied();
+    while let Some(index) = current {
+        moved[index] = false;
+        current = previous[index];
+    }
+    moved
+}
+
+/// Similarity of two texts between 0 and 1, as the Sørensen–Dice
+/// coefficient of their character bigrams.
+fn similarity(a: &str, b: &str) -> f64 {
+    if a == b {
+        return 1.0;
+    }
+    let bigrams = |text: &str| {
+        let chars: Vec<char> = text.chars().collect();
+        let mut counts: HashMap<(char, char), usize> = HashMap::new();
+        for pair in chars.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_default() += 1;
+        }
+        (counts, chars.len().saturating_sub(1))
+    };
+    let ((a, a_len), (b, b_len)) = (bigrams(a), bigrams(b));
+    if a_len + b_len == 0 {
+        return 0.0;
+    }
+    let shared: usize = a
+        .iter()
+        .map(|(bigram, count)| (*count).min(b.get(bigram).copied().unwrap_or(0)))
+        .sum();
+    2.0 * shared as f64 / (a_len + b_len) as f64
+}
+
+/// A cell only one of the notebooks has.
+fn one_sided(cell: &Cell, old: Option<usize>, new: Option<usize>) -> CellDiff {
+    let (status, line, change): (_, fn(String) -> LineDiff, _) = if old.is_some() {
+        (CellStatus::Removed, LineDiff::Removed, ChangeKind::Removed)
+    } else {
+        (CellStatus::Added, LineDiff::Added, ChangeKind::Added)
+    };
+    CellDiff {
+        old,
+        new,
+        status,
+        moved: false,
+        type_change: None,
+        source: cell
+            .source()
+            .as_str()
+            .lines()
+            .map(|text| line(text.to_string()))
+            .collect(),
+        outputs: output_kinds(cell)
+            .into_keys()
+            .map(|kind| OutputChange { kind, change })
+            .collect(),
+        metadata_changed: false,
+    }
+}
+
+/// Compares two versions of a cell; the indices are left for the caller.
+fn diff_cell(old: &Cell, new: &Cell) -> CellDiff {
+    let type_change =
+        (old.cell_type() != new.cell_type()).then(|| (old.cell_type(), new.cell_type()));
+    let source = diff_lines(old.source(), new.source());
+
+    let (old_outputs, mut new_outputs) = (output_kinds(old), output_kinds(new));
+    let mut outputs = vec![];
+    for (kind, contents) in old_outputs {
+        let change = match new_outputs.remove(&kind) {
+            None => ChangeKind::Removed,
+            Some(new) if new != contents => ChangeKind::Modified,
+            Some(_) => continue,
+        };
+        outputs.push(OutputChange { kind, change });
+    }
+    outputs.extend(new_outputs.into_keys().map(|kind| OutputChange {
+        kind,
+        change: ChangeKind::Added,
+    }));
+    outputs.sort_by(|a, b| a.kind.cmp(&b.kind));
+
+    let metadata_changed =
+        old.metadata() != new.metadata() || old.attachments() != new.attachments();
+    let changed = type_change.is_some()
+        || old.source() != new.source()
+        || !outputs.is_empty()
+        || metadata_changed;
+    CellDiff {
+        old: None,
+        new: None,
+        status: if changed {
+            CellStatus::Modified
+        } else {
+            CellStatus::Unchanged
+        },
+        moved: false,
+        type_change,
+        source,
+        outputs,
+        metadata_changed,
+    }
+}
+
+/// The outputs of a cell grouped by kind, in order, as comparable values.
+fn output_kinds(cell: &Cell) -> BTreeMap<String, Vec<Value>> {
+    let mut kinds: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    let Cell::Code(code) = cell else {
+        return kinds;
+    };
+    for output in &code.outputs {
+        match output {
+            Output::Stream { name, text, .. } => {
+                kinds
+                    .entry(name.clone())
+                    .or_default()
+                    .push(json!(text.as_str()));
+            }
+            Output::Error {
+                ename,
+                evalue,
+                traceback,
+                ..
+            } => {
+                let error = json!([ename, evalue, traceback]);
+                kinds.entry("error".to_string()).or_default().push(error);
+            }
+            Output::ExecuteResult { data, .. } | Output::DisplayData { data, .. } => {
+                for (mime, value) in data.iter() {
+                    kinds
+                        .entry(mime.to_string())
+                        .or_default()
+                        .push(value.clone());
+                }
+            }
+        }
+    }
+    kinds
+}
+
+/// Diffs two texts line by line, giving every line of both in order.
+pub fn diff_lines(old: &str, new: &str) -> Vec<LineDiff> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (a, b) in common_lines(&old, &new)
+        .into_iter()
+        .chain([(old.len(), new.len())])
+    {
+        diff.extend(
+            old[i..a]
+                .iter()
+                .map(|line| LineDiff::Removed(line.to_string())),
+        );
+        diff.extend(
+            new[j..b]
+                .iter()
+                .map(|line| LineDiff::Added(line.to_string())),
+        );
+        if a < old.len() {
+            diff.push(LineDiff::Same(old[a].to_string()));
+        }
+        (i, j) = (a + 1, b + 1);
+    }
+    diff
+}
+
+/// The lines both sequences keep, as `(old, new)` index pairs of a longest
+/// common subsequence, in order.
+pub(crate) fn common_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    if a.len().saturating_mul(b.len()) <= MAX_DIFF_CELLS {
+        // `common[i][j]` is the length of the longest common subsequence
+        // of `a[i..]` and `b[j..]`.
+        let mut common = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                common[i][j] = if a[i] == b[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                pairs.push((prefix + i, prefix + j));
+                (i, j) = (i + 1, j + 1);
+            } else if common[i + 1][j] >= common[i][j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    pairs.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+    pairs
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        })
+    }
+}
+
+impl fmt::Display for CellStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CellStatus::Unchanged => "unchanged",
+            CellStatus::Added => "added",
+            CellStatus::Removed => "removed",
+            CellStatus::Modified => "modified",
+        })
+    }
+}
+
+impl CellDiff {
+    /// A one-line summary of the cell's changes, e.g.
+    /// `cell 3 -> 4 modified, moved`.
+    pub fn summary(&self) -> String {
+        let mut summary = match (self.old, self.new) {
+            (Some(old), Some(new)) if old != new => format!("cell {old} -> {new}"),
+            (Some(index), _) | (None, Some(index)) => format!("cell {index}"),
+            (None, None) => "cell".to_string(),
+        };
+        summary.push_str(&format!(" {}", self.status));
+        if self.moved {
+            summary.push_str(", moved");
+        }
+        if let Some((old, new)) = self.type_change {
+            summary.push_str(&format!(", {old} -> {new}"));
+        }
+        if self.metadata_changed {
+            summary.push_str(", metadata changed");
+        }
+        summary
+    }
+}
+
+impl fmt::Display for CellDiff {
+    /// The [`CellDiff::summary`], then the source diff with `+`/`-`
+    /// markers and the changed outputs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        if self.status != CellStatus::Unchanged {
+            for line in &self.source {
+                match line {
+                    LineDiff::Same(text) => writeln!(f, "  {text}")?,
+                    LineDiff::Added(text) => writeln!(f, "+ {text}")?,
+                    LineDiff::Removed(text) => writeln!(f, "- {text}")?,
+                }
+            }
+        }
+        if !self.outputs.is_empty() {
+            let outputs: Vec<String> = self
+                .outputs
+                .iter()
+                .map(|output| format!("{} {}", output.kind, output.change))
+                .collect();
+            writeln!(f, "  outputs: {}", outputs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for NotebookDiff {
+    /// The changed cells, one after the other.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.metadata_changed {
+            writeln!(f, "notebook metadata changed")?;
+        }
+        for cell in self.changes() {
+            write!(f, "{cell}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+//! Structural cell editing.
+//!
+//! Every operation is expressed as a [`CellEdit`] and applying one returns
+//! the edit that undoes it, so an undo/redo history only has to keep a stack
+//! of edits:
+//!
+//! ```
+//! # use nbterm::notebook_util::*;
+//! let mut notebook = Notebook::default();
+//! notebook.push_code_cell(vec!["a = 1"], None, vec![]);
+//! notebook.push_code_cell(vec!["b = 2"], None, vec![]);
+//!
+//! let undo = notebook.move_cell_down(0).unwrap();
+//! assert_eq!(notebook.cells[0].source().as_str(), "b = 2");
+//! notebook.apply(undo).unwrap();
+//! assert_eq!(notebook.cells[0].source().as_str(), "a = 1");
+//! ```
+
+use super::types::*;
+use serde_json::json;
+use std::ops::Range;
+
+/// A reversible change to the cell list of a [`Notebook`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellEdit {
+    /// Insert `cells` so that the first one ends up at `index`.
+    Insert { index: usize, cells: Vec<Cell> },
+    /// Remove `count` cells starting at `index`.
+    Remove { index: usize, count: usize },
+    /// Replace the cell at `index` with `cell`.
+    Replace { index: usize, cell: Box<Cell> },
+    /// Move the cell at `from` so that it ends up at `to`.
+    Move { from: usize, to: usize },
+    /// Exchange the cells at `a` and `b`.
+    Swap { a: usize, b: usize },
+    /// Replace the source of the cell at `index`, keeping the rest of it.
+    SetSource {
+        index: usize,
+        source: MultilineString,
+    },
+    /// Apply several edits in order, as a single undo step.
+    Batch(Vec<CellEdit>),
+}
+
+impl Notebook {
+    /// Applies an edit and returns the edit that reverts it.
+    ///
+    /// Returns `None`, leaving the notebook unchanged, if any index is out
+    /// of bounds.
+    pub fn apply(&mut self, edit: CellEdit) -> Option<CellEdit> {
+        let len = self.cells.len();
+        match edit {
+            CellEdit::Insert { index, cells } => {
+                if index > len {
+                    return None;
+                }
+                let count = cells.len();
+                self.cells.splice(index..index, cells);
+                Some(CellEdit::Remove { index, count })
+            }
+            CellEdit::Remove { index, count } => {
+                let end = index.checked_add(count)?;
+                if end > len {
+                    return None;
+                }
+                let cells = self.cells.drain(index..end).collect();
+                Some(CellEdit::Insert { index, cells })
+            }
+            CellEdit::Replace { index, cell } => {
+                let old = std::mem::replace(self.cells.get_mut(index)?, *cell);
+                Some(CellEdit::Replace {
+                    index,
+                    cell: Box::new(old),
+                })
+            }
+            CellEdit::Move { from, to } => {
+                if from >= len || to >= len {
+                    return None;
+                }
+                let cell = self.cells.remove(from);
+                self.cells.insert(to, cell);
+                Some(CellEdit::Move { from: to, to: from })
+            }
+            CellEdit::Swap { a, b } => {
+                if a >= len || b >= len {
+                    return None;
+                }
+                self.cells.swap(a, b);
+                Some(CellEdit::Swap { a, b })
+            }
+            CellEdit::SetSource { index, source } => {
+                let old = std::mem::replace(self.cells.get_mut(index)?.source_mut(), source);
+                Some(CellEdit::SetSource { index, source: old })
+            }
+            CellEdit::Batch(edits) => {
+                let mut undo = Vec::with_capacity(edits.len());
+                for edit in edits {
+                    match self.apply(edit) {
+                        Some(inverse) => undo.push(inverse),
+                        None => {
+                            // Roll back what was already applied.
+                            for inverse in undo.into_iter().rev() {
+                                self.apply(inverse);
+                            }
+                            return None;
+                        }
+                    }
+                }
+                undo.reverse();
+                Some(CellEdit::Batch(undo))
+            }
+        }
+    }
+
+    /// Deletes the cell at `index`.
+    pub fn delete_cell(&mut self, index: usize) -> Option<CellEdit> {
+        self.delete_cells(index..index.checked_add(1)?)
+    }
+
+    /// Deletes every cell in `range`.
+    pub fn delete_cells(&mut self, range: Range<usize>) -> Option<CellEdit> {
+        if range.start > range.end {
+            return None;
+        }
+        self.apply(CellEdit::Remove {
+            index: range.start,
+            count: range.len(),
+        })
+    }
+
+    /// Moves the cell at `index` one position up.
+    pub fn move_cell_up(&mut self, index: usize) -> Option<CellEdit> {
+        self.apply(CellEdit::Move {
+            from: index,
+            to: index.checked_sub(1)?,
+        })
+    }
+
+    /// Moves the cell at `index` one position down.
+    pub fn move_cell_down(&mut self, index: usize) -> Option<CellEdit> {
+        self.apply(CellEdit::Move {
+            from: index,
+            to: index.checked_add(1)?,
+        })
+    }
+
+    /// Exchanges the cells at `a` and `b`.
+    pub fn swap_cells(&mut self, a: usize, b: usize) -> Option<CellEdit> {
+        self.apply(CellEdit::Swap { a, b })
+    }
+
+    /// Replaces the source of the cell at `index`, as typing does.
+    pub fn set_source(
+        &mut self,
+        index: usize,
+        source: impl Into<MultilineString>,
+    ) -> Option<CellEdit> {
+        self.apply(CellEdit::SetSource {
+            index,
+            source: source.into(),
+        })
+    }
+
+    /// Inserts a copy of the cell at `index` right after it, with a new id.
+    pub fn duplicate_cell(&mut self, index: usize) -> Option<CellEdit> {
+        let mut copy = self.cells.get(index)?.clone();
+        *copy.id_mut() = Some(self.new_cell_id());
+        self.apply(CellEdit::Insert {
+            index: index + 1,
+            cells: vec![copy],
+        })
+    }
+
+    /// Splits the cell at `index` in two at byte offset `cursor` of its
+    /// source.
+    ///
+    /// The first cell keeps the id, outputs and execution count and loses
+    /// the line break right before the cursor, if any. The second cell gets
+    /// a new id, a copy of the metadata and the rest of the source.
+    pub fn split_cell(&mut self, index: usize, cursor: usize) -> Option<CellEdit> {
+        let cell = self.cells.get(index)?;
+        let source = cell.source().as_str();
+        if !source.is_char_boundary(cursor) {
+            return None;
+        }
+        let (head, tail) = source.split_at(cursor);
+        let head = head.strip_suffix('\n').unwrap_or(head);
+
+        let mut first = cell.clone();
+        *first.source_mut() = head.into();
+        let mut second = self.blank_like(cell);
+        *second.source_mut() = tail.into();
+
+        self.apply(CellEdit::Batch(vec![
+            CellEdit::Replace {
+                index,
+                cell: Box::new(first),
+            },
+            CellEdit::Insert {
+                index: index + 1,
+                cells: vec![second],
+            },
+        ]))
+    }
+
+    /// Merges the cell at `index` with the one after it.
+    ///
+    /// The merged cell keeps the type, id and metadata of the first cell;
+    /// the sources are joined by a line break. Outputs of two code cells are
+    /// concatenated and the execution count is cleared, and the attachments
+    /// of the second cell are added to those of the first.
+    ///
+    /// Returns `None`, leaving the cells alone, when the merged cell couldn't
+    /// keep the second cell's outputs or attachments: outputs only fit in a
+    /// code cell, attachments only outside one, and two different
+    /// attachments can't share a name.
+    pub fn merge_cells(&mut self, index: usize) -> Option<CellEdit> {
+        let first = self.cells.get(index)?;
+        let second = self.cells.get(index.checked_add(1)?)?;
+
+        let mut merged = first.clone();
+        let mut source = first.source().to_string();
+        if !source.is_empty() && !source.ends_with('\n') {
+            source.push('\n');
+        }
+        source.push_str(second.source());
+        *merged.source_mut() = source.into();
+        match (&mut merged, second) {
+            (Cell::Code(merged), Cell::Code(second)) => {
+                merged.outputs.extend(second.outputs.iter().cloned());
+                merged.execution_count = None;
+            }
+            (_, Cell::Code(second)) if !second.outputs.is_empty() => return None,
+            _ => {}
+        }
+        if let Some(attachments) = second.attachments().filter(|a| !a.is_empty()) {
+            let merged = merged
+                .attachments_mut()?
+                .get_or_insert_with(Default::default);
+            for (name, attachment) in attachments {
+                if merged.get(name).is_some_and(|known| known != attachment) {
+                    return None;
+                }
+                merged.insert(name.clone(), attachment.clone());
+            }
+        }
+
+        self.apply(CellEdit::Batch(vec![
+            CellEdit::Replace {
+                index,
+                cell: Box::new(merged),
+            },
+            CellEdit::Remove {
+                index: index + 1,
+                count: 1,
+            },
+        ]))
+    }
+
+    /// Changes the type of the cell at `index`, keeping its id, source,
+    /// metadata and unknown keys.
+    ///
+    /// Outputs are dropped when leaving a code cell, and attachments when
+    /// turning a cell into a code cell; undoing the edit restores both.
+    pub fn change_cell_type(&mut self, index: usize, cell_type: CellType) -> Option<CellEdit> {
+        let cell = self.cells.get(index)?;
+        if cell.cell_type() == cell_type {
+            return None;
+        }
+        let (id, source, metadata, attachments, other) = match cell.clone() {
+            Cell::Code(c) => (c.id, c.source, c.metadata, None, c.other),
+            Cell::Markdown(c) => (c.id, c.source, c.metadata, c.attachments, c.other),
+            Cell::Raw(c) => (c.id, c.source, c.metadata, c.attachments, c.other),
+        };
+        let cell = match cell_type {
+            CellType::Code => Cell::Code(CodeCell {
+                id,
+                source,
+                metadata,
+                execution_count: None,
+                outputs: vec![],
+                other,
+            }),
+            CellType::Markdown => Cell::Markdown(MarkdownCell {
+                id,
+                source,
+                metadata,
+                attachments,
+                other,
+            }),
+            CellType::Raw => Cell::Raw(RawCell {
+                id,
+                source,
+                metadata,
+                attachments,
+                other,
+            }),
+        };
+        self.apply(CellEdit::Replace {
+            index,
+            cell: Box::new(cell),
+        })
+    }
+
+    /// Returns copies of the cells in `range`, e.g. for a clipboard.
+    pub fn copy_cells(&self, range: Range<usize>) -> Option<Vec<Cell>> {
+        self.cells.get(range).map(<[Cell]>::to_vec)
+    }
+
+    /// Inserts `cells` at `index`, such as cells copied from this or another
+    /// notebook.
+    ///
+    /// Pasted cells whose id is missing or already taken get a fresh one.
+    pub fn paste_cells(&mut self, index: usize, mut cells: Vec<Cell>) -> Option<CellEdit> {
+        let mut used = self.cell_ids();
+        for cell in &mut cells {
+            let id = match cell.id() {
+                Some(id) if !used.contains(id) => id.to_string(),
+                _ => Self::new_cell_id_avoiding(&used),
+            };
+            used.insert(id.clone());
+            *cell.id_mut() = Some(id);
+        }
+        self.apply(CellEdit::Insert { index, cells })
+    }
+
+    /// Creates an empty cell of the same type as `cell`, with a new id and a
+    /// copy of its metadata.
+    fn blank_like(&self, cell: &Cell) -> Cell {
+        let id = Some(self.new_cell_id());
+        let metadata = cell.metadata().clone();
+        match cell {
+            Cell::Code(_) => Cell::Code(CodeCell {
+                id,
+                source: MultilineString::default(),
+                metadata,
+                execution_count: None,
+                outputs: vec![],
+                other: json!({}),
+            }),
+            Cell::Markdown(_) => Cell::Markdown(MarkdownCell {
+                id,
+                source: MultilineString::default(),
+                metadata,
+                attachments: None,
+                other: json!({}),
+            }),
+            Cell::Raw(_) => Cell::Raw(RawCell {
+                id,
+                source: MultilineString::default(),
+                metadata,
+                attachments: None,
+                other: json!({}),
+            }),
+        }
+    }
+}
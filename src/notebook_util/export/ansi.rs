@@ -0,0 +1,161 @@
+//! Parsing of the ANSI escape sequences found in stream outputs and
+//! tracebacks.
+//!
+//! Only SGR sequences (colors and text attributes) carry meaning for
+//! rendering; every other escape sequence is dropped.
+
+/// A terminal color, as set by an SGR sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 256 indexed colors; 0 to 15 are the basic and bright
+    /// colors.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Text attributes in effect for a run of text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl Style {
+    /// Applies the parameters of one SGR sequence.
+    fn apply(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            *self = Style::default();
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                7 => self.inverse = true,
+                22 => (self.bold, self.dim) = (false, false),
+                23 => self.italic = false,
+                24 => self.underline = false,
+                27 => self.inverse = false,
+                30..=37 => self.fg = Some(Color::Indexed(param as u8 - 30)),
+                38 => self.fg = extended_color(&mut params),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed(param as u8 - 40)),
+                48 => self.bg = extended_color(&mut params),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Color::Indexed(param as u8 - 90 + 8)),
+                100..=107 => self.bg = Some(Color::Indexed(param as u8 - 100 + 8)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads the rest of a `38;5;n` or `38;2;r;g;b` color.
+fn extended_color(params: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    let byte = |value: Option<u16>| value.map(|value| value.min(255) as u8);
+    match params.next()? {
+        5 => byte(params.next()).map(Color::Indexed),
+        2 => Some(Color::Rgb(
+            byte(params.next())?,
+            byte(params.next())?,
+            byte(params.next())?,
+        )),
+        _ => None,
+    }
+}
+
+/// Splits `text` into runs of equally styled text, dropping the escape
+/// sequences themselves.
+pub fn parse(text: &str) -> Vec<(Style, String)> {
+    let mut runs: Vec<(Style, String)> = vec![];
+    let mut style = Style::default();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            match runs.last_mut() {
+                Some((last, run)) if *last == style => run.push(c),
+                _ => runs.push((style, c.to_string())),
+            }
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters, then a final byte in `@`..`~`.
+            Some('[') => {
+                let mut sequence = String::new();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        if c == 'm' {
+                            let params: Vec<u16> = sequence
+                                .split([';', ':'])
+                                .filter(|param| !param.is_empty())
+                                .map(|param| param.parse().unwrap_or(0))
+                                .collect();
+                            style.apply(&params);
+                        }
+                        break;
+                    }
+                    sequence.push(c);
+                }
+            }
+            // OSC: up to BEL or ST.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    runs
+}
+
+/// Removes all escape sequences from `text`.
+pub fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|(_, run)| run).collect()
+}
+
+/// The RGB value of an indexed color, using the xterm palette.
+pub fn rgb(color: Color) -> (u8, u8, u8) {
+    const BASIC: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 49, 49),
+        (13, 188, 121),
+        (229, 229, 16),
+        (36, 114, 200),
+        (188, 63, 188),
+        (17, 168, 205),
+        (229, 229, 229),
+        (102, 102, 102),
+        (241, 76, 76),
+        (35, 209, 139),
+        (245, 245, 67),
+        (59, 142, 234),
+        (214, 112, 214),
+        (41, 184, 219),
+        (255, 255, 255),
+    ];
+    match color {
+        Color::Rgb(r, g, b) => (r, g, b),
+        Color::Indexed(index @ 0..=15) => BASIC[index as usize],
+        Color::Indexed(index @ 16..=231) => {
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            let index = index - 16;
+            (level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        Color::Indexed(index) => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! A small lexer-based syntax highlighter for code cells.
+//!
+//! It knows comments, strings, numbers and keywords of the usual kernel
+//! languages, which is enough for reports; unknown languages are only
+//! escaped.
+
+use super::html::escape;
+
+/// Lexical rules of a language.
+struct Syntax {
+    line_comment: &'static str,
+    block_comment: Option<(&'static str, &'static str)>,
+    triple_quotes: bool,
+    keywords: &'static [&'static str],
+}
+
+const PYTHON: Syntax = Syntax {
+    line_comment: "#",
+    block_comment: None,
+    triple_quotes: true,
+    keywords: &[
+        "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+        "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+        "if", "import", "in", "is", "lambda", "match", "nonlocal", "not", "or", "pass", "raise",
+        "return", "try", "while", "with", "yield",
+    ],
+};
+
+const R: Syntax = Syntax {
+    line_comment: "#",
+    block_comment: None,
+    triple_quotes: false,
+    keywords: &[
+        "FALSE", "Inf", "NA", "NULL", "NaN", "TRUE", "break", "else", "for", "function", "if",
+        "in", "library", "next", "repeat", "return", "while",
+    ],
+};
+
+const JULIA: Syntax = Syntax {
+    line_comment: "#",
+    block_comment: Some(("#=", "=#")),
+    triple_quotes: true,
+    keywords: &[
+        "begin", "break", "catch", "const", "continue", "do", "else", "elseif", "end", "export",
+        "false", "finally", "for", "function", "global", "if", "import", "let", "local", "macro",
+        "module", "mutable", "nothing", "quote", "return", "struct", "true", "try", "using",
+        "while",
+    ],
+};
+
+const SHELL: Syntax = Syntax {
+    line_comment: "#",
+    block_comment: None,
+    triple_quotes: false,
+    keywords: &[
+        "case", "do", "done", "echo", "elif", "else", "esac", "export", "fi", "for", "function",
+        "if", "in", "local", "return", "then", "until", "while",
+    ],
+};
+
+const C_LIKE: Syntax = Syntax {
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+    triple_quotes: false,
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default", "do",
+        "else", "enum", "export", "extends", "false", "fn", "for", "function", "if", "impl",
+        "import", "let", "match", "mut", "new", "null", "pub", "return", "static", "struct",
+        "switch", "this", "throw", "trait", "true", "try", "typeof", "use", "var", "void", "while",
+    ],
+};
+
+fn syntax(language: &str) -> Option<&'static Syntax> {
+    match language.to_ascii_lowercase().as_str() {
+        "python" | "python3" | "ipython" | "ipython3" | "py" => Some(&PYTHON),
+        "r" => Some(&R),
+        "julia" => Some(&JULIA),
+        "bash" | "sh" | "shell" | "zsh" => Some(&SHELL),
+        "javascript" | "js" | "typescript" | "ts" | "rust" | "c" | "c++" | "cpp" | "java"
+        | "go" | "scala" | "kotlin" | "csharp" | "c#" => Some(&C_LIKE),
+        _ => None,
+    }
+}
+
+/// Renders `code` as HTML with `<span>`s around tokens, classed `c`
+/// (comment), `s` (string), `m` (number) and `k` (keyword).
+pub(crate) fn highlight(code: &str, language: &str) -> String {
+    let Some(syntax) = syntax(language) else {
+        return escape(code);
+    };
+    let mut out = String::new();
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let (class, len) = if rest.starts_with(syntax.line_comment)
+            && !syntax
+                .block_comment
+                .is_some_and(|(open, _)| rest.starts_with(open))
+        {
+            ("c", rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some((open, close)) = syntax
+            .block_comment
+            .filter(|(open, _)| rest.starts_with(open))
+        {
+            let end = rest[open.len()..]
+                .find(close)
+                .map_or(rest.len(), |end| open.len() + end + close.len());
+            ("c", end)
+        } else if c == '"' || c == '\'' || (c == '`' && syntax.line_comment == "//") {
+            ("s", string_len(rest, c, syntax.triple_quotes))
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            ("m", len)
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let class = if syntax.keywords.contains(&&rest[..len]) {
+                "k"
+            } else {
+                ""
+            };
+            (class, len)
+        } else {
+            ("", c.len_utf8())
+        };
+        let (token, tail) = rest.split_at(len);
+        if class.is_empty() {
+            out.push_str(&escape(token));
+        } else {
+            out.push_str(&format!("<span class=\"{class}\">{}</span>", escape(token)));
+        }
+        rest = tail;
+    }
+    out
+}
+
+/// Length of the string literal at the start of `text`, up to the end of
+/// the line for unterminated ones.
+fn string_len(text: &str, quote: char, triple_quotes: bool) -> usize {
+    let triple: String = std::iter::repeat_n(quote, 3).collect();
+    if triple_quotes && text.starts_with(&triple) {
+        return text[3..].find(&triple).map_or(text.len(), |end| end + 6);
+    }
+    let mut escaped = false;
+    for (index, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\n' => return index,
+            c if c == quote => return index + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
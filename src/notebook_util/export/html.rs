@@ -0,0 +1,286 @@
+//! Export of notebooks to a single, self-contained HTML page.
+//!
+//! Markdown cells are rendered, code is highlighted, and outputs are
+//! inlined: images as data URIs, HTML and SVG as they are, and ANSI colors
+//! in streams and tracebacks as styled spans. The page needs no scripts or
+//! external files.
+
+use super::ansi::{self, Color, Style};
+use super::highlight::highlight;
+use super::markdown_html;
+use crate::notebook_util::mime::{self, MimeBundle};
+use crate::notebook_util::types::*;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+/// Output representations in the order they are preferred for HTML.
+const HTML_MIME_PRIORITY: &[&str] = &[
+    mime::TEXT_HTML,
+    mime::IMAGE_SVG,
+    mime::IMAGE_PNG,
+    mime::IMAGE_JPEG,
+    "image/gif",
+    mime::TEXT_MARKDOWN,
+    mime::TEXT_LATEX,
+    mime::APPLICATION_JSON,
+    mime::TEXT_PLAIN,
+];
+
+const STYLE: &str = include_str!("report.css");
+
+/// Settings for [`Notebook::to_html`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlOptions {
+    /// Leave out the source of code cells, keeping their outputs.
+    pub hide_input: bool,
+    /// Page title; defaults to the notebook's `title` metadata.
+    pub title: Option<String>,
+}
+
+impl Notebook {
+    /// Renders the notebook as a self-contained HTML page.
+    pub fn to_html(&self, options: &HtmlOptions) -> String {
+        let title = options
+            .title
+            .as_deref()
+            .or_else(|| self.metadata.other.get("title").and_then(Value::as_str))
+            .unwrap_or("Notebook");
+        let language = self.language().unwrap_or_default();
+
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+        let _ = writeln!(out, "<title>{}</title>", escape(title));
+        let _ = writeln!(out, "<style>\n{STYLE}</style>");
+        out.push_str("</head>\n<body>\n<main class=\"notebook\">\n");
+        for cell in &self.cells {
+            match cell {
+                Cell::Markdown(markdown) => {
+                    out.push_str("<div class=\"cell markdown\">\n");
+                    out.push_str(&render_markdown(
+                        &markdown.source,
+                        markdown.attachments.as_ref(),
+                    ));
+                    out.push_str("</div>\n");
+                }
+                Cell::Code(code) => {
+                    if options.hide_input && code.outputs.is_empty() {
+                        continue;
+                    }
+                    out.push_str("<div class=\"cell code\">\n");
+                    if !options.hide_input {
+                        let _ = writeln!(
+                            out,
+                            "<div class=\"input\"><div class=\"prompt\">In&nbsp;[{}]:</div>\
+                             <pre class=\"source\"><code>{}</code></pre></div>",
+                            prompt_number(code.execution_count),
+                            highlight(&code.source, language)
+                        );
+                    }
+                    for output in &code.outputs {
+                        render_output(&mut out, output);
+                    }
+                    out.push_str("</div>\n");
+                }
+                // Like nbconvert, only raw cells meant for HTML are kept.
+                Cell::Raw(raw) => {
+                    let format = raw
+                        .metadata
+                        .get("format")
+                        .or(raw.metadata.get("raw_mimetype"));
+                    if format.and_then(Value::as_str) == Some(mime::TEXT_HTML) {
+                        out.push_str(&raw.source);
+                        out.push('\n');
+                    }
+                }
+            }
+        }
+        out.push_str("</main>\n</body>\n</html>\n");
+        out
+    }
+
+    /// Writes the notebook as an HTML page to `path`, see
+    /// [`Notebook::to_html`].
+    pub fn export_html<P: AsRef<Path>>(&self, path: P, options: &HtmlOptions) -> Result<()> {
+        crate::notebook_util::impls::write_file(path.as_ref(), &self.to_html(options))
+    }
+}
+
+fn prompt_number(execution_count: Option<u32>) -> String {
+    execution_count.map_or(" ".to_string(), |count| count.to_string())
+}
+
+fn render_markdown(source: &str, attachments: Option<&BTreeMap<String, MimeBundle>>) -> String {
+    let resolve = |url: &str| {
+        let bundle = attachments?.get(url.strip_prefix("attachment:")?)?;
+        let (mime, value) = bundle.richest(&[
+            mime::IMAGE_PNG,
+            mime::IMAGE_JPEG,
+            "image/gif",
+            mime::IMAGE_SVG,
+        ])?;
+        data_uri(mime, value.as_str()?)
+    };
+    markdown_html::render(source, &resolve)
+}
+
+fn render_output(out: &mut String, output: &Output) {
+    match output {
+        Output::Stream { name, text, .. } => {
+            let _ = writeln!(
+                out,
+                "<div class=\"output stream {}\"><pre>{}</pre></div>",
+                escape(name),
+                ansi_to_html(&text.collapse_carriage_returns())
+            );
+        }
+        Output::Error { traceback, .. } => {
+            let _ = writeln!(
+                out,
+                "<div class=\"output error\"><pre>{}</pre></div>",
+                ansi_to_html(&traceback.join("\n"))
+            );
+        }
+        Output::ExecuteResult {
+            execution_count,
+            data,
+            metadata,
+            ..
+        } => {
+            let _ = write!(
+                out,
+                "<div class=\"output result\"><div class=\"prompt\">Out[{}]:</div>",
+                prompt_number(*execution_count)
+            );
+            render_bundle(out, data, metadata);
+            out.push_str("</div>\n");
+        }
+        Output::DisplayData { data, metadata, .. } => {
+            out.push_str("<div class=\"output display\">");
+            render_bundle(out, data, metadata);
+            out.push_str("</div>\n");
+        }
+    }
+}
+
+fn render_bundle(out: &mut String, data: &MimeBundle, metadata: &Value) {
+    let Some((mime, value)) = data.richest(HTML_MIME_PRIORITY) else {
+        return;
+    };
+    let text = value.as_str().unwrap_or_default();
+    match mime {
+        mime::TEXT_HTML | mime::IMAGE_SVG => out.push_str(text),
+        mime::TEXT_MARKDOWN => out.push_str(&render_markdown(text, None)),
+        mime::APPLICATION_JSON => {
+            let json = serde_json::to_string_pretty(value).unwrap_or_default();
+            let _ = write!(out, "<pre>{}</pre>", escape(&json));
+        }
+        mime::TEXT_LATEX => {
+            let _ = write!(out, "<div class=\"math\">{}</div>", escape(text));
+        }
+        mime::TEXT_PLAIN => {
+            let _ = write!(out, "<pre>{}</pre>", ansi_to_html(text));
+        }
+        image => {
+            // Only numbers are taken as sizes: the metadata is as
+            // untrusted as the rest of the notebook.
+            let size: String = ["width", "height"]
+                .into_iter()
+                .filter_map(|key| {
+                    let value = metadata.get(image)?.get(key)?;
+                    let size = match value {
+                        Value::Number(number) => number.to_string(),
+                        Value::String(text) => text.trim().parse::<f64>().ok()?.to_string(),
+                        _ => return None,
+                    };
+                    Some(format!(" {key}=\"{size}\""))
+                })
+                .collect();
+            if let Some(uri) = data_uri(image, text) {
+                let _ = write!(out, "<img src=\"{}\"{size}>", escape(&uri));
+            }
+        }
+    }
+}
+
+/// A data URI for base64-encoded (or, for SVG, textual) image data.
+fn data_uri(mime: &str, data: &str) -> Option<String> {
+    if mime == mime::IMAGE_SVG {
+        return Some(format!(
+            "data:{mime};charset=utf-8,{}",
+            data.replace('%', "%25")
+                .replace('#', "%23")
+                .replace('"', "%22")
+        ));
+    }
+    let data: String = data.split_ascii_whitespace().collect();
+    Some(format!("data:{mime};base64,{data}"))
+}
+
+/// Escapes text and turns its ANSI styles into `<span>`s with inline CSS.
+pub fn ansi_to_html(text: &str) -> String {
+    let mut out = String::new();
+    for (style, run) in ansi::parse(text) {
+        let css = css(&style);
+        if css.is_empty() {
+            out.push_str(&escape(&run));
+        } else {
+            let _ = write!(out, "<span style=\"{css}\">{}</span>", escape(&run));
+        }
+    }
+    out
+}
+
+fn css(style: &Style) -> String {
+    let (fg, bg) = if style.inverse {
+        (
+            style.bg.or(Some(Color::Indexed(0))),
+            style.fg.or(Some(Color::Indexed(7))),
+        )
+    } else {
+        (style.fg, style.bg)
+    };
+    let color = |color: Color| {
+        let (r, g, b) = ansi::rgb(color);
+        format!("#{r:02x}{g:02x}{b:02x}")
+    };
+    let mut css = vec![];
+    if let Some(fg) = fg {
+        css.push(format!("color: {}", color(fg)));
+    }
+    if let Some(bg) = bg {
+        css.push(format!("background-color: {}", color(bg)));
+    }
+    if style.bold {
+        css.push("font-weight: bold".to_string());
+    }
+    if style.dim {
+        css.push("opacity: 0.7".to_string());
+    }
+    if style.italic {
+        css.push("font-style: italic".to_string());
+    }
+    if style.underline {
+        css.push("text-decoration: underline".to_string());
+    }
+    css.join("; ")
+}
+
+/// Escapes the characters that are special in HTML text and attributes.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
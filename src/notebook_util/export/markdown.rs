@@ -0,0 +1,183 @@
+//! Export of notebooks to a Markdown document with their outputs.
+//!
+//! Unlike the Jupytext Markdown format, this includes the outputs: text as
+//! code blocks, HTML and Markdown as they are, and images as files in a
+//! `<name>_files/` directory next to the document, linked from it. Cell
+//! attachments are written there too.
+
+use super::ansi;
+use crate::notebook_util::markdown::fence_for;
+use crate::notebook_util::mime::{self, MimeBundle};
+use crate::notebook_util::types::*;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// Output representations in the order they are preferred for Markdown,
+/// as in nbconvert's Markdown exporter.
+const MARKDOWN_MIME_PRIORITY: &[&str] = &[
+    mime::TEXT_HTML,
+    mime::TEXT_MARKDOWN,
+    mime::IMAGE_SVG,
+    mime::TEXT_LATEX,
+    mime::IMAGE_PNG,
+    mime::IMAGE_JPEG,
+    "image/gif",
+    mime::TEXT_PLAIN,
+];
+
+/// A notebook rendered as Markdown, with the files it links to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarkdownDocument {
+    pub markdown: String,
+    /// Files to write next to the document, as paths relative to it and
+    /// their contents.
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Notebook {
+    /// Renders the notebook as a Markdown document whose images live in
+    /// `files_dir`, a directory relative to the document.
+    ///
+    /// Returns an error if an image is not valid base64.
+    pub fn to_markdown_document(&self, files_dir: &str) -> Result<MarkdownDocument> {
+        let language = self.language().unwrap_or_default();
+        let mut document = MarkdownDocument::default();
+        let mut blocks = vec![];
+        for (index, cell) in self.cells.iter().enumerate() {
+            match cell {
+                Cell::Markdown(MarkdownCell {
+                    source,
+                    attachments,
+                    ..
+                }) => {
+                    let mut text = source.to_string();
+                    for (name, bundle) in attachments.iter().flatten() {
+                        let Some((mime, _)) = bundle.richest(&[
+                            mime::IMAGE_PNG,
+                            mime::IMAGE_JPEG,
+                            "image/gif",
+                            mime::IMAGE_SVG,
+                        ]) else {
+                            continue;
+                        };
+                        let Some(file_name) = attachment_file_name(name) else {
+                            continue;
+                        };
+                        let file = format!("{files_dir}/{index}_{file_name}");
+                        document
+                            .files
+                            .push((file.clone(), image_bytes(bundle, mime)?));
+                        text = text.replace(&format!("attachment:{name}"), &file);
+                    }
+                    blocks.push(text.trim_end().to_string());
+                }
+                Cell::Code(code) => {
+                    let source = code.source.trim_end();
+                    if !source.is_empty() {
+                        blocks.push(fenced(source, language));
+                    }
+                    for (number, output) in code.outputs.iter().enumerate() {
+                        let stem = format!("{files_dir}/output_{index}_{number}");
+                        if let Some(block) = render_output(output, &stem, &mut document.files)? {
+                            blocks.push(block);
+                        }
+                    }
+                }
+                Cell::Raw(raw) => {
+                    let format = raw
+                        .metadata
+                        .get("format")
+                        .or(raw.metadata.get("raw_mimetype"));
+                    if format.and_then(|format| format.as_str()) == Some(mime::TEXT_MARKDOWN) {
+                        blocks.push(raw.source.trim_end().to_string());
+                    }
+                }
+            }
+        }
+        document.markdown = blocks.join("\n\n") + "\n";
+        Ok(document)
+    }
+
+    /// Writes the notebook as Markdown to `path`, with its images in a
+    /// `<name>_files/` directory next to it.
+    pub fn export_markdown_document<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let document = self.to_markdown_document(&format!("{stem}_files"))?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for (file, content) in &document.files {
+            let file = base.join(file);
+            if let Some(dir) = file.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+            }
+            fs::write(&file, content)
+                .with_context(|| format!("Failed to write file: {}", file.display()))?;
+        }
+        crate::notebook_util::impls::write_file(path, &document.markdown)
+    }
+}
+
+/// The name an attachment is written under in the files directory: its
+/// name without directories, so that one like `../../x` stays inside.
+/// `None` if nothing is left.
+fn attachment_file_name(name: &str) -> Option<&str> {
+    let file_name = name.rsplit(['/', '\\']).next()?;
+    (!matches!(file_name, "" | "." | "..")).then_some(file_name)
+}
+
+fn render_output(
+    output: &Output,
+    stem: &str,
+    files: &mut Vec<(String, Vec<u8>)>,
+) -> Result<Option<String>> {
+    let data = match output {
+        Output::Stream { text, .. } => {
+            return Ok(Some(fenced(
+                &ansi::strip(&text.collapse_carriage_returns()),
+                "",
+            )));
+        }
+        Output::Error { traceback, .. } => {
+            return Ok(Some(fenced(&ansi::strip(&traceback.join("\n")), "")));
+        }
+        Output::ExecuteResult { data, .. } | Output::DisplayData { data, .. } => data,
+    };
+    let Some((mime, value)) = data.richest(MARKDOWN_MIME_PRIORITY) else {
+        return Ok(None);
+    };
+    let text = value.as_str().unwrap_or_default();
+    Ok(Some(match mime {
+        mime::TEXT_HTML | mime::TEXT_MARKDOWN => text.trim_end().to_string(),
+        mime::TEXT_LATEX => format!("$$\n{}\n$$", text.trim().trim_matches('$').trim()),
+        mime::TEXT_PLAIN => fenced(&ansi::strip(text), ""),
+        image => {
+            let extension = match image {
+                mime::IMAGE_SVG => "svg",
+                mime::IMAGE_JPEG => "jpg",
+                other => other.trim_start_matches("image/"),
+            };
+            let file = format!("{stem}.{extension}");
+            files.push((file.clone(), image_bytes(data, image)?));
+            let alt = image.trim_start_matches("image/").trim_end_matches("+xml");
+            format!("![{alt}]({file})")
+        }
+    }))
+}
+
+/// The file content for an image: base64-decoded, except for SVG.
+fn image_bytes(bundle: &MimeBundle, mime: &str) -> Result<Vec<u8>> {
+    match mime {
+        mime::IMAGE_SVG => Ok(bundle.svg().unwrap_or_default().as_bytes().to_vec()),
+        _ => bundle.decode_base64(mime).unwrap_or_else(|| Ok(vec![])),
+    }
+}
+
+fn fenced(text: &str, language: &str) -> String {
+    let fence = fence_for(text);
+    format!(
+        "{fence}{language}\n{}\n{fence}",
+        text.trim_end_matches('\n')
+    )
+}
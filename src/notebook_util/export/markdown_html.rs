@@ -0,0 +1,410 @@
+//! Rendering of markdown cells to HTML.
+//!
+//! This covers the parts of CommonMark and GitHub Flavored Markdown that
+//! notebooks use in practice: ATX headings, paragraphs, fenced code,
+//! lists, block quotes, tables, rules, raw HTML, and the usual inline
+//! markup. LaTeX math is left as text in the page.
+
+use super::highlight::highlight;
+use super::html::escape;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+static HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}(#{1,6})(?:[ \t]+(.*?))?(?:[ \t]+#+)?[ \t]*$").unwrap());
+static RULE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}(?:(?:\*[ \t]*){3,}|(?:-[ \t]*){3,}|(?:_[ \t]*){3,})$").unwrap()
+});
+static LIST_ITEM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^( {0,3})([-*+]|\d{1,9}[.)])(?:[ \t]+(.*))?$").unwrap());
+static TABLE_DELIMITER: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}\|?[ \t]*:?-+:?[ \t]*(?:\|[ \t]*:?-+:?[ \t]*)*\|?[ \t]*$").unwrap()
+});
+static HTML_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ {0,3}</?[A-Za-z][A-Za-z0-9-]*(?:[\s/>]|$)|^ {0,3}<!--").unwrap()
+});
+
+static CODE_SPAN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(`+)([^`]|[^`].*?[^`])(`+)").unwrap());
+static INLINE_HTML: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"</?[A-Za-z][A-Za-z0-9-]*(?:\s[^<>]*)?/?>").unwrap());
+static AUTOLINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<(https?://[^\s<>]+)>").unwrap());
+static IMAGE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"!\[([^\]]*)\]\(\s*<?([^)\s>]*)>?(?:\s+"([^"]*)")?\s*\)"#).unwrap()
+});
+static LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\[([^\]]+)\]\(\s*<?([^)\s>]*)>?(?:\s+"([^"]*)")?\s*\)"#).unwrap()
+});
+static STRONG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\*\*(\S(?:.*?\S)?)\*\*|__(\S(?:.*?\S)?)__").unwrap());
+static EMPHASIS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\*(\S(?:[^*]*?\S)?)\*|\b_(\S(?:[^_]*?\S)?)_\b").unwrap());
+static STRIKETHROUGH: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"~~(\S(?:.*?\S)?)~~").unwrap());
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new("\u{0}(\\d+)\u{0}").unwrap());
+
+/// Renders `markdown` to HTML. Image and link targets go through
+/// `resolve`, which can replace them (e.g. `attachment:` URLs with data
+/// URIs).
+pub(crate) fn render(markdown: &str, resolve: &dyn Fn(&str) -> Option<String>) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut out = String::new();
+    Renderer { resolve }.blocks(&lines, &mut out);
+    out
+}
+
+struct Renderer<'r> {
+    resolve: &'r dyn Fn(&str) -> Option<String>,
+}
+
+impl Renderer<'_> {
+    fn blocks(&self, lines: &[&str], out: &mut String) {
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            if line.trim().is_empty() {
+                index += 1;
+            } else if let Some(captures) = HEADING.captures(line) {
+                let level = captures[1].len();
+                let text = captures.get(2).map_or("", |text| text.as_str());
+                out.push_str(&format!(
+                    "<h{level} id=\"{}\">{}</h{level}>\n",
+                    slug(text),
+                    self.inline(text)
+                ));
+                index += 1;
+            } else if let Some((fence, info)) = fence_open(line) {
+                let start = index + 1;
+                let end = lines[start..]
+                    .iter()
+                    .position(|line| is_fence_close(line, &fence))
+                    .map_or(lines.len(), |offset| start + offset);
+                let language = info.split_whitespace().next().unwrap_or_default();
+                let code = lines[start..end].join("\n");
+                if language.is_empty() {
+                    out.push_str(&format!("<pre><code>{}</code></pre>\n", escape(&code)));
+                } else {
+                    out.push_str(&format!(
+                        "<pre><code class=\"language-{}\">{}</code></pre>\n",
+                        escape(language),
+                        highlight(&code, language)
+                    ));
+                }
+                index = end + 1;
+            } else if line.trim_start().starts_with("$$") {
+                let end = math_block_end(lines, index);
+                let math = lines[index..end].join("\n");
+                out.push_str(&format!("<div class=\"math\">{}</div>\n", escape(&math)));
+                index = end;
+            } else if RULE.is_match(line) {
+                out.push_str("<hr>\n");
+                index += 1;
+            } else if line.trim_start().starts_with('>') {
+                let end = index
+                    + lines[index..]
+                        .iter()
+                        .position(|line| line.trim().is_empty())
+                        .unwrap_or(lines.len() - index);
+                let quoted: Vec<&str> = lines[index..end]
+                    .iter()
+                    .map(|line| {
+                        let line = line.trim_start();
+                        let line = line.strip_prefix('>').unwrap_or(line);
+                        line.strip_prefix(' ').unwrap_or(line)
+                    })
+                    .collect();
+                out.push_str("<blockquote>\n");
+                self.blocks(&quoted, out);
+                out.push_str("</blockquote>\n");
+                index = end;
+            } else if LIST_ITEM.is_match(line) {
+                index = self.list(lines, index, out);
+            } else if index + 1 < lines.len()
+                && line.contains('|')
+                && TABLE_DELIMITER.is_match(lines[index + 1])
+            {
+                index = self.table(lines, index, out);
+            } else if HTML_BLOCK.is_match(line) {
+                while index < lines.len() && !lines[index].trim().is_empty() {
+                    out.push_str(lines[index]);
+                    out.push('\n');
+                    index += 1;
+                }
+            } else {
+                let start = index;
+                index += 1;
+                while index < lines.len() && !starts_block(lines[index]) {
+                    index += 1;
+                }
+                let text = lines[start..index]
+                    .iter()
+                    .map(|line| line.trim())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                out.push_str(&format!("<p>{}</p>\n", self.inline(&text)));
+            }
+        }
+    }
+
+    /// Renders the list starting at `lines[start]` and returns the index
+    /// of the line after it.
+    fn list(&self, lines: &[&str], start: usize, out: &mut String) -> usize {
+        let first = LIST_ITEM.captures(lines[start]).unwrap();
+        let indent = first[1].len();
+        let ordered = first[2].ends_with(['.', ')']);
+        let tag = if ordered { "ol" } else { "ul" };
+        let number: usize = first[2].trim_end_matches(['.', ')']).parse().unwrap_or(1);
+        if ordered && number != 1 {
+            out.push_str(&format!("<ol start=\"{number}\">\n"));
+        } else {
+            out.push_str(&format!("<{tag}>\n"));
+        }
+
+        let mut index = start;
+        let mut items: Vec<Vec<String>> = vec![];
+        let mut loose = false;
+        while index < lines.len() {
+            let line = lines[index];
+            let item = LIST_ITEM
+                .captures(line)
+                .filter(|captures| captures[1].len() == indent)
+                .filter(|captures| captures[2].ends_with(['.', ')']) == ordered);
+            if let Some(captures) = item {
+                let content_indent = captures.get(3).map_or(line.len(), |text| text.start());
+                items.push(vec![
+                    captures.get(3).map_or("", |text| text.as_str()).to_string(),
+                ]);
+                index += 1;
+                // Continuation lines: indented ones, blank lines followed
+                // by indented ones, and lazy paragraph lines.
+                while index < lines.len() {
+                    let line = lines[index];
+                    let current = items.last_mut().unwrap();
+                    if line.trim().is_empty() {
+                        let next_is_inside = lines.get(index + 1).is_some_and(|next| {
+                            leading_spaces(next) > indent && !next.trim().is_empty()
+                        });
+                        if !next_is_inside {
+                            break;
+                        }
+                        loose = loose || !LIST_ITEM.is_match(lines[index + 1]);
+                        current.push(String::new());
+                    } else if leading_spaces(line) > indent {
+                        let strip = leading_spaces(line).min(content_indent);
+                        current.push(line[strip..].to_string());
+                    } else if !starts_block(line) && !LIST_ITEM.is_match(line) {
+                        current.push(line.trim().to_string());
+                    } else {
+                        break;
+                    }
+                    index += 1;
+                }
+            } else if line.trim().is_empty()
+                && lines.get(index + 1).is_some_and(|next| {
+                    LIST_ITEM
+                        .captures(next)
+                        .is_some_and(|captures| captures[1].len() == indent)
+                })
+            {
+                loose = true;
+                index += 1;
+            } else {
+                break;
+            }
+        }
+
+        for item in items {
+            let item: Vec<&str> = item.iter().map(String::as_str).collect();
+            let mut body = String::new();
+            self.blocks(&item, &mut body);
+            if !loose {
+                body = tighten(&body);
+            }
+            out.push_str(&format!("<li>{}</li>\n", body.trim_end()));
+        }
+        out.push_str(&format!("</{tag}>\n"));
+        index
+    }
+
+    fn table(&self, lines: &[&str], start: usize, out: &mut String) -> usize {
+        let alignments: Vec<&str> = cells(lines[start + 1])
+            .iter()
+            .map(|cell| match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => " style=\"text-align: center\"",
+                (false, true) => " style=\"text-align: right\"",
+                (true, false) => " style=\"text-align: left\"",
+                (false, false) => "",
+            })
+            .collect();
+        let row = |line: &str, tag: &str| {
+            let mut html = String::from("<tr>");
+            for (column, cell) in cells(line).iter().enumerate() {
+                let align = alignments.get(column).copied().unwrap_or_default();
+                html.push_str(&format!("<{tag}{align}>{}</{tag}>", self.inline(cell)));
+            }
+            html.push_str("</tr>\n");
+            html
+        };
+        out.push_str("<table>\n<thead>\n");
+        out.push_str(&row(lines[start], "th"));
+        out.push_str("</thead>\n<tbody>\n");
+        let mut index = start + 2;
+        while index < lines.len() && lines[index].contains('|') && !lines[index].trim().is_empty() {
+            out.push_str(&row(lines[index], "td"));
+            index += 1;
+        }
+        out.push_str("</tbody>\n</table>\n");
+        index
+    }
+
+    /// Renders inline markup. Code spans, raw HTML, links and images are
+    /// swapped for placeholders first, so that the emphasis rules and
+    /// escaping don't touch them.
+    fn inline(&self, text: &str) -> String {
+        // NUL delimits the placeholders; CommonMark has it replaced anyway.
+        let text = text.replace('\0', "\u{FFFD}");
+        let mut pieces: Vec<String> = vec![];
+        let mut stash = |html: String| {
+            pieces.push(html);
+            format!("\u{0}{}\u{0}", pieces.len() - 1)
+        };
+
+        let text = CODE_SPAN.replace_all(&text, |captures: &Captures| {
+            if captures[1].len() != captures[3].len() {
+                return captures[0].to_string();
+            }
+            stash(format!("<code>{}</code>", escape(captures[2].trim())))
+        });
+        let text = AUTOLINK.replace_all(&text, |captures: &Captures| {
+            let url = escape(&captures[1]);
+            stash(format!("<a href=\"{url}\">{url}</a>"))
+        });
+        let text =
+            INLINE_HTML.replace_all(&text, |captures: &Captures| stash(captures[0].to_string()));
+        let text = IMAGE.replace_all(&text, |captures: &Captures| {
+            let src = self.url(&captures[2]);
+            let title = captures
+                .get(3)
+                .map(|title| format!(" title=\"{}\"", escape(title.as_str())))
+                .unwrap_or_default();
+            stash(format!(
+                "<img src=\"{src}\" alt=\"{}\"{title}>",
+                escape(&captures[1])
+            ))
+        });
+        let text = LINK.replace_all(&text, |captures: &Captures| {
+            let href = self.url(&captures[2]);
+            let title = captures
+                .get(3)
+                .map(|title| format!(" title=\"{}\"", escape(title.as_str())))
+                .unwrap_or_default();
+            stash(format!("<a href=\"{href}\"{title}>")) + &captures[1] + &stash("</a>".to_string())
+        });
+
+        let mut html = escape(&text);
+        html = STRONG
+            .replace_all(&html, |captures: &Captures| {
+                let inner = captures.get(1).or(captures.get(2)).unwrap().as_str();
+                format!("<strong>{inner}</strong>")
+            })
+            .into_owned();
+        html = EMPHASIS
+            .replace_all(&html, |captures: &Captures| {
+                let inner = captures.get(1).or(captures.get(2)).unwrap().as_str();
+                format!("<em>{inner}</em>")
+            })
+            .into_owned();
+        html = STRIKETHROUGH
+            .replace_all(&html, "<del>$1</del>")
+            .into_owned();
+        html = html.replace("  \n", "<br>\n").replace("\\\n", "<br>\n");
+
+        restore(&html, &pieces)
+    }
+
+    fn url(&self, url: &str) -> String {
+        escape(&(self.resolve)(url).unwrap_or_else(|| url.to_string()))
+    }
+}
+
+/// Puts the stashed pieces back in place of their placeholders. Pieces can
+/// nest (raw HTML holding code), but only hold placeholders of the pieces
+/// stashed before them, which keeps this from looping.
+fn restore(text: &str, pieces: &[String]) -> String {
+    PLACEHOLDER
+        .replace_all(text, |captures: &Captures| {
+            let index = captures[1].parse().unwrap_or(usize::MAX);
+            match pieces.get(index) {
+                Some(piece) => restore(piece, &pieces[..index]),
+                None => captures[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Whether `line` interrupts a paragraph.
+fn starts_block(line: &str) -> bool {
+    line.trim().is_empty()
+        || HEADING.is_match(line)
+        || fence_open(line).is_some()
+        || RULE.is_match(line)
+        || line.trim_start().starts_with('>')
+        || line.trim_start().starts_with("$$")
+        || LIST_ITEM
+            .captures(line)
+            .is_some_and(|captures| captures.get(3).is_some())
+        || HTML_BLOCK.is_match(line)
+}
+
+fn fence_open(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+    let info = trimmed[len..].trim();
+    (len >= 3 && !(marker == '`' && info.contains('`')))
+        .then(|| (marker.to_string().repeat(len), info))
+}
+
+fn is_fence_close(line: &str, fence: &str) -> bool {
+    let line = line.trim();
+    line.starts_with(fence) && line.chars().all(|c| fence.starts_with(c))
+}
+
+/// The index after a `$$` math block starting at `lines[start]`.
+fn math_block_end(lines: &[&str], start: usize) -> usize {
+    let first = lines[start].trim();
+    if first.len() > 2 && first.ends_with("$$") {
+        return start + 1;
+    }
+    lines[start + 1..]
+        .iter()
+        .position(|line| line.trim_end().ends_with("$$"))
+        .map_or(lines.len(), |offset| start + offset + 2)
+}
+
+fn cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(str::trim).collect()
+}
+
+fn leading_spaces(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+/// Unwraps the paragraphs of a tight list item.
+fn tighten(html: &str) -> String {
+    html.replace("<p>", "").replace("</p>", "")
+}
+
+/// The anchor id for a heading, as GitHub and Jupyter make them.
+fn slug(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            c if c.is_alphanumeric() || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,14 @@
+//! Exporters that turn notebooks into documents for people who don't run
+//! Jupyter.
+
+pub mod ansi;
+mod highlight;
+pub mod html;
+pub mod markdown;
+mod markdown_html;
+pub mod script;
+pub mod text;
+
+pub use html::HtmlOptions;
+pub use markdown::MarkdownDocument;
+pub use text::TextOptions;
@@ -0,0 +1,97 @@
+//! Export of notebooks to a plain script in the kernel's language.
+//!
+//! Code cells are written as they are, separated by blank lines, and
+//! markdown cells become comments. Unlike the paired py:percent format,
+//! the script carries no cell markers and is not meant to be read back.
+
+use crate::notebook_util::types::*;
+use anyhow::Result;
+use std::path::Path;
+
+impl LanguageInfo {
+    /// The line comment marker of the language, `#` unless the name or file
+    /// extension says otherwise.
+    pub fn line_comment(&self) -> &'static str {
+        let extension = self.file_extension.as_deref().unwrap_or_default();
+        match (self.name.to_ascii_lowercase().as_str(), extension) {
+            (
+                "c" | "c++" | "cpp" | "csharp" | "c#" | "go" | "java" | "javascript" | "kotlin"
+                | "rust" | "scala" | "swift" | "typescript",
+                _,
+            )
+            | (_, ".c" | ".cpp" | ".cs" | ".go" | ".java" | ".js" | ".kt" | ".rs" | ".scala")
+            | (_, ".swift" | ".ts") => "//",
+            ("haskell" | "lua" | "sql", _) | (_, ".hs" | ".lua" | ".sql") => "--",
+            ("matlab" | "octave" | "erlang", _) | (_, ".m" | ".erl") => "%",
+            ("clojure" | "common-lisp" | "scheme", _) | (_, ".clj" | ".lisp" | ".scm") => ";;",
+            _ => "#",
+        }
+    }
+}
+
+impl Notebook {
+    /// Renders the notebook as a script in its kernel's language, with
+    /// markdown cells as comments. Raw cells are left out, and in Python
+    /// notebooks IPython magics are commented out so the script runs.
+    pub fn to_script(&self) -> String {
+        let comment = self
+            .metadata
+            .language_info
+            .as_ref()
+            .map_or("#", LanguageInfo::line_comment);
+        let python = self.language().is_none_or(|language| language == "python");
+
+        let mut blocks = vec![];
+        for cell in &self.cells {
+            let source = cell.source().as_str().trim_end();
+            let block: Vec<String> = match cell {
+                Cell::Code(_) => source
+                    .lines()
+                    .map(|line| {
+                        let trimmed = line.trim_start();
+                        if python && (trimmed.starts_with('%') || trimmed.starts_with('!')) {
+                            format!("{comment} {line}")
+                        } else {
+                            line.to_string()
+                        }
+                    })
+                    .collect(),
+                Cell::Markdown(_) => source
+                    .lines()
+                    .map(|line| match line {
+                        "" => comment.to_string(),
+                        line => format!("{comment} {line}"),
+                    })
+                    .collect(),
+                Cell::Raw(_) => continue,
+            };
+            if !block.is_empty() {
+                blocks.push(block.join("\n"));
+            }
+        }
+
+        let mut script = String::new();
+        if python {
+            script.push_str("#!/usr/bin/env python\n\n");
+        }
+        script.push_str(&blocks.join("\n\n"));
+        script.push('\n');
+        script
+    }
+
+    /// The file extension for [`Notebook::to_script`], with the dot, as
+    /// recorded by the kernel; `.py` if unknown.
+    pub fn script_extension(&self) -> &str {
+        self.metadata
+            .language_info
+            .as_ref()
+            .and_then(|info| info.file_extension.as_deref())
+            .unwrap_or(".py")
+    }
+
+    /// Writes the notebook as a script to `path`, see
+    /// [`Notebook::to_script`].
+    pub fn export_script<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        crate::notebook_util::impls::write_file(path.as_ref(), &self.to_script())
+    }
+}
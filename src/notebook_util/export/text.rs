@@ -0,0 +1,123 @@
+//! Rendering of notebooks as plain text for a terminal, e.g. for
+//! `nbterm cat`.
+//!
+//! Code cells get IPython-style `In [n]:` and `Out[n]:` prompts, markdown
+//! and raw cells are shown as they are, and outputs as text; outputs with
+//! no text representation are shown as a placeholder naming their type.
+
+use super::ansi;
+use crate::notebook_util::mime::MimeBundle;
+use crate::notebook_util::types::*;
+
+/// Settings for [`Notebook::to_text`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextOptions {
+    /// Color the prompts and keep the ANSI colors of outputs; without it,
+    /// the text has no escape sequences at all.
+    pub color: bool,
+    /// Leave out the outputs of code cells.
+    pub hide_outputs: bool,
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+impl Notebook {
+    /// Renders the notebook as text, with cells separated by blank lines.
+    pub fn to_text(&self, options: &TextOptions) -> String {
+        let mut blocks = vec![];
+        for cell in &self.cells {
+            let source = cell.source().as_str().trim_end();
+            match cell {
+                Cell::Markdown(_) => blocks.push(source.to_string()),
+                Cell::Raw(_) => blocks.push(paint(options, DIM, source)),
+                Cell::Code(code) => {
+                    let count = prompt_number(code.execution_count);
+                    blocks.push(format!(
+                        "{}\n{}",
+                        paint(options, GREEN, &format!("In [{count}]:")),
+                        indent(source)
+                    ));
+                    if options.hide_outputs {
+                        continue;
+                    }
+                    for output in &code.outputs {
+                        blocks.push(output.to_text(options).trim_end().to_string());
+                    }
+                }
+            }
+        }
+        blocks.retain(|block| !block.is_empty());
+        let mut text = blocks.join("\n\n");
+        text.push('\n');
+        text
+    }
+}
+
+impl Output {
+    /// Renders the output as text, the way [`Notebook::to_text`] shows it.
+    pub fn to_text(&self, options: &TextOptions) -> String {
+        match self {
+            Output::Stream { text, .. } => plain(options, &text.collapse_carriage_returns()),
+            Output::Error { traceback, .. } => plain(options, &traceback.join("\n")),
+            Output::ExecuteResult {
+                execution_count,
+                data,
+                ..
+            } => format!(
+                "{}\n{}",
+                paint(
+                    options,
+                    RED,
+                    &format!("Out[{}]:", prompt_number(*execution_count))
+                ),
+                indent(&plain(options, &bundle_text(data)))
+            ),
+            Output::DisplayData { data, .. } => plain(options, &bundle_text(data)),
+        }
+    }
+}
+
+fn paint(options: &TextOptions, color: &str, text: &str) -> String {
+    if options.color {
+        format!("{color}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+fn plain(options: &TextOptions, text: &str) -> String {
+    if options.color {
+        text.to_string()
+    } else {
+        ansi::strip(text)
+    }
+}
+
+fn prompt_number(execution_count: Option<u32>) -> String {
+    execution_count.map_or(" ".to_string(), |count| count.to_string())
+}
+
+/// The text of a bundle: its Markdown or plain text, or a placeholder such
+/// as `[image/png]` naming its type.
+fn bundle_text(data: &MimeBundle) -> String {
+    match data.markdown().or(data.plain()) {
+        Some(text) => text.to_string(),
+        None => data
+            .mime_types()
+            .next()
+            .map_or(String::new(), |mime| format!("[{mime}]")),
+    }
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| match line {
+            "" => String::new(),
+            line => format!("    {line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,231 @@
+//! Pluggable on-disk formats for notebooks.
+//!
+//! A [`NotebookFormat`] reads and writes notebooks as text, and can tell
+//! whether some text looks like its format. The [`FormatRegistry`] picks
+//! the format for a file from its extension, sniffing the content when
+//! several formats share an extension (as plain and MyST Markdown do) or
+//! none is registered for it.
+
+use super::impls::write_file;
+use super::lazy::DEFAULT_LAZY_THRESHOLD;
+use super::markdown::MarkdownFlavor;
+use super::types::Notebook;
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A way of storing notebooks in files.
+pub trait NotebookFormat: Send + Sync {
+    /// Short name used to pick the format by hand, e.g. `ipynb`.
+    fn name(&self) -> &'static str;
+
+    /// File extensions, without the dot, the format is used for.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// Returns `true` if `content` looks like this format.
+    fn detect(&self, content: &str) -> bool;
+
+    fn read(&self, content: &str) -> Result<Notebook>;
+
+    fn write(&self, notebook: &Notebook) -> Result<String>;
+
+    /// Loads a notebook from `path`. Formats can override this to read
+    /// files in a smarter way than whole.
+    fn read_file(&self, path: &Path) -> Result<Notebook> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        self.read(&content)
+    }
+
+    /// Saves a notebook to `path`, creating the directory if needed.
+    fn write_file(&self, notebook: &Notebook, path: &Path) -> Result<()> {
+        write_file(path, &self.write(notebook)?)
+    }
+}
+
+/// Jupyter's JSON format.
+pub struct Ipynb;
+
+impl NotebookFormat for Ipynb {
+    fn name(&self) -> &'static str {
+        "ipynb"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ipynb"]
+    }
+
+    fn detect(&self, content: &str) -> bool {
+        content.trim_start().starts_with('{')
+    }
+
+    fn read(&self, content: &str) -> Result<Notebook> {
+        Notebook::from_str(content)
+    }
+
+    fn write(&self, notebook: &Notebook) -> Result<String> {
+        notebook.save_to_str()
+    }
+
+    /// Leaves large outputs in the file, see [`Notebook::from_file_lazy`].
+    fn read_file(&self, path: &Path) -> Result<Notebook> {
+        Notebook::from_file_lazy(path, DEFAULT_LAZY_THRESHOLD)
+    }
+}
+
+/// Jupytext's py:percent scripts, see [`super::percent`].
+pub struct PercentScript;
+
+impl NotebookFormat for PercentScript {
+    fn name(&self) -> &'static str {
+        "py:percent"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["py"]
+    }
+
+    fn detect(&self, content: &str) -> bool {
+        content
+            .lines()
+            .any(|line| line.starts_with("# %%") || line.starts_with("#%%"))
+    }
+
+    fn read(&self, content: &str) -> Result<Notebook> {
+        Ok(Notebook::from_percent_str(content))
+    }
+
+    fn write(&self, notebook: &Notebook) -> Result<String> {
+        Ok(notebook.to_percent_string())
+    }
+}
+
+/// Plain or MyST Markdown, see [`super::markdown`].
+pub struct Markdown(pub MarkdownFlavor);
+
+impl NotebookFormat for Markdown {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            MarkdownFlavor::Plain => "md",
+            MarkdownFlavor::MyST => "myst",
+        }
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        match self.0 {
+            MarkdownFlavor::Plain => &["md", "markdown"],
+            MarkdownFlavor::MyST => &["md", "myst", "mystnb"],
+        }
+    }
+
+    /// MyST documents are told apart by their cell directives or front
+    /// matter; any other document with front matter or a code fence is
+    /// taken as plain Markdown.
+    fn detect(&self, content: &str) -> bool {
+        let flavor = MarkdownFlavor::detect(Path::new(""), content);
+        match self.0 {
+            MarkdownFlavor::MyST => flavor == MarkdownFlavor::MyST,
+            MarkdownFlavor::Plain => {
+                flavor == MarkdownFlavor::Plain
+                    && (content.starts_with("---\n")
+                        || content.lines().any(|line| line.starts_with("```")))
+            }
+        }
+    }
+
+    fn read(&self, content: &str) -> Result<Notebook> {
+        Ok(Notebook::from_markdown_str(content, self.0))
+    }
+
+    fn write(&self, notebook: &Notebook) -> Result<String> {
+        Ok(notebook.to_markdown_string(self.0))
+    }
+}
+
+/// The formats nbterm knows about.
+///
+/// Formats registered later take precedence over earlier ones for the same
+/// extension.
+#[derive(Clone)]
+pub struct FormatRegistry {
+    formats: Vec<Arc<dyn NotebookFormat>>,
+}
+
+impl Default for FormatRegistry {
+    /// The built-in formats: `.ipynb`, py:percent scripts, and plain and
+    /// MyST Markdown.
+    fn default() -> Self {
+        let mut registry = Self { formats: vec![] };
+        registry.register(Arc::new(Ipynb));
+        registry.register(Arc::new(PercentScript));
+        // Plain Markdown goes last, so that it is the fallback for `.md`
+        // files that look like neither flavor in particular.
+        registry.register(Arc::new(Markdown(MarkdownFlavor::MyST)));
+        registry.register(Arc::new(Markdown(MarkdownFlavor::Plain)));
+        registry
+    }
+}
+
+impl FormatRegistry {
+    pub fn register(&mut self, format: Arc<dyn NotebookFormat>) {
+        self.formats.insert(0, format);
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &Arc<dyn NotebookFormat>> {
+        self.formats.iter()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<Arc<dyn NotebookFormat>> {
+        self.formats
+            .iter()
+            .find(|format| format.name() == name)
+            .cloned()
+    }
+
+    /// The format for `path`: among the formats registered for its
+    /// extension, the first that detects `content`, or the first at all if
+    /// the content is unknown or none does. Without a format for the
+    /// extension, the content alone decides.
+    pub fn for_path(&self, path: &Path, content: Option<&str>) -> Option<Arc<dyn NotebookFormat>> {
+        let candidates = self.for_extension(path);
+        let pool = if candidates.is_empty() {
+            self.formats.iter().collect()
+        } else {
+            candidates.clone()
+        };
+        content
+            .and_then(|content| pool.into_iter().find(|format| format.detect(content)))
+            .or(candidates.first().copied())
+            .cloned()
+    }
+
+    /// Loads the notebook at `path`, returning it with the format it was
+    /// read as.
+    ///
+    /// The file is only read up front when its extension doesn't settle
+    /// the format, so that formats reading files their own way (such as
+    /// lazily loaded `.ipynb`) still can.
+    pub fn read_file(&self, path: &Path) -> Result<(Notebook, Arc<dyn NotebookFormat>)> {
+        if let [format] = self.for_extension(path).as_slice() {
+            return Ok((format.read_file(path)?, Arc::clone(format)));
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let Some(format) = self.for_path(path, Some(&content)) else {
+            bail!("Unknown notebook format: {}", path.display());
+        };
+        Ok((format.read(&content)?, format))
+    }
+
+    fn for_extension(&self, path: &Path) -> Vec<&Arc<dyn NotebookFormat>> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        self.formats
+            .iter()
+            .filter(|format| format.extensions().contains(&extension))
+            .collect()
+    }
+}
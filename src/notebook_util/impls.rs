@@ -1,4 +1,6 @@
+use super::mime::MimeBundle;
 use super::types::*;
+use std::collections::{BTreeMap, HashSet};
 use std::slice::{Iter, IterMut};
 
 impl Notebook {
@@ -6,6 +8,8 @@ impl Notebook {
     ///
     /// This allows iterating over cells in read-only mode:
     /// ```rust
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// for cell in notebook.iter() {
     ///     // inspect cell
     /// }
@@ -18,6 +22,8 @@ impl Notebook {
     ///
     /// Allows modifying each cell during iteration:
     /// ```rust
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// for cell in notebook.iter_mut() {
     ///     // modify cell
     /// }
@@ -44,6 +50,8 @@ impl Notebook {
     ///
     /// This filters and yields only cells of type `Cell::Code`:
     /// ```rust
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// for code_cell in notebook.code_cells() {
     ///     println!("{:?}", code_cell.source);
     /// }
@@ -55,6 +63,21 @@ impl Notebook {
         })
     }
 
+    /// Returns the programming language of the notebook's kernel, from
+    /// `language_info` or else the kernelspec.
+    pub fn language(&self) -> Option<&str> {
+        self.metadata
+            .language_info
+            .as_ref()
+            .map(|info| info.name.as_str())
+            .or_else(|| {
+                self.metadata
+                    .kernelspec
+                    .as_ref()
+                    .and_then(|kernelspec| kernelspec.language.as_deref())
+            })
+    }
+
     /// Returns an iterator over all markdown cells in the notebook.
     ///
     /// This filters and yields only cells of type `Cell::Markdown`.
@@ -94,7 +117,7 @@ impl<'a> IntoIterator for &'a mut Notebook {
     }
 }
 
-use serde_json::json;
+use serde_json::{Value, json};
 
 impl Notebook {
     // === Internal cell handling ===
@@ -140,12 +163,17 @@ impl Notebook {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// notebook.insert_markdown_cell(0, vec!["# Heading", "Some description"]);
     /// ```
     pub fn insert_markdown_cell<S: Into<String>>(&mut self, index: usize, source: Vec<S>) -> bool {
         let cell = Cell::Markdown(MarkdownCell {
+            id: Some(self.new_cell_id()),
             source: source.into_iter().map(Into::into).collect(),
             metadata: json!({}),
+            attachments: None,
+            other: json!({}),
         });
         self.insert_cell(index, cell)
     }
@@ -161,12 +189,17 @@ impl Notebook {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// notebook.insert_raw_cell(2, vec!["Raw content"]);
     /// ```
     pub fn insert_raw_cell<S: Into<String>>(&mut self, index: usize, source: Vec<S>) -> bool {
         let cell = Cell::Raw(RawCell {
+            id: Some(self.new_cell_id()),
             source: source.into_iter().map(Into::into).collect(),
             metadata: json!({}),
+            attachments: None,
+            other: json!({}),
         });
         self.insert_cell(index, cell)
     }
@@ -178,12 +211,17 @@ impl Notebook {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// notebook.push_markdown_cell(vec!["## Section", "Details follow here..."]);
     /// ```
     pub fn push_markdown_cell<S: Into<String>>(&mut self, source: Vec<S>) {
         let cell = Cell::Markdown(MarkdownCell {
+            id: Some(self.new_cell_id()),
             source: source.into_iter().map(Into::into).collect(),
             metadata: json!({}),
+            attachments: None,
+            other: json!({}),
         });
         self.push_cell(cell);
     }
@@ -195,12 +233,17 @@ impl Notebook {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// notebook.push_raw_cell(vec!["Unformatted text"]);
     /// ```
     pub fn push_raw_cell<S: Into<String>>(&mut self, source: Vec<S>) {
         let cell = Cell::Raw(RawCell {
+            id: Some(self.new_cell_id()),
             source: source.into_iter().map(Into::into).collect(),
             metadata: json!({}),
+            attachments: None,
+            other: json!({}),
         });
         self.push_cell(cell);
     }
@@ -220,6 +263,8 @@ impl Notebook {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// notebook.insert_code_cell(0, vec!["print(\"Hello\")"], Some(1), vec![]);
     /// ```
     pub fn insert_code_cell<S: Into<String>>(
@@ -230,10 +275,12 @@ impl Notebook {
         outputs: Vec<Output>,
     ) -> bool {
         let cell = Cell::Code(CodeCell {
+            id: Some(self.new_cell_id()),
             source: source.into_iter().map(Into::into).collect(),
             metadata: json!({}),
             execution_count,
             outputs,
+            other: json!({}),
         });
         self.insert_cell(index, cell)
     }
@@ -247,6 +294,8 @@ impl Notebook {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
+    /// # let mut notebook = Notebook::default();
     /// notebook.push_code_cell(vec!["a = 1 + 2"], Some(1), vec![]);
     /// ```
     pub fn push_code_cell<S: Into<String>>(
@@ -256,26 +305,208 @@ impl Notebook {
         outputs: Vec<Output>,
     ) {
         let cell = Cell::Code(CodeCell {
+            id: Some(self.new_cell_id()),
             source: source.into_iter().map(Into::into).collect(),
             metadata: json!({}),
             execution_count,
             outputs,
+            other: json!({}),
         });
         self.push_cell(cell);
     }
 }
 
+impl Cell {
+    /// Creates a cell without id, outputs or attachments, as read from text
+    /// formats that don't store them.
+    pub(crate) fn from_parts(
+        cell_type: CellType,
+        source: MultilineString,
+        metadata: Value,
+    ) -> Self {
+        match cell_type {
+            CellType::Code => Cell::Code(CodeCell {
+                id: None,
+                source,
+                metadata,
+                execution_count: None,
+                outputs: vec![],
+                other: json!({}),
+            }),
+            CellType::Markdown => Cell::Markdown(MarkdownCell {
+                id: None,
+                source,
+                metadata,
+                attachments: None,
+                other: json!({}),
+            }),
+            CellType::Raw => Cell::Raw(RawCell {
+                id: None,
+                source,
+                metadata,
+                attachments: None,
+                other: json!({}),
+            }),
+        }
+    }
+
+    /// Returns the cell's id, if it has one.
+    ///
+    /// Cells loaded from notebooks older than nbformat 4.5 have no id until
+    /// [`Notebook::ensure_cell_ids`] is called.
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Cell::Code(cell) => cell.id.as_deref(),
+            Cell::Markdown(cell) => cell.id.as_deref(),
+            Cell::Raw(cell) => cell.id.as_deref(),
+        }
+    }
+
+    /// Returns the kind of the cell.
+    pub fn cell_type(&self) -> CellType {
+        match self {
+            Cell::Code(_) => CellType::Code,
+            Cell::Markdown(_) => CellType::Markdown,
+            Cell::Raw(_) => CellType::Raw,
+        }
+    }
+
+    /// Returns the cell's source text.
+    pub fn source(&self) -> &MultilineString {
+        match self {
+            Cell::Code(cell) => &cell.source,
+            Cell::Markdown(cell) => &cell.source,
+            Cell::Raw(cell) => &cell.source,
+        }
+    }
+
+    /// Returns a mutable reference to the cell's source text.
+    pub fn source_mut(&mut self) -> &mut MultilineString {
+        match self {
+            Cell::Code(cell) => &mut cell.source,
+            Cell::Markdown(cell) => &mut cell.source,
+            Cell::Raw(cell) => &mut cell.source,
+        }
+    }
+
+    /// Returns the cell-level metadata.
+    pub fn metadata(&self) -> &serde_json::Value {
+        match self {
+            Cell::Code(cell) => &cell.metadata,
+            Cell::Markdown(cell) => &cell.metadata,
+            Cell::Raw(cell) => &cell.metadata,
+        }
+    }
+
+    /// Returns a mutable reference to the cell-level metadata.
+    pub fn metadata_mut(&mut self) -> &mut serde_json::Value {
+        match self {
+            Cell::Code(cell) => &mut cell.metadata,
+            Cell::Markdown(cell) => &mut cell.metadata,
+            Cell::Raw(cell) => &mut cell.metadata,
+        }
+    }
+
+    /// Returns the cell's attachments. Code cells have none.
+    pub fn attachments(&self) -> Option<&BTreeMap<String, MimeBundle>> {
+        match self {
+            Cell::Code(_) => None,
+            Cell::Markdown(cell) => cell.attachments.as_ref(),
+            Cell::Raw(cell) => cell.attachments.as_ref(),
+        }
+    }
+
+    /// Returns a mutable reference to the cell's attachments slot, or
+    /// `None` for a code cell, which can't have any.
+    pub(crate) fn attachments_mut(&mut self) -> Option<&mut Option<BTreeMap<String, MimeBundle>>> {
+        match self {
+            Cell::Code(_) => None,
+            Cell::Markdown(cell) => Some(&mut cell.attachments),
+            Cell::Raw(cell) => Some(&mut cell.attachments),
+        }
+    }
+
+    /// Returns a mutable reference to the cell's id slot.
+    pub(crate) fn id_mut(&mut self) -> &mut Option<String> {
+        match self {
+            Cell::Code(cell) => &mut cell.id,
+            Cell::Markdown(cell) => &mut cell.id,
+            Cell::Raw(cell) => &mut cell.id,
+        }
+    }
+}
+
+impl std::fmt::Display for CellType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CellType::Code => "code",
+            CellType::Markdown => "markdown",
+            CellType::Raw => "raw",
+        })
+    }
+}
+
+impl Notebook {
+    // === Cell ids ===
+
+    /// Generates a fresh cell id that is not used by any cell in the notebook.
+    ///
+    /// Ids follow the format used by Jupyter itself: the first 8 hex digits of
+    /// a random UUIDv4, which satisfies the nbformat 4.5 id pattern
+    /// `^[a-zA-Z0-9-_]+$`.
+    pub fn new_cell_id(&self) -> String {
+        Self::new_cell_id_avoiding(&self.cell_ids())
+    }
+
+    /// The ids of the notebook's cells.
+    pub(crate) fn cell_ids(&self) -> HashSet<String> {
+        self.cells
+            .iter()
+            .filter_map(|cell| cell.id().map(str::to_string))
+            .collect()
+    }
+
+    /// A fresh cell id that is not in `taken`, for callers that give ids to
+    /// several cells and keep the set of used ones up to date.
+    pub(crate) fn new_cell_id_avoiding(taken: &HashSet<String>) -> String {
+        loop {
+            let mut id = uuid::Uuid::new_v4().simple().to_string();
+            id.truncate(8);
+            if !taken.contains(&id) {
+                return id;
+            }
+        }
+    }
+
+    /// Assigns a fresh id to every cell that lacks one.
+    ///
+    /// Notebooks older than nbformat 4.5 have no cell ids in their file;
+    /// they get them here when they are upgraded on load.
+    pub fn ensure_cell_ids(&mut self) {
+        let mut taken = self.cell_ids();
+        for cell in &mut self.cells {
+            if cell.id().is_none() {
+                let id = Self::new_cell_id_avoiding(&taken);
+                taken.insert(id.clone());
+                *cell.id_mut() = Some(id);
+            }
+        }
+    }
+}
+
 impl Output {
     /// Creates a stream output for stdout.
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
     /// let out = Output::stream_stdout("hello\n");
     /// ```
     pub fn stream_stdout<S: Into<String>>(text: S) -> Self {
         Output::Stream {
             name: "stdout".to_string(),
-            text: vec![text.into()],
+            text: text.into().into(),
+            other: json!({}),
         }
     }
 
@@ -283,7 +514,8 @@ impl Output {
     pub fn stream_stderr<S: Into<String>>(text: S) -> Self {
         Output::Stream {
             name: "stderr".to_string(),
-            text: vec![text.into()],
+            text: text.into().into(),
+            other: json!({}),
         }
     }
 
@@ -291,15 +523,15 @@ impl Output {
     ///
     /// # Example
     /// ```
+    /// # use nbterm::notebook_util::*;
     /// let out = Output::execute_result(1, "3");
     /// ```
     pub fn execute_result<S: Into<String>>(execution_count: u32, result: S) -> Self {
         Output::ExecuteResult {
-            execution_count,
-            data: serde_json::json!({
-                "text/plain": result.into()
-            }),
+            execution_count: Some(execution_count),
+            data: MimeBundle::text_plain(result),
             metadata: serde_json::json!({}),
+            other: json!({}),
         }
     }
 
@@ -309,10 +541,13 @@ impl Output {
             ename: ename.into(),
             evalue: evalue.into(),
             traceback: traceback.into_iter().map(Into::into).collect(),
+            other: json!({}),
         }
     }
 }
 
+use super::convert;
+use super::validation::{self, ValidationMode, Violation, Violations};
 use anyhow::{Context, Result}; // add `anyhow = "1"` to Cargo.toml
 use std::fs;
 use std::path::Path;
@@ -320,11 +555,86 @@ use std::path::Path;
 impl Notebook {
     /// Loads a notebook from a JSON string.
     ///
+    /// This is [`Notebook::from_str_with_mode`] in lenient mode: common schema
+    /// problems are repaired rather than rejected.
+    ///
     /// Returns an error if the input is not valid JSON or doesn't match the notebook schema.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
-        let notebook: Self =
-            serde_json::from_str(s).context("Failed to parse notebook from JSON string")?;
-        Ok(notebook)
+        Ok(Self::from_str_with_mode(s, ValidationMode::Lenient)?.0)
+    }
+
+    /// Loads a notebook from a JSON string, checking it against the nbformat
+    /// schema.
+    ///
+    /// In strict mode any schema violation fails the load; in lenient mode
+    /// the problems handled by [`validation::repair`] are fixed first, and
+    /// returned with the notebook. Either way, a notebook that cannot be
+    /// loaded reports all of its violations (as a [`Violations`] error)
+    /// instead of a single parse error.
+    ///
+    /// Notebooks in nbformat v3 are upgraded to the v4 layout first, see
+    /// [`convert::upgrade`]. Older notebooks become 4.5 in memory, with cell
+    /// ids, and remember their version in [`Notebook::original_version`].
+    pub fn from_str_with_mode(s: &str, mode: ValidationMode) -> Result<(Self, Vec<Violation>)> {
+        let value = serde_json::from_str(s).context("Failed to parse notebook from JSON string")?;
+        Self::from_value_with_mode(value, mode)
+    }
+
+    /// Upgrades, validates and deserializes parsed notebook JSON, see
+    /// [`Notebook::from_str_with_mode`].
+    pub(crate) fn from_value_with_mode(
+        mut value: serde_json::Value,
+        mode: ValidationMode,
+    ) -> Result<(Self, Vec<Violation>)> {
+        let version = convert::detect_version(&value);
+        convert::upgrade(&mut value)?;
+
+        let repaired = match mode {
+            ValidationMode::Strict => {
+                let violations = validation::validate(&value);
+                if !violations.is_empty() {
+                    return Err(Violations(violations).into());
+                }
+                vec![]
+            }
+            ValidationMode::Lenient => validation::repair(&mut value),
+        };
+
+        // a notebook that doesn't load is checked for what is wrong with it
+        let mut notebook: Self = match serde::Deserialize::deserialize(&value) {
+            Ok(notebook) => notebook,
+            Err(err) => {
+                let violations = validation::validate(&value);
+                if violations.is_empty() {
+                    return Err(err).context("Failed to parse notebook from JSON string");
+                }
+                return Err(anyhow::Error::new(Violations(violations)).context(err.to_string()));
+            }
+        };
+        if let Some((major, minor)) = version
+            && (major, minor) < (NBFORMAT.into(), NBFORMAT_MINOR.into())
+        {
+            notebook.original_version = Some((major as u8, minor as u8));
+            notebook.nbformat = NBFORMAT;
+            notebook.nbformat_minor = NBFORMAT_MINOR;
+        }
+        notebook.ensure_cell_ids();
+        Ok((notebook, repaired))
+    }
+
+    /// Serializes the notebook to JSON as it is saved, without the marks
+    /// the upgrade from v3 leaves in memory.
+    pub(crate) fn to_saved_value(&self) -> Result<Value> {
+        let mut value = serde_json::to_value(self).context("Failed to serialize notebook")?;
+        convert::clear_upgrade_marks(&mut value);
+        Ok(value)
+    }
+
+    /// Checks the notebook against the nbformat schema and returns every
+    /// violation found.
+    pub fn validate(&self) -> Result<Vec<Violation>> {
+        Ok(validation::validate(&self.to_saved_value()?))
     }
 
     /// Loads a notebook from a `.ipynb` file.
@@ -336,34 +646,72 @@ impl Notebook {
         Self::from_str(&content)
     }
 
-    /// Serializes the notebook to a pretty JSON string.
+    /// Serializes the notebook to a JSON string laid out like Jupyter's own
+    /// writer: sorted keys, 1-space indent and a trailing newline.
+    ///
+    /// The notebook is written in its in-memory version, 4.5, whatever
+    /// version it was loaded from; see [`Notebook::save_to_str_as_version`].
     ///
     /// Returns an error if serialization fails.
     pub fn save_to_str(&self) -> Result<String> {
-        let json =
-            serde_json::to_string_pretty(self).context("Failed to serialize notebook to string")?;
-        Ok(json)
+        self.to_nbformat_string()
     }
 
-    /// Saves the notebook to a `.ipynb` file with pretty formatting.
+    /// Serializes the notebook like [`Notebook::save_to_str`], but downgraded
+    /// to an older nbformat version (4.0 to 4.4, or 3.0) for older Jupyter
+    /// installs, e.g. to [`Notebook::original_version`].
+    ///
+    /// Returns an error if the version is unsupported or serialization fails.
+    pub fn save_to_str_as_version(&self, nbformat: u8, nbformat_minor: u8) -> Result<String> {
+        let mut value =
+            serde_json::to_value(self).context("Failed to serialize notebook to string")?;
+        convert::downgrade(&mut value, nbformat, nbformat_minor)?;
+        Ok(super::writer::to_nbformat_string(&value))
+    }
+
+    /// Saves the notebook to a `.ipynb` file in Jupyter's on-disk format.
     ///
     /// If the directory does not exist, it will be created.
     ///
     /// Returns an error if writing fails.
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let path = path.as_ref();
+        write_file(path.as_ref(), &self.save_to_str()?)
+    }
 
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).with_context(|| {
-                format!("Failed to create directories for: {}", parent.display())
-            })?;
-        }
+    /// Saves the notebook to a `.ipynb` file downgraded to an older nbformat
+    /// version, see [`Notebook::save_to_str_as_version`].
+    pub fn save_to_file_as_version<P: AsRef<Path>>(
+        &self,
+        path: P,
+        nbformat: u8,
+        nbformat_minor: u8,
+    ) -> Result<()> {
+        write_file(
+            path.as_ref(),
+            &self.save_to_str_as_version(nbformat, nbformat_minor)?,
+        )
+    }
+}
 
-        // Serialize and write the notebook
-        let json = self.save_to_str()?;
-        fs::write(path, json)
-            .with_context(|| format!("Failed to write notebook to file: {}", path.display()))?;
-        Ok(())
+/// Writes serialized notebook content, or an export of it, to `path`,
+/// creating parent directories. The file is replaced at once, never left
+/// half written.
+pub fn write_file(path: &Path, content: &str) -> Result<()> {
+    // Ensure parent directory exists
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directories for: {}", parent.display()))?;
     }
+
+    // Write next to the target and rename over it, so that a failed write
+    // never leaves a truncated notebook behind.
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{file_name}.nbterm-tmp"));
+    fs::write(&temp, content)
+        .and_then(|()| fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+        .with_context(|| format!("Failed to write notebook to file: {}", path.display()))?;
+    Ok(())
 }
@@ -0,0 +1,497 @@
+//! Memory-efficient loading of notebooks with large outputs.
+//!
+//! [`Notebook::from_file_lazy`] memory-maps a private copy of the file and
+//! parses it in a single pass, keeping every output and attachment payload
+//! above a size threshold as a byte range into the mapping instead of a
+//! parsed value. Such payloads are parsed on first access through
+//! [`MimeBundle`], and saving writes them back unchanged, one at a time.
+//!
+//! [`MimeBundle`]: super::MimeBundle
+
+use super::mime;
+use super::types::*;
+use super::validation::ValidationMode;
+use super::writer;
+use anyhow::{Context, Result};
+use memmap2::Mmap;
+use serde::Deserialize as _;
+use serde::de::{DeserializeSeed, Deserializer, Error as _, MapAccess, SeqAccess, Visitor};
+use serde_json::value::RawValue;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+use std::ptr;
+use std::sync::{Arc, OnceLock};
+
+/// Payloads larger than this many bytes of JSON stay in the file by
+/// default; roughly a 48 KB image.
+pub const DEFAULT_LAZY_THRESHOLD: usize = 64 * 1024;
+
+/// A MIME value that is read from the notebook file when first needed.
+///
+/// Clones share the file mapping and the parsed value.
+#[derive(Clone)]
+pub struct LazyPayload {
+    source: Arc<Mmap>,
+    range: Range<usize>,
+    mime: String,
+    value: Arc<OnceLock<Value>>,
+}
+
+impl LazyPayload {
+    /// Size of the payload's JSON in the file, in bytes.
+    pub fn len(&self) -> usize {
+        self.range.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.range.is_empty()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.value.get().is_some()
+    }
+
+    /// Parses the payload on first call and keeps it in memory.
+    ///
+    /// A payload that can't be parsed reads as `null`, see
+    /// [`LazyPayload::try_value`].
+    pub fn value(&self) -> &Value {
+        self.try_value().unwrap_or(&Value::Null)
+    }
+
+    /// Parses the payload on first call and keeps it in memory.
+    ///
+    /// Returns an error if the payload is not valid JSON.
+    pub fn try_value(&self) -> Result<&Value> {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let value = self.parse()?;
+        Ok(self.value.get_or_init(|| value))
+    }
+
+    /// The payload, parsed just for this call unless it is already in
+    /// memory. Used when saving, so that a save does not keep every
+    /// payload around.
+    pub fn value_uncached(&self) -> Cow<'_, Value> {
+        self.try_value_uncached().unwrap_or(Cow::Owned(Value::Null))
+    }
+
+    fn try_value_uncached(&self) -> Result<Cow<'_, Value>> {
+        match self.value.get() {
+            Some(value) => Ok(Cow::Borrowed(value)),
+            None => self.parse().map(Cow::Owned),
+        }
+    }
+
+    /// Returns `true` if both refer to the same bytes of the same file.
+    pub(crate) fn same_source(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.source, &other.source) && self.range == other.range
+    }
+
+    fn parse(&self) -> Result<Value> {
+        let value = serde_json::from_slice(&self.source[self.range.clone()])
+            .with_context(|| format!("Failed to parse {} output", self.mime))?;
+        Ok(mime::normalize(&self.mime, value))
+    }
+}
+
+impl fmt::Debug for LazyPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyPayload")
+            .field("bytes", &self.len())
+            .field("loaded", &self.is_loaded())
+            .finish()
+    }
+}
+
+impl Notebook {
+    /// Loads a notebook from a `.ipynb` file, leaving output and attachment
+    /// payloads larger than `threshold` bytes in the file until they are
+    /// accessed.
+    ///
+    /// Cell sources, metadata and small outputs are loaded as with
+    /// [`Notebook::from_file`], and notebooks that need upgrading from
+    /// nbformat v3 are loaded fully. The payloads are read from a private
+    /// copy of the file, so the file itself may be changed or replaced
+    /// while the notebook is open.
+    pub fn from_file_lazy<P: AsRef<Path>>(path: P, threshold: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let source = Arc::new(
+            snapshot(file, path)
+                .with_context(|| format!("Failed to map file: {}", path.display()))?,
+        );
+
+        let mut scanner = Scanner {
+            base: source.as_ptr() as usize,
+            threshold,
+            cell: 0,
+            output: 0,
+            attachment: String::new(),
+            slots: Vec::new(),
+        };
+        let mut deserializer = serde_json::Deserializer::from_slice(&source);
+        let value = Level::Root
+            .seed(&mut scanner)
+            .deserialize(&mut deserializer)
+            .and_then(|value| deserializer.end().map(|()| value))
+            .context("Failed to parse notebook from JSON string")?;
+
+        let (mut notebook, _) = Self::from_value_with_mode(value, ValidationMode::Lenient)?;
+        for slot in scanner.slots {
+            let payload = LazyPayload {
+                source: Arc::clone(&source),
+                range: slot.range,
+                mime: slot.mime.clone(),
+                value: Arc::default(),
+            };
+            match notebook.bundle_mut(slot.cell, &slot.place) {
+                Some(bundle) => bundle.insert_lazy(slot.mime, payload),
+                // The placeholder ended up somewhere unexpected, e.g. a
+                // `data` key on a stream output. Rather than losing the
+                // payload there, load everything.
+                None => return Self::from_file(path),
+            }
+        }
+        Ok(notebook)
+    }
+
+    /// Renders the notebook for [`Notebook::save_to_str`]. Payloads still
+    /// in the file are parsed one at a time while they are written, rather
+    /// than all at once into the notebook's JSON.
+    pub(crate) fn to_nbformat_string(&self) -> Result<String> {
+        let unloaded = self.unloaded_payloads();
+        if unloaded.is_empty() {
+            return Ok(writer::to_nbformat_string(&self.to_saved_value()?));
+        }
+
+        let mut notebook = self.clone();
+        for (cell, place, mime, _) in &unloaded {
+            if let Some(bundle) = notebook.bundle_mut(*cell, place) {
+                bundle.insert(mime.as_str(), Value::Null);
+            }
+        }
+        let value = notebook.to_saved_value()?;
+        let escape = |token: &str| token.replace('~', "~0").replace('/', "~1");
+        let payloads: HashMap<*const Value, (&str, &LazyPayload)> = unloaded
+            .iter()
+            .filter_map(|(cell, place, mime, payload)| {
+                let pointer = match place {
+                    Place::Output(index) => {
+                        format!("/cells/{cell}/outputs/{index}/data/{}", escape(mime))
+                    }
+                    Place::Attachment(name) => {
+                        format!(
+                            "/cells/{cell}/attachments/{}/{}",
+                            escape(name),
+                            escape(mime)
+                        )
+                    }
+                };
+                let node = value.pointer(&pointer)?;
+                Some((ptr::from_ref(node), (mime.as_str(), payload)))
+            })
+            .collect();
+
+        let error = RefCell::new(None);
+        let out = writer::to_nbformat_string_with(&value, &|node| {
+            let (mime, payload) = payloads.get(&ptr::from_ref(node))?;
+            match payload.try_value_uncached() {
+                Ok(value) => Some(mime::stored_form(mime, value)),
+                Err(err) => {
+                    error.borrow_mut().get_or_insert(err);
+                    None
+                }
+            }
+        });
+        match error.into_inner() {
+            Some(err) => Err(err),
+            None => Ok(out),
+        }
+    }
+
+    /// The payloads not read from the file yet, with where they belong.
+    fn unloaded_payloads(&self) -> Vec<(usize, Place, String, LazyPayload)> {
+        let mut unloaded = Vec::new();
+        let mut add = |cell: usize, place: &dyn Fn() -> Place, bundle: &super::MimeBundle| {
+            for (mime, payload) in bundle.unloaded() {
+                unloaded.push((cell, place(), mime.to_string(), payload.clone()));
+            }
+        };
+        for (index, cell) in self.cells.iter().enumerate() {
+            match cell {
+                Cell::Code(code) => {
+                    for (output, data) in code.outputs.iter().enumerate() {
+                        if let Output::ExecuteResult { data, .. }
+                        | Output::DisplayData { data, .. } = data
+                        {
+                            add(index, &|| Place::Output(output), data);
+                        }
+                    }
+                }
+                Cell::Markdown(MarkdownCell { attachments, .. })
+                | Cell::Raw(RawCell { attachments, .. }) => {
+                    for (name, bundle) in attachments.iter().flatten() {
+                        add(index, &|| Place::Attachment(name.clone()), bundle);
+                    }
+                }
+            }
+        }
+        unloaded
+    }
+
+    fn bundle_mut(&mut self, cell: usize, place: &Place) -> Option<&mut super::MimeBundle> {
+        match (self.cells.get_mut(cell)?, place) {
+            (Cell::Code(code), Place::Output(index)) => match code.outputs.get_mut(*index)? {
+                Output::ExecuteResult { data, .. } | Output::DisplayData { data, .. } => Some(data),
+                _ => None,
+            },
+            (Cell::Markdown(MarkdownCell { attachments, .. }), Place::Attachment(name))
+            | (Cell::Raw(RawCell { attachments, .. }), Place::Attachment(name)) => {
+                attachments.as_mut()?.get_mut(name)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Copies `file` to a file only this process can reach and maps the copy.
+///
+/// Mapping the notebook itself would let any program rewriting it in place
+/// change the payloads under us, or crash us with `SIGBUS` by truncating
+/// it. The copy is made next to the notebook, where filesystems that
+/// support it share the blocks instead of copying them, and unlinked right
+/// away.
+#[cfg(unix)]
+fn snapshot(mut file: File, path: &Path) -> std::io::Result<Mmap> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let name = format!(
+        ".{}.nbterm-snapshot-{}",
+        path.file_name().unwrap_or_default().to_string_lossy(),
+        uuid::Uuid::new_v4()
+    );
+    let create = |dir: &Path| {
+        let copy_path = dir.join(&name);
+        let copy = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&copy_path)?;
+        std::fs::remove_file(&copy_path)?;
+        Ok::<_, std::io::Error>(copy)
+    };
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let mut copy = match dir.map(create) {
+        Some(Ok(copy)) => copy,
+        _ => create(&std::env::temp_dir())?,
+    };
+    std::io::copy(&mut file, &mut copy)?;
+    // SAFETY: the copy is unlinked, so no other process can open it, and
+    // this process never writes to it again.
+    unsafe { Mmap::map(&copy) }
+}
+
+/// Reads `file` into anonymous memory, where unlinking an open file to
+/// keep it private isn't possible.
+#[cfg(not(unix))]
+fn snapshot(mut file: File, _path: &Path) -> std::io::Result<Mmap> {
+    use std::io::Read;
+
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    let mut map = memmap2::MmapOptions::new()
+        .len(content.len().max(1))
+        .map_anon()?;
+    map[..content.len()].copy_from_slice(&content);
+    let map = map.make_read_only()?;
+    Ok(map)
+}
+
+/// Where a lazy payload belongs in the loaded notebook.
+enum Place {
+    Output(usize),
+    Attachment(String),
+}
+
+struct Slot {
+    cell: usize,
+    place: Place,
+    mime: String,
+    range: Range<usize>,
+}
+
+struct Scanner {
+    base: usize,
+    threshold: usize,
+    cell: usize,
+    output: usize,
+    attachment: String,
+    slots: Vec<Slot>,
+}
+
+/// The position in the notebook JSON a value is parsed at. Only the path
+/// down to MIME bundles is tracked; everything else is parsed as usual.
+#[derive(Clone, Copy)]
+enum Level {
+    Root,
+    Cells,
+    Cell,
+    Outputs,
+    Output,
+    Attachments,
+    Bundle(Owner),
+    Other,
+}
+
+#[derive(Clone, Copy)]
+enum Owner {
+    Output,
+    Attachment,
+}
+
+impl Level {
+    fn seed(self, scanner: &mut Scanner) -> Seed<'_> {
+        Seed {
+            scanner,
+            level: self,
+        }
+    }
+
+    fn child(self, key: &str) -> Level {
+        match (self, key) {
+            (Level::Root, "cells") => Level::Cells,
+            (Level::Cell, "outputs") => Level::Outputs,
+            (Level::Cell, "attachments") => Level::Attachments,
+            (Level::Output, "data") => Level::Bundle(Owner::Output),
+            (Level::Attachments, _) => Level::Bundle(Owner::Attachment),
+            _ => Level::Other,
+        }
+    }
+}
+
+struct Seed<'s> {
+    scanner: &'s mut Scanner,
+    level: Level,
+}
+
+impl<'de> DeserializeSeed<'de> for Seed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        match self.level {
+            Level::Other => Value::deserialize(deserializer),
+            _ => deserializer.deserialize_any(self),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Seed<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::from(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let item = match self.level {
+            Level::Cells => Level::Cell,
+            Level::Outputs => Level::Output,
+            _ => Level::Other,
+        };
+        let mut values = Vec::new();
+        loop {
+            match self.level {
+                Level::Cells => self.scanner.cell = values.len(),
+                Level::Outputs => self.scanner.output = values.len(),
+                _ => {}
+            }
+            match seq.next_element_seed(item.seed(self.scanner))? {
+                Some(value) => values.push(value),
+                None => return Ok(Value::Array(values)),
+            }
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut values = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = match self.level {
+                Level::Bundle(owner) => {
+                    let raw: &'de RawValue = map.next_value()?;
+                    self.scanner
+                        .payload(owner, &key, raw)
+                        .map_err(A::Error::custom)?
+                }
+                level => {
+                    if let Level::Attachments = level {
+                        self.scanner.attachment.clone_from(&key);
+                    }
+                    map.next_value_seed(level.child(&key).seed(self.scanner))?
+                }
+            };
+            values.insert(key, value);
+        }
+        Ok(Value::Object(values))
+    }
+}
+
+impl Scanner {
+    /// Parses a MIME bundle value, or records it as a lazy payload and
+    /// returns a placeholder if it is large.
+    fn payload(&mut self, owner: Owner, mime: &str, raw: &RawValue) -> serde_json::Result<Value> {
+        let json = raw.get();
+        if json.len() <= self.threshold {
+            return serde_json::from_str(json);
+        }
+        let start = json.as_ptr() as usize - self.base;
+        self.slots.push(Slot {
+            cell: self.cell,
+            place: match owner {
+                Owner::Output => Place::Output(self.output),
+                Owner::Attachment => Place::Attachment(self.attachment.clone()),
+            },
+            mime: mime.to_string(),
+            range: start..start + json.len(),
+        });
+        // Any string passes validation, whatever the MIME type.
+        Ok(Value::String(String::new()))
+    }
+}
@@ -0,0 +1,547 @@
+//! Notebooks as Markdown documents, for editing tutorials and docs.
+//!
+//! Two flavors are supported, both with the notebook metadata in YAML
+//! front matter:
+//!
+//! * [`MarkdownFlavor::Plain`], Jupytext's Markdown format. Fenced code
+//!   blocks are code cells and the text between them is markdown, split
+//!   into cells at two consecutive blank lines. Cells that need metadata or
+//!   would not survive that split are wrapped in `<!-- #region -->` and
+//!   `<!-- #endregion -->`; raw cells in `<!-- #raw -->` and
+//!   `<!-- #endraw -->`.
+//! * [`MarkdownFlavor::MyST`], MyST Markdown notebooks. Code cells are
+//!   `{code-cell}` directives with their metadata as `:key: value`
+//!   options, and markdown cells are separated by `+++` lines, which may
+//!   carry the cell metadata as JSON.
+//!
+//! Outputs and cell ids are not stored; fresh ids are assigned on load.
+
+use super::percent::{metadata_from_value, parse_attributes, push_attribute};
+use super::types::*;
+use super::yaml;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Notebook metadata keys left out of the front matter, because the kernel
+/// fills them in and they would only add noise to the document.
+const SKIPPED_METADATA: [&str; 2] = ["language_info", "widgets"];
+
+/// `<!-- #region ... -->` or `<!-- #raw ... -->`, with the attributes.
+static REGION_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^<!--\s*#(region|raw)\b(.*?)-->\s*$").unwrap());
+
+/// `<!-- #endregion -->` or `<!-- #endraw -->`, closing the region of the
+/// same kind.
+static REGION_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^<!--\s*#end(region|raw)\s*-->\s*$").unwrap());
+
+/// A `:key: value` option at the start of a MyST directive body.
+static DIRECTIVE_OPTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^:([A-Za-z_][\w.-]*):(?:\s+(.*))?$").unwrap());
+
+/// The Markdown dialect a notebook is read from or written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MarkdownFlavor {
+    #[default]
+    Plain,
+    MyST,
+}
+
+impl MarkdownFlavor {
+    /// Picks the flavor of a document: MyST for `.myst` and `.mystnb`
+    /// files, and for other files if they use MyST cell directives or
+    /// their front matter says so.
+    pub fn detect(path: &Path, text: &str) -> Self {
+        let myst_extension = path
+            .extension()
+            .is_some_and(|ext| ext == "myst" || ext == "mystnb");
+        let has_directives = text
+            .lines()
+            .any(|line| Fence::open(line).is_some_and(|fence| directive(fence.info).is_some()));
+        let front_matter_says_so = take_front_matter(&mut text.lines().collect())
+            .map(|front_matter| yaml::parse(&front_matter))
+            .is_some_and(|value| format_name(&value) == Some("myst"));
+        if myst_extension || has_directives || front_matter_says_so {
+            MarkdownFlavor::MyST
+        } else {
+            MarkdownFlavor::Plain
+        }
+    }
+}
+
+impl Notebook {
+    /// Reads a notebook from a Markdown document of the given flavor.
+    ///
+    /// Like the script format, this never fails: text that isn't a cell
+    /// marker is markdown. Notebooks read as MyST record that in their
+    /// `jupytext` metadata, so [`Notebook::markdown_flavor`] returns it.
+    pub fn from_markdown_str(s: &str, flavor: MarkdownFlavor) -> Self {
+        let mut lines: Vec<&str> = s.lines().collect();
+        let mut notebook = Notebook::default();
+        if let Some(front_matter) = take_front_matter(&mut lines) {
+            let value = yaml::parse(&front_matter);
+            notebook.metadata = match flavor {
+                MarkdownFlavor::Plain => value
+                    .get("jupyter")
+                    .cloned()
+                    .map(metadata_from_value)
+                    .unwrap_or_default(),
+                MarkdownFlavor::MyST => metadata_from_value(value),
+            };
+        }
+        notebook.cells = match flavor {
+            MarkdownFlavor::Plain => read_plain(&lines),
+            MarkdownFlavor::MyST => read_myst(&lines),
+        };
+        if flavor == MarkdownFlavor::MyST && notebook.markdown_flavor() != flavor {
+            set_format_name(&mut notebook.metadata, "myst");
+        }
+        notebook.ensure_cell_ids();
+        notebook
+    }
+
+    /// Writes the notebook as a Markdown document of the given flavor.
+    pub fn to_markdown_string(&self, flavor: MarkdownFlavor) -> String {
+        let mut out = String::new();
+        if let Ok(Value::Object(metadata)) = serde_json::to_value(&self.metadata) {
+            let metadata: Map<String, Value> = metadata
+                .into_iter()
+                .filter(|(key, _)| !SKIPPED_METADATA.contains(&key.as_str()))
+                .collect();
+            if !metadata.is_empty() {
+                let front_matter = match flavor {
+                    MarkdownFlavor::Plain => {
+                        Map::from_iter([("jupyter".to_string(), Value::Object(metadata))])
+                    }
+                    MarkdownFlavor::MyST => metadata,
+                };
+                out.push_str("---\n");
+                out.push_str(&yaml::render(&front_matter));
+                out.push_str("---\n\n");
+            }
+        }
+        match flavor {
+            MarkdownFlavor::Plain => self.write_plain(&mut out),
+            MarkdownFlavor::MyST => self.write_myst(&mut out),
+        }
+        out
+    }
+
+    /// The flavor recorded in the notebook's `jupytext` metadata, which
+    /// is where the notebook should be saved back to for `.md` files.
+    pub fn markdown_flavor(&self) -> MarkdownFlavor {
+        let metadata = serde_json::to_value(&self.metadata).unwrap_or_default();
+        match format_name(&metadata) {
+            Some("myst") => MarkdownFlavor::MyST,
+            _ => MarkdownFlavor::Plain,
+        }
+    }
+
+    /// Loads a notebook from a Markdown file, with the flavor picked by
+    /// [`MarkdownFlavor::detect`].
+    pub fn from_markdown_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read file: {}", path.display()))?;
+        let flavor = MarkdownFlavor::detect(path, &content);
+        Ok(Self::from_markdown_str(&content, flavor))
+    }
+
+    /// Saves the notebook as a Markdown file, creating the directory if
+    /// needed.
+    pub fn save_to_markdown_file<P: AsRef<Path>>(
+        &self,
+        path: P,
+        flavor: MarkdownFlavor,
+    ) -> Result<()> {
+        super::impls::write_file(path.as_ref(), &self.to_markdown_string(flavor))
+    }
+
+    /// The language for code fences: the kernel's language, defaulting to
+    /// Python.
+    fn fence_language(&self) -> &str {
+        self.language().unwrap_or("python")
+    }
+
+    fn write_plain(&self, out: &mut String) {
+        let mut previous = None;
+        for cell in &self.cells {
+            let cell_type = cell.cell_type();
+            if let Some(previous) = previous {
+                out.push('\n');
+                if previous == CellType::Markdown && cell_type == CellType::Markdown {
+                    out.push('\n');
+                }
+            }
+            previous = Some(cell_type);
+
+            let source = cell.source().as_str();
+            let metadata = cell.metadata().as_object();
+            match cell_type {
+                CellType::Code => {
+                    let fence = fence_for(source);
+                    out.push_str(&fence);
+                    out.push_str(self.fence_language());
+                    for (key, value) in metadata.into_iter().flatten() {
+                        push_attribute(out, key, value);
+                    }
+                    out.push('\n');
+                    push_body(out, source);
+                    out.push_str(&fence);
+                    out.push('\n');
+                }
+                CellType::Markdown if !needs_region(source, metadata) => push_body(out, source),
+                CellType::Markdown | CellType::Raw => {
+                    let kind = if cell_type == CellType::Raw {
+                        "raw"
+                    } else {
+                        "region"
+                    };
+                    out.push_str("<!-- #");
+                    out.push_str(kind);
+                    for (key, value) in metadata.into_iter().flatten() {
+                        push_attribute(out, key, value);
+                    }
+                    out.push_str(" -->\n");
+                    push_body(out, source);
+                    out.push_str("<!-- #end");
+                    out.push_str(kind);
+                    out.push_str(" -->\n");
+                }
+            }
+        }
+    }
+
+    fn write_myst(&self, out: &mut String) {
+        let language = self
+            .metadata
+            .language_info
+            .as_ref()
+            .and_then(|info| info.other.get("pygments_lexer"))
+            .and_then(Value::as_str)
+            .unwrap_or(self.fence_language());
+        let mut previous = None;
+        for cell in &self.cells {
+            let cell_type = cell.cell_type();
+            if previous.is_some() {
+                out.push('\n');
+            }
+
+            let source = cell.source().as_str();
+            let metadata = cell.metadata().as_object().filter(|map| !map.is_empty());
+            match cell_type {
+                CellType::Markdown => {
+                    if previous == Some(CellType::Markdown)
+                        || metadata.is_some()
+                        || source.trim().is_empty()
+                    {
+                        out.push_str("+++");
+                        if let Some(metadata) = metadata {
+                            out.push(' ');
+                            out.push_str(&Value::Object(metadata.clone()).to_string());
+                        }
+                        out.push_str("\n\n");
+                    }
+                    push_body(out, source);
+                }
+                CellType::Code | CellType::Raw => {
+                    let fence = fence_for(source);
+                    out.push_str(&fence);
+                    match cell_type {
+                        CellType::Code => {
+                            out.push_str("{code-cell} ");
+                            out.push_str(language);
+                        }
+                        _ => out.push_str("{raw-cell}"),
+                    }
+                    out.push('\n');
+                    for (key, value) in metadata.into_iter().flatten() {
+                        out.push_str(&format!(":{key}: {}\n", yaml::render_scalar(value)));
+                    }
+                    if metadata.is_some() {
+                        out.push('\n');
+                    }
+                    push_body(out, source);
+                    out.push_str(&fence);
+                    out.push('\n');
+                }
+            }
+            previous = Some(cell_type);
+        }
+    }
+}
+
+/// An opening code fence: three or more backticks or tildes, and the info
+/// string after them.
+struct Fence<'a> {
+    marker: char,
+    len: usize,
+    info: &'a str,
+}
+
+impl<'a> Fence<'a> {
+    fn open(line: &'a str) -> Option<Self> {
+        let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+        let len = line.len() - line.trim_start_matches(marker).len();
+        let info = line[len..].trim();
+        (len >= 3 && !(marker == '`' && info.contains('`'))).then_some(Fence { marker, len, info })
+    }
+
+    fn closes(&self, line: &str) -> bool {
+        let line = line.trim_end();
+        line.len() >= self.len && line.chars().all(|c| c == self.marker)
+    }
+
+    /// Splits the lines after the opening fence into the block's body and
+    /// the index of the line following the closing fence.
+    fn body<'l>(&self, lines: &'l [&'a str], start: usize) -> (&'l [&'a str], usize) {
+        match lines[start..].iter().position(|line| self.closes(line)) {
+            Some(offset) => (&lines[start..start + offset], start + offset + 1),
+            None => (&lines[start..], lines.len()),
+        }
+    }
+}
+
+/// The cell type a MyST directive such as `{code-cell} python` stands for.
+fn directive(info: &str) -> Option<CellType> {
+    match info.split_whitespace().next()? {
+        "{code-cell}" => Some(CellType::Code),
+        "{raw-cell}" => Some(CellType::Raw),
+        _ => None,
+    }
+}
+
+fn read_plain(lines: &[&str]) -> Vec<Cell> {
+    let mut cells = vec![];
+    let mut markdown = vec![];
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        if let Some(fence) = Fence::open(line) {
+            push_markdown_chunks(&mut cells, &mut markdown);
+            let (body, next) = fence.body(lines, index + 1);
+            let attributes = fence
+                .info
+                .split_once(char::is_whitespace)
+                .map(|(_, rest)| rest);
+            let metadata = attributes.and_then(parse_attributes).unwrap_or_default();
+            cells.push(Cell::from_parts(
+                CellType::Code,
+                body.join("\n").into(),
+                Value::Object(metadata),
+            ));
+            index = next;
+        } else if let Some(captures) = REGION_START.captures(line) {
+            push_markdown_chunks(&mut cells, &mut markdown);
+            let kind = &captures[1];
+            let cell_type = match kind {
+                "raw" => CellType::Raw,
+                _ => CellType::Markdown,
+            };
+            let metadata = parse_attributes(&captures[2]).unwrap_or_default();
+            let start = index + 1;
+            let end = lines[start..]
+                .iter()
+                .position(|line| REGION_END.captures(line).is_some_and(|end| &end[1] == kind))
+                .map_or(lines.len(), |offset| start + offset);
+            cells.push(Cell::from_parts(
+                cell_type,
+                trim_blank_lines(&lines[start..end]).join("\n").into(),
+                Value::Object(metadata),
+            ));
+            index = end + 1;
+        } else {
+            markdown.push(line);
+            index += 1;
+        }
+    }
+    push_markdown_chunks(&mut cells, &mut markdown);
+    cells
+}
+
+/// Turns the collected markdown lines into cells, split at two or more
+/// consecutive blank lines.
+fn push_markdown_chunks(cells: &mut Vec<Cell>, markdown: &mut Vec<&str>) {
+    let mut chunk: Vec<&str> = vec![];
+    let mut blank_run = 0;
+    for line in markdown.drain(..).chain([""; 2]) {
+        if line.trim().is_empty() {
+            blank_run += 1;
+            if blank_run == 2 {
+                let lines = trim_blank_lines(&chunk);
+                if !lines.is_empty() {
+                    cells.push(Cell::from_parts(
+                        CellType::Markdown,
+                        lines.join("\n").into(),
+                        json!({}),
+                    ));
+                }
+                chunk.clear();
+            }
+        } else {
+            blank_run = 0;
+        }
+        chunk.push(line);
+    }
+}
+
+fn read_myst(lines: &[&str]) -> Vec<Cell> {
+    let mut cells = vec![];
+    let mut markdown = vec![];
+    let mut metadata = Map::new();
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index];
+        if let Some(rest) = line.strip_prefix("+++") {
+            push_markdown_cell(&mut cells, &mut markdown, std::mem::take(&mut metadata));
+            if let Ok(Value::Object(map)) = serde_json::from_str(rest.trim()) {
+                metadata = map;
+            }
+            index += 1;
+            continue;
+        }
+        let Some(fence) = Fence::open(line) else {
+            markdown.push(line);
+            index += 1;
+            continue;
+        };
+        let (body, next) = fence.body(lines, index + 1);
+        match directive(fence.info) {
+            Some(cell_type) => {
+                push_markdown_cell(&mut cells, &mut markdown, std::mem::take(&mut metadata));
+                let (options, body) = directive_options(body);
+                cells.push(Cell::from_parts(
+                    cell_type,
+                    body.join("\n").into(),
+                    Value::Object(options),
+                ));
+            }
+            // Other fenced blocks are code samples inside markdown.
+            None => markdown.extend(&lines[index..next]),
+        }
+        index = next;
+    }
+    push_markdown_cell(&mut cells, &mut markdown, metadata);
+    cells
+}
+
+fn push_markdown_cell(
+    cells: &mut Vec<Cell>,
+    markdown: &mut Vec<&str>,
+    metadata: Map<String, Value>,
+) {
+    let lines = trim_blank_lines(markdown);
+    if !lines.is_empty() || !metadata.is_empty() {
+        cells.push(Cell::from_parts(
+            CellType::Markdown,
+            lines.join("\n").into(),
+            Value::Object(metadata),
+        ));
+    }
+    markdown.clear();
+}
+
+/// Splits the options off a MyST directive body, given either as
+/// `:key: value` lines or as a `---` delimited YAML block.
+fn directive_options<'l, 'a>(body: &'l [&'a str]) -> (Map<String, Value>, &'l [&'a str]) {
+    if body.first() == Some(&"---")
+        && let Some(end) = body[1..].iter().position(|line| *line == "---")
+        && let Value::Object(options) = yaml::parse(&body[1..=end].join("\n"))
+    {
+        return (options, &body[end + 2..]);
+    }
+    let mut options = Map::new();
+    let mut start = 0;
+    while let Some(captures) = body
+        .get(start)
+        .and_then(|line| DIRECTIVE_OPTION.captures(line))
+    {
+        let value = captures
+            .get(2)
+            .map_or(Value::Null, |value| yaml::scalar(value.as_str()));
+        options.insert(captures[1].to_string(), value);
+        start += 1;
+    }
+    // A blank line conventionally separates the options from the code.
+    if start > 0 && body.get(start).is_some_and(|line| line.trim().is_empty()) {
+        start += 1;
+    }
+    (options, &body[start..])
+}
+
+/// Removes a `---` delimited front matter block from the start of `lines`
+/// and returns its content.
+fn take_front_matter(lines: &mut Vec<&str>) -> Option<String> {
+    if lines.first() != Some(&"---") {
+        return None;
+    }
+    let end = lines[1..].iter().position(|line| *line == "---")? + 1;
+    let front_matter = lines[1..end].join("\n");
+    lines.drain(..=end);
+    Some(front_matter)
+}
+
+/// Reads `jupytext.text_representation.format_name` from notebook metadata,
+/// or from the `jupyter` section of plain Markdown front matter.
+fn format_name(metadata: &Value) -> Option<&str> {
+    let metadata = metadata.get("jupyter").unwrap_or(metadata);
+    metadata
+        .pointer("/jupytext/text_representation/format_name")
+        .and_then(Value::as_str)
+}
+
+fn set_format_name(metadata: &mut NotebookMetadata, name: &str) {
+    if !metadata.other.is_object() {
+        metadata.other = json!({});
+    }
+    let mut object = &mut metadata.other;
+    for key in ["jupytext", "text_representation"] {
+        if !object.get(key).is_some_and(Value::is_object) {
+            object[key] = json!({});
+        }
+        object = &mut object[key];
+    }
+    object["format_name"] = json!(name);
+}
+
+/// Whether a markdown cell has to be wrapped in a region to be read back
+/// as the same, single cell.
+fn needs_region(source: &str, metadata: Option<&Map<String, Value>>) -> bool {
+    metadata.is_some_and(|metadata| !metadata.is_empty())
+        || source.trim().is_empty()
+        || source.contains("\n\n\n")
+        || source.starts_with("---")
+        || source
+            .lines()
+            .any(|line| Fence::open(line).is_some() || line.starts_with("<!--"))
+}
+
+/// A backtick fence longer than any backtick run in `source`.
+pub(crate) fn fence_for(source: &str) -> String {
+    let longest = source
+        .lines()
+        .map(|line| line.len() - line.trim_start_matches('`').len())
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn push_body(out: &mut String, source: &str) {
+    if !source.is_empty() {
+        out.push_str(source);
+        out.push('\n');
+    }
+}
+
+fn trim_blank_lines<'l, 'a>(lines: &'l [&'a str]) -> &'l [&'a str] {
+    let start = lines.iter().position(|line| !line.trim().is_empty());
+    let end = lines.iter().rposition(|line| !line.trim().is_empty());
+    match (start, end) {
+        (Some(start), Some(end)) => &lines[start..=end],
+        _ => &[],
+    }
+}
@@ -0,0 +1,458 @@
+//! Three-way merges of notebooks, e.g. for a git merge driver.
+//!
+//! Cells are aligned between the base and each side as for
+//! [`Notebook::diff`]. Changes to different cells, or to different lines or
+//! fields of one cell, are combined; cells added by both sides at the same
+//! place are kept in order, ours first. Where both sides changed the same
+//! thing differently, the merged notebook gets a conflict cell instead: its
+//! source carries git-style markers around the conflicting lines, and its
+//! metadata keeps both versions of the cell, so that
+//! [`Notebook::resolve_conflict`] can pick one later.
+//!
+//! Outputs are treated as derived data: when both sides changed the outputs
+//! of a cell differently, they are cleared rather than reported as a
+//! conflict. Conflicting notebook metadata resolves to ours.
+
+use super::diff::{align, common_lines, moved_pairs};
+use super::edit::CellEdit;
+use super::types::*;
+use serde_json::{Map, Value, json};
+use std::collections::HashSet;
+
+/// Key, under [`METADATA_KEY`], of the two versions of a conflict cell.
+const CONFLICT_KEY: &str = "conflict";
+
+const OURS_MARKER: &str = "<<<<<<< ours\n";
+const SEPARATOR_MARKER: &str = "=======\n";
+const THEIRS_MARKER: &str = ">>>>>>> theirs\n";
+
+/// How to settle a conflict cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep our version of the cell, or no cell if we deleted it.
+    Ours,
+    /// Keep their version of the cell, or no cell if they deleted it.
+    Theirs,
+    /// Keep both versions, ours first.
+    Both,
+    /// Keep the conflict cell as it was edited by hand, without its
+    /// conflict data.
+    Edited,
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`.
+///
+/// The merged notebook has a conflict cell for every change the sides
+/// disagree on, see [`Notebook::conflicts`].
+pub fn merge(base: &Notebook, ours: &Notebook, theirs: &Notebook) -> Notebook {
+    let our_side = Side::new(&base.cells, &ours.cells);
+    let their_side = Side::new(&base.cells, &theirs.cells);
+
+    // Cells that stay where the base had them, and cells that one side
+    // added or moved, by slot: 0 is before the first base cell and i + 1
+    // after base cell i. Added and moved cells keep the index they have on
+    // their side, to be put in that side's order.
+    let mut in_place: Vec<Option<Cell>> = vec![None; base.cells.len()];
+    let mut our_slots: Vec<Vec<(usize, Cell)>> = vec![vec![]; base.cells.len() + 1];
+    let mut their_slots = our_slots.clone();
+
+    for (index, cell) in base.cells.iter().enumerate() {
+        let (our_index, their_index) = (our_side.of_base[index], their_side.of_base[index]);
+        let merged = merge_cell(
+            cell,
+            our_index.map(|i| &ours.cells[i]),
+            their_index.map(|i| &theirs.cells[i]),
+        );
+        let Some(merged) = merged else {
+            continue;
+        };
+        match (our_index, their_index) {
+            (Some(i), _) if our_side.moved[index] => our_slots[our_side.slot[i]].push((i, merged)),
+            (_, Some(i)) if their_side.moved[index] => {
+                their_slots[their_side.slot[i]].push((i, merged))
+            }
+            _ => in_place[index] = Some(merged),
+        }
+    }
+    for (side, cells, slots) in [
+        (&our_side, &ours.cells, &mut our_slots),
+        (&their_side, &theirs.cells, &mut their_slots),
+    ] {
+        for (index, cell) in cells.iter().enumerate() {
+            if !side.paired[index] {
+                slots[side.slot[index]].push((index, cell.clone()));
+            }
+        }
+    }
+
+    let mut cells = vec![];
+    for slot in 0..=base.cells.len() {
+        if slot > 0
+            && let Some(cell) = in_place[slot - 1].take()
+        {
+            cells.push(cell);
+        }
+        let mut ours_here = std::mem::take(&mut our_slots[slot]);
+        let mut theirs_here = std::mem::take(&mut their_slots[slot]);
+        ours_here.sort_by_key(|(index, _)| *index);
+        theirs_here.sort_by_key(|(index, _)| *index);
+        let start = cells.len();
+        cells.extend(ours_here.into_iter().map(|(_, cell)| cell));
+        for (_, cell) in theirs_here {
+            // Both sides adding the same cell at the same place adds it once.
+            if !cells[start..].iter().any(|ours| same_content(ours, &cell)) {
+                cells.push(cell);
+            }
+        }
+    }
+
+    let metadata = [&base.metadata, &ours.metadata, &theirs.metadata]
+        .map(|metadata| serde_json::to_value(metadata).unwrap_or_default());
+    let metadata = merge_json(&metadata[0], &metadata[1], &metadata[2])
+        .and_then(|merged| serde_json::from_value(merged).ok())
+        .unwrap_or_else(|| ours.metadata.clone());
+
+    let mut merged = Notebook {
+        cells,
+        metadata,
+        ..ours.clone()
+    };
+    // Cells both sides added from the same source may share an id.
+    let mut ids = HashSet::new();
+    for cell in &mut merged.cells {
+        if let Some(id) = cell.id().map(str::to_string)
+            && !ids.insert(id)
+        {
+            *cell.id_mut() = None;
+        }
+    }
+    merged.ensure_cell_ids();
+    merged
+}
+
+impl Notebook {
+    /// Indices of the conflict cells left by [`merge`].
+    pub fn conflicts(&self) -> Vec<usize> {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| conflict(cell).is_some())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Replaces the conflict cell at `index` by the version `resolution`
+    /// picks.
+    ///
+    /// Returns the edit that undoes it, or `None` if there is no conflict
+    /// cell at `index`.
+    pub fn resolve_conflict(&mut self, index: usize, resolution: Resolution) -> Option<CellEdit> {
+        let cell = self.cells.get(index)?;
+        let versions = conflict(cell)?;
+        let version =
+            |key: &str| -> Option<Cell> { serde_json::from_value(versions.get(key)?.clone()).ok() };
+        let mut cells: Vec<Cell> = match resolution {
+            Resolution::Ours => version("ours").into_iter().collect(),
+            Resolution::Theirs => version("theirs").into_iter().collect(),
+            Resolution::Both => version("ours")
+                .into_iter()
+                .chain(version("theirs"))
+                .collect(),
+            Resolution::Edited => {
+                let mut cell = cell.clone();
+                clear_conflict(&mut cell);
+                vec![cell]
+            }
+        };
+        let mut ids: HashSet<String> = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .filter_map(|(_, cell)| cell.id().map(str::to_string))
+            .collect();
+        for cell in &mut cells {
+            if cell.id().is_none_or(|id| ids.contains(id)) {
+                let id = Self::new_cell_id_avoiding(&ids);
+                *cell.id_mut() = Some(id);
+            }
+            ids.extend(cell.id().map(str::to_string));
+        }
+        self.apply(CellEdit::Batch(vec![
+            CellEdit::Remove { index, count: 1 },
+            CellEdit::Insert { index, cells },
+        ]))
+    }
+}
+
+/// The two versions stored in a conflict cell, if it is one.
+fn conflict(cell: &Cell) -> Option<&Map<String, Value>> {
+    cell.metadata()
+        .get(METADATA_KEY)?
+        .get(CONFLICT_KEY)?
+        .as_object()
+}
+
+fn clear_conflict(cell: &mut Cell) {
+    let Some(metadata) = cell.metadata_mut().as_object_mut() else {
+        return;
+    };
+    if let Some(ours) = metadata
+        .get_mut(METADATA_KEY)
+        .and_then(Value::as_object_mut)
+    {
+        ours.remove(CONFLICT_KEY);
+        if ours.is_empty() {
+            metadata.remove(METADATA_KEY);
+        }
+    }
+}
+
+/// How the cells of one side relate to the base.
+struct Side {
+    /// For every base cell, its index on this side, if it's still there.
+    of_base: Vec<Option<usize>>,
+    /// For every base cell, whether this side moved it.
+    moved: Vec<bool>,
+    /// For every cell of this side, whether it has a base version.
+    paired: Vec<bool>,
+    /// For every cell of this side, the slot it goes into if it's new or
+    /// moved: after the base version of the closest cell before it that
+    /// stayed in place.
+    slot: Vec<usize>,
+}
+
+impl Side {
+    fn new(base: &[Cell], cells: &[Cell]) -> Self {
+        let pairs = align(base, cells);
+        let moved_pairs = moved_pairs(&pairs);
+        let mut of_base = vec![None; base.len()];
+        let mut moved = vec![false; base.len()];
+        let mut paired = vec![false; cells.len()];
+        let mut anchor = vec![None; cells.len()];
+        for (&(base_index, index), &is_moved) in pairs.iter().zip(&moved_pairs) {
+            of_base[base_index] = Some(index);
+            moved[base_index] = is_moved;
+            paired[index] = true;
+            if !is_moved {
+                anchor[index] = Some(base_index);
+            }
+        }
+        let mut slot = Vec::with_capacity(cells.len());
+        let mut current = 0;
+        for anchor in anchor {
+            if let Some(base_index) = anchor {
+                current = base_index + 1;
+            }
+            slot.push(current);
+        }
+        Self {
+            of_base,
+            moved,
+            paired,
+            slot,
+        }
+    }
+}
+
+/// Merges the versions of a base cell; `None` where a side deleted it.
+///
+/// Returns `None` if the merged notebook should not have the cell.
+fn merge_cell(base: &Cell, ours: Option<&Cell>, theirs: Option<&Cell>) -> Option<Cell> {
+    match (ours, theirs) {
+        (None, None) => None,
+        (None, Some(kept)) | (Some(kept), None) if same_content(kept, base) => None,
+        (None, Some(_)) | (Some(_), None) => {
+            let mut marked = String::new();
+            push_conflict(&mut marked, &source_lines(ours), &source_lines(theirs));
+            Some(conflict_cell(ours, theirs, marked))
+        }
+        (Some(ours), Some(theirs)) => Some(merge_versions(base, ours, theirs)),
+    }
+}
+
+fn merge_versions(base: &Cell, ours: &Cell, theirs: &Cell) -> Cell {
+    if same_content(ours, base) {
+        return theirs.clone();
+    }
+    if same_content(theirs, base) || same_content(ours, theirs) {
+        return ours.clone();
+    }
+
+    let cell_type = pick(&base.cell_type(), &ours.cell_type(), &theirs.cell_type());
+    let (source, clean_source) = merge_text(base.source(), ours.source(), theirs.source());
+    let metadata = merge_json(base.metadata(), ours.metadata(), theirs.metadata());
+    let attachments = pick(
+        &base.attachments().cloned(),
+        &ours.attachments().cloned(),
+        &theirs.attachments().cloned(),
+    );
+    let (Some(cell_type), true, Some(metadata), Some(attachments)) =
+        (cell_type, clean_source, metadata, attachments)
+    else {
+        return conflict_cell(Some(ours), Some(theirs), source);
+    };
+
+    let mut merged = if cell_type == ours.cell_type() {
+        ours.clone()
+    } else {
+        theirs.clone()
+    };
+    *merged.source_mut() = MultilineString(source);
+    *merged.metadata_mut() = metadata;
+    match &mut merged {
+        Cell::Code(code) => {
+            let results = [base, ours, theirs].map(|cell| match cell {
+                Cell::Code(code) => (code.outputs.clone(), code.execution_count),
+                _ => (vec![], None),
+            });
+            (code.outputs, code.execution_count) =
+                pick(&results[0], &results[1], &results[2]).unwrap_or_default();
+        }
+        Cell::Markdown(MarkdownCell {
+            attachments: cell_attachments,
+            ..
+        })
+        | Cell::Raw(RawCell {
+            attachments: cell_attachments,
+            ..
+        }) => *cell_attachments = attachments,
+    }
+    merged
+}
+
+/// A cell standing for a conflict between our and their version of a cell
+/// (`None` where deleted), with `source` as its source.
+fn conflict_cell(ours: Option<&Cell>, theirs: Option<&Cell>, source: String) -> Cell {
+    let mut cell = ours.or(theirs).expect("a conflict has a side").clone();
+    *cell.source_mut() = MultilineString(source);
+    if let Cell::Code(code) = &mut cell {
+        code.outputs.clear();
+        code.execution_count = None;
+    }
+    let versions = json!({
+        "ours": ours.and_then(|cell| serde_json::to_value(cell).ok()),
+        "theirs": theirs.and_then(|cell| serde_json::to_value(cell).ok()),
+    });
+    let metadata = cell.metadata_mut();
+    if !metadata.is_object() {
+        *metadata = json!({});
+    }
+    let own = metadata
+        .as_object_mut()
+        .expect("metadata is an object")
+        .entry(METADATA_KEY)
+        .or_insert_with(|| json!({}));
+    if !own.is_object() {
+        *own = json!({});
+    }
+    own[CONFLICT_KEY] = versions;
+    cell
+}
+
+/// Returns `true` if the cells only differ by id.
+fn same_content(a: &Cell, b: &Cell) -> bool {
+    let mut a = a.clone();
+    *a.id_mut() = b.id().map(str::to_string);
+    a == *b
+}
+
+/// The side that changed, or `None` if both changed differently.
+fn pick<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == base {
+        Some(theirs.clone())
+    } else if theirs == base || ours == theirs {
+        Some(ours.clone())
+    } else {
+        None
+    }
+}
+
+/// Merges JSON values, key by key for objects; `None` on a conflict.
+fn merge_json(base: &Value, ours: &Value, theirs: &Value) -> Option<Value> {
+    if let Some(merged) = pick(base, ours, theirs) {
+        return Some(merged);
+    }
+    let (Some(ours), Some(theirs)) = (ours.as_object(), theirs.as_object()) else {
+        return None;
+    };
+    let empty = Map::new();
+    let base = base.as_object().unwrap_or(&empty);
+    let mut merged = ours.clone();
+    for key in base.keys().chain(ours.keys()).chain(theirs.keys()) {
+        match pick(&base.get(key), &ours.get(key), &theirs.get(key))? {
+            Some(value) => merged.insert(key.clone(), value.clone()),
+            None => merged.remove(key),
+        };
+    }
+    Some(Value::Object(merged))
+}
+
+fn source_lines(cell: Option<&Cell>) -> Vec<&str> {
+    cell.map_or(vec![], |cell| split_lines(cell.source()))
+}
+
+/// Splits text into lines that keep their line endings.
+fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// Merges texts line by line, like `diff3`.
+///
+/// Returns the merged text and whether it is free of conflicts; otherwise
+/// the conflicting lines are put between markers.
+fn merge_text(base: &str, ours: &str, theirs: &str) -> (String, bool) {
+    let (base, ours, theirs) = (split_lines(base), split_lines(ours), split_lines(theirs));
+    let mut in_ours = vec![None; base.len()];
+    for (base_index, index) in common_lines(&base, &ours) {
+        in_ours[base_index] = Some(index);
+    }
+    let mut in_theirs = vec![None; base.len()];
+    for (base_index, index) in common_lines(&base, &theirs) {
+        in_theirs[base_index] = Some(index);
+    }
+
+    let mut merged = String::new();
+    let mut clean = true;
+    let (mut next_base, mut next_ours, mut next_theirs) = (0, 0, 0);
+    loop {
+        // The next base line both sides kept ends the current chunk.
+        let sync = (next_base..base.len())
+            .find_map(|index| Some((index, in_ours[index]?, in_theirs[index]?)));
+        let (end_base, end_ours, end_theirs) =
+            sync.unwrap_or((base.len(), ours.len(), theirs.len()));
+        let chunk_base = &base[next_base..end_base];
+        let chunk_ours = &ours[next_ours..end_ours];
+        let chunk_theirs = &theirs[next_theirs..end_theirs];
+        match pick(&chunk_base, &chunk_ours, &chunk_theirs) {
+            Some(lines) => merged.extend(lines.iter().copied()),
+            None => {
+                clean = false;
+                push_conflict(&mut merged, chunk_ours, chunk_theirs);
+            }
+        }
+        let Some((index, index_ours, index_theirs)) = sync else {
+            break;
+        };
+        merged.push_str(base[index]);
+        (next_base, next_ours, next_theirs) = (index + 1, index_ours + 1, index_theirs + 1);
+    }
+    (merged, clean)
+}
+
+/// Appends two conflicting versions of some lines between markers.
+fn push_conflict(out: &mut String, ours: &[&str], theirs: &[&str]) {
+    let end_line = |out: &mut String| {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    };
+    end_line(out);
+    out.push_str(OURS_MARKER);
+    out.extend(ours.iter().copied());
+    end_line(out);
+    out.push_str(SEPARATOR_MARKER);
+    out.extend(theirs.iter().copied());
+    end_line(out);
+    out.push_str(THEIRS_MARKER);
+}
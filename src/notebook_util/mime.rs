@@ -0,0 +1,294 @@
+//! Typed access to the MIME bundles carried by rich outputs and attachments.
+
+use super::lazy::LazyPayload;
+use super::types::MultilineString;
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+pub const TEXT_PLAIN: &str = "text/plain";
+pub const TEXT_HTML: &str = "text/html";
+pub const TEXT_MARKDOWN: &str = "text/markdown";
+pub const TEXT_LATEX: &str = "text/latex";
+pub const IMAGE_PNG: &str = "image/png";
+pub const IMAGE_JPEG: &str = "image/jpeg";
+pub const IMAGE_SVG: &str = "image/svg+xml";
+pub const APPLICATION_JSON: &str = "application/json";
+pub const APPLICATION_JAVASCRIPT: &str = "application/javascript";
+
+/// Richest-first order used when no other priority list is given, the same
+/// as nbconvert's default `display_data_priority`.
+pub const DEFAULT_MIME_PRIORITY: &[&str] = &[
+    TEXT_HTML,
+    "application/pdf",
+    TEXT_LATEX,
+    IMAGE_SVG,
+    IMAGE_PNG,
+    IMAGE_JPEG,
+    TEXT_MARKDOWN,
+    TEXT_PLAIN,
+];
+
+/// Returns `true` for MIME types whose value is arbitrary JSON rather than
+/// text (`application/json` and `application/*+json`).
+pub fn is_json_mime(mime: &str) -> bool {
+    mime == APPLICATION_JSON || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// Returns `true` for MIME types Jupyter writes as a list of lines.
+fn is_split_mime(mime: &str) -> bool {
+    mime.starts_with("text/") || mime == APPLICATION_JAVASCRIPT || mime == IMAGE_SVG
+}
+
+/// Brings a stored MIME value into its in-memory form: text stored as a
+/// list of lines is joined, JSON values are left alone.
+pub(crate) fn normalize(mime: &str, value: Value) -> Value {
+    match value {
+        Value::Array(lines) if !is_json_mime(mime) && lines.iter().all(Value::is_string) => {
+            Value::String(lines.iter().filter_map(Value::as_str).collect())
+        }
+        value => value,
+    }
+}
+
+/// A MIME-type keyed bundle of output data.
+///
+/// Text values are kept joined in memory whichever form they were stored in,
+/// and JSON values are kept as parsed JSON. Unknown and vendor MIME types are
+/// kept as-is, so every entry survives a load/save round-trip.
+///
+/// Values of notebooks loaded with [`Notebook::from_file_lazy`] may still
+/// live in the file; they are parsed on first access.
+///
+/// [`Notebook::from_file_lazy`]: super::Notebook::from_file_lazy
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MimeBundle(BTreeMap<String, MimeData>);
+
+#[derive(Debug, Clone)]
+enum MimeData {
+    Loaded(Value),
+    Lazy(LazyPayload),
+}
+
+impl MimeData {
+    fn value(&self) -> &Value {
+        match self {
+            MimeData::Loaded(value) => value,
+            MimeData::Lazy(payload) => payload.value(),
+        }
+    }
+
+    /// The value, without keeping a lazy payload in memory afterwards.
+    fn value_uncached(&self) -> Cow<'_, Value> {
+        match self {
+            MimeData::Loaded(value) => Cow::Borrowed(value),
+            MimeData::Lazy(payload) => payload.value_uncached(),
+        }
+    }
+}
+
+impl PartialEq for MimeData {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (MimeData::Lazy(a), MimeData::Lazy(b)) if a.same_source(b) => true,
+            _ => self.value_uncached() == other.value_uncached(),
+        }
+    }
+}
+
+impl MimeBundle {
+    /// Creates an empty bundle.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a bundle holding a single `text/plain` value.
+    pub fn text_plain<S: Into<String>>(text: S) -> Self {
+        let mut bundle = Self::new();
+        bundle.insert(TEXT_PLAIN, Value::String(text.into()));
+        bundle
+    }
+
+    /// Adds or replaces the value for `mime`.
+    pub fn insert<S: Into<String>>(&mut self, mime: S, value: Value) {
+        self.0.insert(mime.into(), MimeData::Loaded(value));
+    }
+
+    /// Adds or replaces the value for `mime` with one that is parsed on
+    /// first access.
+    pub(crate) fn insert_lazy<S: Into<String>>(&mut self, mime: S, payload: LazyPayload) {
+        self.0.insert(mime.into(), MimeData::Lazy(payload));
+    }
+
+    /// Removes the value for `mime` and returns it.
+    pub fn remove(&mut self, mime: &str) -> Option<Value> {
+        self.0.remove(mime).map(|data| match data {
+            MimeData::Loaded(value) => value,
+            MimeData::Lazy(payload) => payload.value_uncached().into_owned(),
+        })
+    }
+
+    /// Returns the raw value stored for `mime`.
+    pub fn get(&self, mime: &str) -> Option<&Value> {
+        self.0.get(mime).map(MimeData::value)
+    }
+
+    /// Returns the value stored for a textual `mime` as a string.
+    pub fn text(&self, mime: &str) -> Option<&str> {
+        self.get(mime).and_then(Value::as_str)
+    }
+
+    /// Returns `false` if the value for `mime` has not been read from the
+    /// notebook file yet, see [`MimeBundle`].
+    pub fn is_loaded(&self, mime: &str) -> bool {
+        match self.0.get(mime) {
+            Some(MimeData::Lazy(payload)) => payload.is_loaded(),
+            _ => true,
+        }
+    }
+
+    /// Iterates over the values that have not been read from the notebook
+    /// file yet.
+    pub(crate) fn unloaded(&self) -> impl Iterator<Item = (&str, &LazyPayload)> {
+        self.0.iter().filter_map(|(mime, data)| match data {
+            MimeData::Lazy(payload) if !payload.is_loaded() => Some((mime.as_str(), payload)),
+            _ => None,
+        })
+    }
+
+    /// Returns `true` if the bundle holds data for `mime`.
+    pub fn contains(&self, mime: &str) -> bool {
+        self.0.contains_key(mime)
+    }
+
+    /// Iterates over the MIME types in the bundle, in sorted order.
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Iterates over the MIME types and their values, in sorted order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0
+            .iter()
+            .map(|(mime, data)| (mime.as_str(), data.value()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The `text/plain` representation.
+    pub fn plain(&self) -> Option<&str> {
+        self.text(TEXT_PLAIN)
+    }
+
+    /// The `text/html` representation.
+    pub fn html(&self) -> Option<&str> {
+        self.text(TEXT_HTML)
+    }
+
+    /// The `text/markdown` representation.
+    pub fn markdown(&self) -> Option<&str> {
+        self.text(TEXT_MARKDOWN)
+    }
+
+    /// The `text/latex` representation.
+    pub fn latex(&self) -> Option<&str> {
+        self.text(TEXT_LATEX)
+    }
+
+    /// The `image/svg+xml` representation.
+    pub fn svg(&self) -> Option<&str> {
+        self.text(IMAGE_SVG)
+    }
+
+    /// The `application/json` representation.
+    pub fn json(&self) -> Option<&Value> {
+        self.get(APPLICATION_JSON)
+    }
+
+    /// The `image/png` representation, base64-decoded.
+    pub fn png(&self) -> Option<Result<Vec<u8>>> {
+        self.decode_base64(IMAGE_PNG)
+    }
+
+    /// The `image/jpeg` representation, base64-decoded.
+    pub fn jpeg(&self) -> Option<Result<Vec<u8>>> {
+        self.decode_base64(IMAGE_JPEG)
+    }
+
+    /// Decodes a base64-encoded binary value, ignoring the line breaks
+    /// Jupyter may insert.
+    pub fn decode_base64(&self, mime: &str) -> Option<Result<Vec<u8>>> {
+        let encoded = self.text(mime)?;
+        let compact: String = encoded.split_ascii_whitespace().collect();
+        Some(
+            base64::engine::general_purpose::STANDARD
+                .decode(compact)
+                .with_context(|| format!("Invalid base64 data for {mime}")),
+        )
+    }
+
+    /// Picks the richest representation available, i.e. the first MIME type
+    /// of `priority` that the bundle holds.
+    pub fn richest<'a>(&'a self, priority: &[&str]) -> Option<(&'a str, &'a Value)> {
+        priority
+            .iter()
+            .find_map(|mime| self.0.get_key_value(*mime))
+            .map(|(mime, data)| (mime.as_str(), data.value()))
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, Value)> for MimeBundle {
+    fn from_iter<I: IntoIterator<Item = (S, Value)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), MimeData::Loaded(v)))
+                .collect(),
+        )
+    }
+}
+
+/// Brings an in-memory MIME value into the form Jupyter stores it in:
+/// the inverse of [`normalize`].
+pub(crate) fn stored_form(mime: &str, value: Cow<'_, Value>) -> Value {
+    match value {
+        Cow::Borrowed(Value::String(text)) if is_split_mime(mime) => {
+            Value::from(MultilineString::from(text.as_str()).lines())
+        }
+        Cow::Owned(Value::String(text)) if is_split_mime(mime) => {
+            Value::from(MultilineString::from(text).lines())
+        }
+        value => value.into_owned(),
+    }
+}
+
+impl Serialize for MimeBundle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            self.0
+                .iter()
+                .map(|(mime, data)| (mime, stored_form(mime, data.value_uncached()))),
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for MimeBundle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let map = Map::deserialize(deserializer)?;
+        Ok(map
+            .into_iter()
+            .map(|(mime, value)| {
+                let value = normalize(&mime, value);
+                (mime, value)
+            })
+            .collect())
+    }
+}
@@ -1,4 +1,20 @@
+pub mod convert;
+pub mod diff;
+pub mod edit;
+pub mod export;
+pub mod format;
 pub mod impls;
+pub mod lazy;
+pub mod markdown;
+pub mod merge;
+pub mod mime;
+pub mod percent;
+pub mod sanitize;
 pub mod types;
+pub mod validation;
+pub mod writer;
+mod yaml;
 
+pub use edit::CellEdit;
+pub use mime::MimeBundle;
 pub use types::*; // So you can use `Notebook` from `notebook_util::Notebook`
@@ -0,0 +1,295 @@
+//! The py:percent script format used by Jupytext to pair notebooks with
+//! `.py` files.
+//!
+//! Every cell starts with a marker line: `# %%`, then an optional title,
+//! the cell type for non-code cells (`[markdown]` or `[raw]`) and the cell
+//! metadata as `key=value` pairs with JSON values:
+//!
+//! ```text
+//! # %% Setup [markdown] tags=["intro"]
+//! # Some *markdown*, commented out.
+//! ```
+//!
+//! Notebook metadata goes into a commented YAML header. Outputs and cell
+//! ids are not part of the format, so they are dropped when writing and
+//! fresh ids are assigned when reading.
+
+use super::types::*;
+use super::yaml;
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::{Map, Value, json};
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Notebook metadata keys written to the header; the rest (such as
+/// `language_info`) is filled in by the kernel and only adds noise to
+/// scripts, as in Jupytext's default metadata filter.
+const HEADER_METADATA: [&str; 2] = ["jupytext", "kernelspec"];
+
+/// Marker lines, `# %%` or `#%%` followed by nothing or whitespace, so
+/// that a commented-out `%%time` cell magic is not taken as one.
+static MARKER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^#\s*%%(\s|$)").unwrap());
+
+/// A metadata key at the start of the text, with the `=` that follows it.
+static METADATA_KEY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*([A-Za-z_][\w.-]*)=").unwrap());
+
+/// IPython magics and shell escapes, which are commented out in code cells
+/// so that the script stays valid Python.
+static MAGIC: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(%%?|!)[A-Za-z]").unwrap());
+
+impl Notebook {
+    /// Reads a notebook from a py:percent script.
+    ///
+    /// The format has no invalid inputs: text before the first marker
+    /// becomes a code cell, and a marker whose metadata doesn't parse keeps
+    /// the whole line as the cell title.
+    pub fn from_percent_str(s: &str) -> Self {
+        let mut lines: Vec<&str> = s.lines().collect();
+        let mut notebook = Notebook::default();
+        if let Some(header) = take_header(&mut lines) {
+            notebook.metadata = parse_header(&header);
+        }
+
+        let mut start = lines.iter().position(|line| MARKER.is_match(line));
+        let preamble = &lines[..start.unwrap_or(lines.len())];
+        if preamble.iter().any(|line| !line.trim().is_empty()) {
+            notebook
+                .cells
+                .push(make_cell(CellType::Code, json!({}), preamble));
+        }
+        while let Some(marker) = start {
+            let end = lines[marker + 1..]
+                .iter()
+                .position(|line| MARKER.is_match(line))
+                .map(|offset| marker + 1 + offset);
+            let (cell_type, metadata) = parse_marker(lines[marker]);
+            let body = &lines[marker + 1..end.unwrap_or(lines.len())];
+            notebook.cells.push(make_cell(cell_type, metadata, body));
+            start = end;
+        }
+
+        notebook.ensure_cell_ids();
+        notebook
+    }
+
+    /// Writes the notebook as a py:percent script.
+    pub fn to_percent_string(&self) -> String {
+        let mut out = String::new();
+        if let Ok(Value::Object(metadata)) = serde_json::to_value(&self.metadata) {
+            let header: Map<String, Value> = metadata
+                .into_iter()
+                .filter(|(key, _)| HEADER_METADATA.contains(&key.as_str()))
+                .collect();
+            if !header.is_empty() {
+                out.push_str("# ---\n");
+                let mut jupyter = Map::new();
+                jupyter.insert("jupyter".to_string(), Value::Object(header));
+                for line in yaml::render(&jupyter).lines() {
+                    push_comment(&mut out, line);
+                }
+                out.push_str("# ---\n\n");
+            }
+        }
+
+        for (index, cell) in self.cells.iter().enumerate() {
+            if index > 0 {
+                out.push('\n');
+            }
+            out.push_str(&marker_line(cell));
+            out.push('\n');
+            for line in cell.source().as_str().lines() {
+                match cell.cell_type() {
+                    CellType::Code if MAGIC.is_match(line) => push_comment(&mut out, line),
+                    CellType::Code => {
+                        out.push_str(line);
+                        out.push('\n');
+                    }
+                    CellType::Markdown | CellType::Raw => push_comment(&mut out, line),
+                }
+            }
+        }
+        out
+    }
+
+    /// Loads a notebook from a py:percent script, see
+    /// [`Notebook::from_percent_str`].
+    pub fn from_percent_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read file: {}", path.as_ref().display()))?;
+        Ok(Self::from_percent_str(&content))
+    }
+
+    /// Saves the notebook as a py:percent script, creating the directory if
+    /// needed.
+    pub fn save_to_percent_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        super::impls::write_file(path.as_ref(), &self.to_percent_string())
+    }
+}
+
+/// Removes a `# ---` delimited header from the start of `lines` and returns
+/// its uncommented content.
+fn take_header(lines: &mut Vec<&str>) -> Option<String> {
+    if lines.first() != Some(&"# ---") {
+        return None;
+    }
+    let end = lines[1..].iter().position(|line| *line == "# ---")? + 1;
+    let header = lines[1..end]
+        .iter()
+        .map(|line| uncomment(line))
+        .collect::<Vec<_>>()
+        .join("\n");
+    lines.drain(..=end);
+    Some(header)
+}
+
+fn parse_header(header: &str) -> NotebookMetadata {
+    let Value::Object(mut document) = yaml::parse(header) else {
+        return NotebookMetadata::default();
+    };
+    document
+        .remove("jupyter")
+        .map(metadata_from_value)
+        .unwrap_or_default()
+}
+
+/// Converts notebook metadata read from a text header, keeping it as is if
+/// the modeled fields (such as `kernelspec`) don't have the expected shape.
+pub(super) fn metadata_from_value(metadata: Value) -> NotebookMetadata {
+    if !metadata.is_object() {
+        return NotebookMetadata::default();
+    }
+    serde_json::from_value(metadata.clone()).unwrap_or_else(|_| NotebookMetadata {
+        other: metadata,
+        ..NotebookMetadata::default()
+    })
+}
+
+/// Splits a marker line into the cell type and the cell metadata, which
+/// includes the title, if any, under `title`.
+fn parse_marker(line: &str) -> (CellType, Value) {
+    let rest = MARKER.replace(line, "");
+    let rest = rest.trim();
+
+    let (head, attributes) = match rest
+        .char_indices()
+        .filter(|&(index, c)| index == 0 || c.is_whitespace())
+        .map(|(index, _)| index)
+        .find(|&index| METADATA_KEY.is_match(&rest[index..]))
+    {
+        Some(index) => match parse_attributes(&rest[index..]) {
+            Some(metadata) => (&rest[..index], metadata),
+            None => (rest, Map::new()),
+        },
+        None => (rest, Map::new()),
+    };
+
+    let mut metadata = attributes;
+    let mut cell_type = CellType::Code;
+    let mut title = head.trim().to_string();
+    for (tag, kind) in [
+        ("[markdown]", CellType::Markdown),
+        ("[md]", CellType::Markdown),
+        ("[raw]", CellType::Raw),
+    ] {
+        if let Some(index) = title.find(tag) {
+            cell_type = kind;
+            title = format!("{} {}", &title[..index], &title[index + tag.len()..])
+                .trim()
+                .to_string();
+            break;
+        }
+    }
+    if !title.is_empty() {
+        metadata.insert("title".to_string(), Value::String(title));
+    }
+    (cell_type, Value::Object(metadata))
+}
+
+/// Parses `key=value` pairs with JSON values up to the end of the text, or
+/// returns `None` if anything else is found.
+pub(super) fn parse_attributes(mut text: &str) -> Option<Map<String, Value>> {
+    let mut metadata = Map::new();
+    while !text.trim().is_empty() {
+        let key = METADATA_KEY.captures(text)?;
+        let rest = &text[key.get(0)?.end()..];
+        let mut values = serde_json::Deserializer::from_str(rest).into_iter::<Value>();
+        let value = values.next()?.ok()?;
+        let end = values.byte_offset();
+        if !rest[end..].is_empty() && !rest[end..].starts_with(char::is_whitespace) {
+            return None;
+        }
+        metadata.insert(key[1].to_string(), value);
+        text = &rest[end..];
+    }
+    Some(metadata)
+}
+
+fn make_cell(cell_type: CellType, metadata: Value, lines: &[&str]) -> Cell {
+    let end = lines
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |index| index + 1);
+    let lines = &lines[..end];
+    let source: MultilineString = match cell_type {
+        CellType::Code => lines
+            .iter()
+            .map(|line| match line.strip_prefix("# ") {
+                Some(magic) if MAGIC.is_match(magic) => magic,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into(),
+        CellType::Markdown | CellType::Raw => lines
+            .iter()
+            .map(|line| uncomment(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into(),
+    };
+    Cell::from_parts(cell_type, source, metadata)
+}
+
+fn marker_line(cell: &Cell) -> String {
+    let mut line = "# %%".to_string();
+    let metadata = cell.metadata().as_object();
+    if let Some(Value::String(title)) = metadata.and_then(|metadata| metadata.get("title")) {
+        line.push(' ');
+        line.push_str(title);
+    }
+    match cell.cell_type() {
+        CellType::Code => {}
+        CellType::Markdown => line.push_str(" [markdown]"),
+        CellType::Raw => line.push_str(" [raw]"),
+    }
+    for (key, value) in metadata.into_iter().flatten() {
+        if key == "title" && value.is_string() {
+            continue;
+        }
+        push_attribute(&mut line, key, value);
+    }
+    line
+}
+
+/// Appends ` key=value` with a JSON value, as read by [`parse_attributes`].
+pub(super) fn push_attribute(line: &mut String, key: &str, value: &Value) {
+    line.push_str(&format!(" {key}={value}"));
+}
+
+fn push_comment(out: &mut String, line: &str) {
+    out.push('#');
+    if !line.is_empty() {
+        out.push(' ');
+        out.push_str(line);
+    }
+    out.push('\n');
+}
+
+fn uncomment(line: &str) -> &str {
+    line.strip_prefix("# ")
+        .or_else(|| line.strip_prefix('#'))
+        .unwrap_or(line)
+}
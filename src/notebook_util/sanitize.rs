@@ -0,0 +1,154 @@
+//! Clearing and stripping of outputs and metadata, e.g. before committing
+//! notebooks to version control.
+//!
+//! Every operation works on a range of cells and returns the [`CellEdit`]
+//! that undoes it, or `None` if the range is out of bounds or nothing
+//! changed.
+
+use super::edit::CellEdit;
+use super::types::*;
+use serde::Deserialize;
+use std::ops::Range;
+
+/// Which sanitizing steps to run, typically loaded per project.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SanitizeRules {
+    /// Remove all outputs of code cells.
+    pub clear_outputs: bool,
+    /// Set every `execution_count` to null.
+    pub reset_execution_counts: bool,
+    /// Top-level cell metadata keys to remove.
+    pub drop_cell_metadata: Vec<String>,
+    /// Remove single outputs whose JSON is larger than this many bytes.
+    pub max_output_bytes: Option<usize>,
+}
+
+impl Default for SanitizeRules {
+    /// The same defaults as nbstripout: clear outputs and counts and drop
+    /// the metadata that only records UI state or timing.
+    fn default() -> Self {
+        Self {
+            clear_outputs: true,
+            reset_execution_counts: true,
+            drop_cell_metadata: ["collapsed", "scrolled", "ExecuteTime", "execution"]
+                .map(str::to_string)
+                .to_vec(),
+            max_output_bytes: None,
+        }
+    }
+}
+
+impl Notebook {
+    /// Removes the outputs of the code cells in `range`.
+    pub fn clear_outputs(&mut self, range: Range<usize>) -> Option<CellEdit> {
+        self.edit_cells(range, |cell| {
+            if let Cell::Code(code) = cell {
+                code.outputs.clear();
+            }
+        })
+    }
+
+    /// Resets the execution counts of the code cells in `range`, including
+    /// those recorded on `execute_result` outputs.
+    pub fn reset_execution_counts(&mut self, range: Range<usize>) -> Option<CellEdit> {
+        self.edit_cells(range, |cell| {
+            if let Cell::Code(code) = cell {
+                reset_counts(code);
+            }
+        })
+    }
+
+    /// Removes the given top-level metadata keys from the cells in `range`.
+    pub fn strip_metadata(&mut self, range: Range<usize>, keys: &[String]) -> Option<CellEdit> {
+        self.edit_cells(range, |cell| {
+            drop_metadata_keys(cell, keys);
+        })
+    }
+
+    /// Removes outputs whose JSON representation is larger than `max_bytes`
+    /// from the code cells in `range`.
+    pub fn strip_large_outputs(
+        &mut self,
+        range: Range<usize>,
+        max_bytes: usize,
+    ) -> Option<CellEdit> {
+        self.edit_cells(range, |cell| {
+            if let Cell::Code(code) = cell {
+                drop_large_outputs(code, max_bytes);
+            }
+        })
+    }
+
+    /// Runs every step enabled in `rules` on the cells in `range`, as a
+    /// single undo step.
+    pub fn sanitize(&mut self, range: Range<usize>, rules: &SanitizeRules) -> Option<CellEdit> {
+        self.edit_cells(range, |cell| {
+            drop_metadata_keys(cell, &rules.drop_cell_metadata);
+            let Cell::Code(code) = cell else {
+                return;
+            };
+            if rules.clear_outputs {
+                code.outputs.clear();
+            }
+            if let Some(max_bytes) = rules.max_output_bytes {
+                drop_large_outputs(code, max_bytes);
+            }
+            if rules.reset_execution_counts {
+                reset_counts(code);
+            }
+        })
+    }
+
+    /// Applies `change` to a copy of every cell in `range` and replaces the
+    /// cells that actually changed, as one batch edit.
+    fn edit_cells(
+        &mut self,
+        range: Range<usize>,
+        mut change: impl FnMut(&mut Cell),
+    ) -> Option<CellEdit> {
+        let edits: Vec<CellEdit> = self
+            .cells
+            .get(range.clone())?
+            .iter()
+            .zip(range)
+            .filter_map(|(cell, index)| {
+                let mut changed = cell.clone();
+                change(&mut changed);
+                (changed != *cell).then(|| CellEdit::Replace {
+                    index,
+                    cell: Box::new(changed),
+                })
+            })
+            .collect();
+        if edits.is_empty() {
+            return None;
+        }
+        self.apply(CellEdit::Batch(edits))
+    }
+}
+
+fn reset_counts(code: &mut CodeCell) {
+    code.execution_count = None;
+    for output in &mut code.outputs {
+        if let Output::ExecuteResult {
+            execution_count, ..
+        } = output
+        {
+            *execution_count = None;
+        }
+    }
+}
+
+fn drop_metadata_keys(cell: &mut Cell, keys: &[String]) {
+    if let Some(metadata) = cell.metadata_mut().as_object_mut() {
+        for key in keys {
+            metadata.remove(key);
+        }
+    }
+}
+
+fn drop_large_outputs(code: &mut CodeCell, max_bytes: usize) {
+    code.outputs
+        .retain(|output| serde_json::to_vec(output).map_or(true, |json| json.len() <= max_bytes));
+}
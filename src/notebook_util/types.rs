@@ -1,43 +1,72 @@
-use serde::{Deserialize, Serialize};
+use super::mime::MimeBundle;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Deref;
+
+/// Major nbformat version notebooks are edited as in memory.
+pub const NBFORMAT: u8 = 4;
+
+/// Minor nbformat version notebooks are edited as in memory.
+pub const NBFORMAT_MINOR: u8 = 5;
+
+/// Metadata key holding nbterm's own data in a cell.
+pub(crate) const METADATA_KEY: &str = "nbterm";
 
 /// Represents the top-level structure of a Jupyter notebook file.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Notebook {
     pub cells: Vec<Cell>,
     pub metadata: NotebookMetadata,
     pub nbformat: u8,
     pub nbformat_minor: u8,
+    /// The nbformat version of the file the notebook was loaded from, when
+    /// it was older than the in-memory one. Saving doesn't go back to it by
+    /// itself, see [`Notebook::save_to_str_as_version`].
+    #[serde(skip)]
+    pub original_version: Option<(u8, u8)>,
+    #[serde(flatten)]
+    pub other: Value,
 }
 
 /// Top-level metadata field (can contain various kernel or language info).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct NotebookMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub kernelspec: Option<Kernelspec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub language_info: Option<LanguageInfo>,
     #[serde(flatten)]
     pub other: Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Kernelspec {
     pub name: String,
     pub display_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(flatten)]
+    pub other: Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LanguageInfo {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mimetype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub file_extension: Option<String>,
     #[serde(flatten)]
     pub other: Value,
 }
 
 /// Enum for all supported Jupyter cell types.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "cell_type")]
 pub enum Cell {
     #[serde(rename = "code")]
@@ -50,51 +79,95 @@ pub enum Cell {
     Raw(RawCell),
 }
 
+/// The kind of a [`Cell`], without its content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CellType {
+    Code,
+    Markdown,
+    Raw,
+}
+
 /// A code cell with executable content and outputs.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CodeCell {
-    pub source: Vec<String>,
+    /// Cell identifier, required since nbformat 4.5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub source: MultilineString,
     pub metadata: Value,
     pub execution_count: Option<u32>,
     pub outputs: Vec<Output>,
+    /// Keys not modeled above (e.g. vendor extensions), kept for round-trips.
+    #[serde(flatten)]
+    pub other: Value,
 }
 
 /// A markdown cell with formatted text.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MarkdownCell {
-    pub source: Vec<String>,
+    /// Cell identifier, required since nbformat 4.5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub source: MultilineString,
     pub metadata: Value,
+    /// Files embedded in the cell, keyed by filename, then by MIME type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<BTreeMap<String, MimeBundle>>,
+    #[serde(flatten)]
+    pub other: Value,
 }
 
 /// A raw cell with unformatted text.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RawCell {
-    pub source: Vec<String>,
+    /// Cell identifier, required since nbformat 4.5.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub source: MultilineString,
     pub metadata: Value,
+    /// Files embedded in the cell, keyed by filename, then by MIME type.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachments: Option<BTreeMap<String, MimeBundle>>,
+    #[serde(flatten)]
+    pub other: Value,
 }
 
 /// Output objects for code cells.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Every variant keeps keys it doesn't model (such as `transient`) in
+/// `other`, so they survive a load/save round-trip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "output_type")]
 pub enum Output {
     #[serde(rename = "stream")]
     Stream {
         name: String, // "stdout" or "stderr"
-        text: Vec<String>,
+        text: MultilineString,
+        #[serde(flatten)]
+        other: Value,
     },
     #[serde(rename = "execute_result")]
     ExecuteResult {
-        execution_count: u32,
-        data: Value, // Typically contains "text/plain", "text/html", etc.
+        execution_count: Option<u32>,
+        data: MimeBundle, // Typically contains "text/plain", "text/html", etc.
         metadata: Value,
+        #[serde(flatten)]
+        other: Value,
     },
     #[serde(rename = "display_data")]
-    DisplayData { data: Value, metadata: Value },
+    DisplayData {
+        data: MimeBundle,
+        metadata: Value,
+        #[serde(flatten)]
+        other: Value,
+    },
     #[serde(rename = "error")]
     Error {
         ename: String,
         evalue: String,
         traceback: Vec<String>,
+        #[serde(flatten)]
+        other: Value,
     },
 }
 
@@ -103,8 +176,10 @@ impl Default for Notebook {
         Self {
             cells: vec![],
             metadata: NotebookMetadata::default(),
-            nbformat: 4,
-            nbformat_minor: 5,
+            nbformat: NBFORMAT,
+            nbformat_minor: NBFORMAT_MINOR,
+            original_version: None,
+            other: Value::Null,
         }
     }
 }
@@ -118,3 +193,159 @@ impl Default for NotebookMetadata {
         }
     }
 }
+
+/// Text that nbformat stores either as one string or as a list of strings.
+///
+/// Both forms are accepted on load; the text is kept joined in memory and
+/// written back in the canonical list form, one entry per line with line
+/// endings kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MultilineString(pub String);
+
+impl MultilineString {
+    /// Returns the text as a single string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Splits the text into lines, keeping the line endings.
+    ///
+    /// This mirrors Python's `str.splitlines(keepends=True)`, which is what
+    /// Jupyter uses when writing notebooks, so that `\r`-only line breaks
+    /// (progress bars) split the same way.
+    pub fn lines(&self) -> Vec<&str> {
+        let mut lines = vec![];
+        let mut start = 0;
+        let mut chars = self.0.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            let end = match c {
+                '\r' => match chars.peek() {
+                    Some((_, '\n')) => {
+                        chars.next();
+                        index + 2
+                    }
+                    _ => index + 1,
+                },
+                '\n' | '\u{0b}' | '\u{0c}' | '\u{1c}' | '\u{1d}' | '\u{1e}' | '\u{85}'
+                | '\u{2028}' | '\u{2029}' => index + c.len_utf8(),
+                _ => continue,
+            };
+            lines.push(&self.0[start..end]);
+            start = end;
+        }
+        if start < self.0.len() {
+            lines.push(&self.0[start..]);
+        }
+        lines
+    }
+
+    /// Appends stream text the way a terminal shows it: a `\r` returns to
+    /// the start of the line, so progress bars redrawn with it collapse to
+    /// their latest state.
+    ///
+    /// The last line keeps its final `\r` and what follows, so that text
+    /// appended later still overwrites it from the right place.
+    pub fn push_stream(&mut self, text: &str) {
+        let start = self.0.rfind('\n').map_or(0, |index| index + 1);
+        let tail = collapse_carriage_returns(&format!("{}{text}", &self.0[start..]), true);
+        self.0.replace_range(start.., &tail);
+    }
+
+    /// The text as a terminal would show it, with every `\r` applied.
+    pub fn collapse_carriage_returns(&self) -> String {
+        collapse_carriage_returns(&self.0, false)
+    }
+}
+
+/// Overwrites each line with the text following its carriage returns.
+///
+/// With `keep_pending`, an unterminated last line keeps its final `\r` and
+/// the text after it.
+fn collapse_carriage_returns(text: &str, keep_pending: bool) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (body, newline) = match line.strip_suffix('\n') {
+            Some(body) => (body, true),
+            None => (line, false),
+        };
+        let mut segments: Vec<&str> = body.split('\r').collect();
+        let pending = if keep_pending && !newline && segments.len() > 1 {
+            segments.pop()
+        } else {
+            None
+        };
+        let mut shown: Vec<char> = vec![];
+        for segment in segments {
+            for (column, c) in segment.chars().enumerate() {
+                match shown.get_mut(column) {
+                    Some(old) => *old = c,
+                    None => shown.push(c),
+                }
+            }
+        }
+        collapsed.extend(shown);
+        if let Some(pending) = pending {
+            collapsed.push('\r');
+            collapsed.push_str(pending);
+        }
+        if newline {
+            collapsed.push('\n');
+        }
+    }
+    collapsed
+}
+
+impl Deref for MultilineString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MultilineString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for MultilineString {
+    fn from(s: String) -> Self {
+        Self(s)
+    }
+}
+
+impl From<&str> for MultilineString {
+    fn from(s: &str) -> Self {
+        Self(s.to_string())
+    }
+}
+
+/// Collecting concatenates the pieces, as nbformat does for list values.
+impl<S: Into<String>> FromIterator<S> for MultilineString {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl Serialize for MultilineString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.lines())
+    }
+}
+
+impl<'de> Deserialize<'de> for MultilineString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            One(String),
+            Many(Vec<String>),
+        }
+
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::One(s) => Self(s),
+            Repr::Many(lines) => Self(lines.concat()),
+        })
+    }
+}
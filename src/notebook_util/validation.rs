@@ -0,0 +1,289 @@
+//! Validation of notebook JSON against the nbformat 4.x schemas.
+//!
+//! The schemas are bundled with the binary, so validation works offline.
+//! Every violation is reported with a readable JSON path such as
+//! `cells[12].outputs[0].data`. [`repair`] fixes the common problems that
+//! Jupyter itself tolerates, for lenient loading.
+
+use jsonschema::Validator;
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::paths::{Location, LocationSegment};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+const SCHEMA_V4_4: &str = include_str!("schemas/nbformat.v4.4.schema.json");
+const SCHEMA_V4_5: &str = include_str!("schemas/nbformat.v4.5.schema.json");
+
+/// Definitions that the `cell` and `output` `oneOf`s dispatch to.
+const PARTS: [&str; 7] = [
+    "raw_cell",
+    "markdown_cell",
+    "code_cell",
+    "execute_result",
+    "display_data",
+    "stream",
+    "error",
+];
+
+/// How strictly a notebook is checked when it is loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Refuse to load notebooks with any schema violation.
+    Strict,
+    /// Repair common problems (see [`repair`]) and load whatever parses.
+    #[default]
+    Lenient,
+}
+
+/// A single schema violation, located by a path into the notebook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Path such as `cells[12].outputs[0].data`; empty for the root.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "<root>: {}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// All violations found in a notebook, usable as an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violations(pub Vec<Violation>);
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "notebook has {} schema violation(s)", self.0.len())?;
+        for violation in &self.0 {
+            write!(f, "\n  {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Violations {}
+
+/// A compiled schema plus one validator per cell and output definition.
+struct SchemaSet {
+    root: Validator,
+    parts: HashMap<&'static str, Validator>,
+}
+
+impl SchemaSet {
+    fn new(mut schema: Value) -> Self {
+        let root = jsonschema::draft4::new(&schema).expect("bundled nbformat schema is valid");
+        let definitions = schema["definitions"].take();
+        let parts = PARTS
+            .into_iter()
+            .map(|part| {
+                let sub = json!({
+                    "$schema": schema["$schema"],
+                    "$ref": format!("#/definitions/{part}"),
+                    "definitions": definitions,
+                });
+                let validator =
+                    jsonschema::draft4::new(&sub).expect("bundled nbformat schema is valid");
+                (part, validator)
+            })
+            .collect();
+        Self { root, parts }
+    }
+
+    /// Picks the definition a cell or output object should be checked
+    /// against, based on its `cell_type` or `output_type`.
+    ///
+    /// Returns `None` for values that are neither, and an error message for
+    /// unknown types.
+    fn part_for(&self, instance: &Value) -> Option<Result<&Validator, String>> {
+        let (key, name) = if let Some(tag) = instance.get("cell_type") {
+            ("cell_type", tag.as_str().map(|tag| format!("{tag}_cell")))
+        } else if let Some(tag) = instance.get("output_type") {
+            ("output_type", tag.as_str().map(str::to_string))
+        } else {
+            return None;
+        };
+        Some(
+            name.and_then(|name| self.parts.get(name.as_str()))
+                .ok_or_else(|| format!("unknown {key} {}", instance[key])),
+        )
+    }
+
+    /// Validates `instance`, appending violations under the `prefix` path.
+    ///
+    /// A failed `oneOf` on a cell or output only says that no variant
+    /// matched, so those are re-checked against the variant named by the
+    /// type tag to report the actual problem.
+    fn collect(
+        &self,
+        validator: &Validator,
+        instance: &Value,
+        prefix: &str,
+        out: &mut Vec<Violation>,
+    ) {
+        for error in validator.iter_errors(instance) {
+            let path = join_path(prefix, error.instance_path());
+            if let ValidationErrorKind::OneOfNotValid { .. } = error.kind()
+                && let Some(part) = self.part_for(error.instance())
+            {
+                match part {
+                    Ok(part) => self.collect(part, error.instance(), &path, out),
+                    Err(message) => out.push(Violation { path, message }),
+                }
+                continue;
+            }
+            out.push(Violation {
+                path,
+                message: error.masked().to_string(),
+            });
+        }
+    }
+}
+
+fn schema_v4_4() -> &'static SchemaSet {
+    static SET: OnceLock<SchemaSet> = OnceLock::new();
+    SET.get_or_init(|| {
+        let mut schema: Value = serde_json::from_str(SCHEMA_V4_4).expect("bundled schema is JSON");
+        // 4.0 to 4.3 only differ from 4.4 by additions, so the 4.4 schema
+        // also serves them once it stops rejecting their minor version.
+        schema["properties"]["nbformat_minor"]["minimum"] = json!(0);
+        SchemaSet::new(schema)
+    })
+}
+
+fn schema_v4_5() -> &'static SchemaSet {
+    static SET: OnceLock<SchemaSet> = OnceLock::new();
+    SET.get_or_init(|| {
+        SchemaSet::new(serde_json::from_str(SCHEMA_V4_5).expect("bundled schema is JSON"))
+    })
+}
+
+/// Appends a schema location to a readable path, e.g. `cells[3]` +
+/// `/outputs/0/data/text~1plain` gives `cells[3].outputs[0].data["text/plain"]`.
+fn join_path(prefix: &str, location: &Location) -> String {
+    let mut path = prefix.to_string();
+    for segment in location.iter() {
+        match segment {
+            LocationSegment::Index(index) => path.push_str(&format!("[{index}]")),
+            LocationSegment::Property(key) => {
+                let plain = key.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                    && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !plain {
+                    path.push_str(&format!("[{}]", Value::from(key.as_ref())));
+                } else if path.is_empty() {
+                    path.push_str(&key);
+                } else {
+                    path.push('.');
+                    path.push_str(&key);
+                }
+            }
+        }
+    }
+    path
+}
+
+/// Checks notebook JSON against the schema for its nbformat version and
+/// returns every violation found.
+///
+/// Notebooks declaring nbformat 4.5 or later are checked against the 4.5
+/// schema, older 4.x ones against 4.4.
+pub fn validate(notebook: &Value) -> Vec<Violation> {
+    let set = match notebook.get("nbformat").and_then(Value::as_u64) {
+        Some(4) | None => match notebook.get("nbformat_minor").and_then(Value::as_u64) {
+            Some(minor) if minor < 5 => schema_v4_4(),
+            _ => schema_v4_5(),
+        },
+        Some(major) => {
+            return vec![Violation {
+                path: "nbformat".to_string(),
+                message: format!("unsupported nbformat version {major}, expected 4"),
+            }];
+        }
+    };
+    let mut violations = vec![];
+    set.collect(&set.root, notebook, "", &mut violations);
+    violations
+}
+
+/// Sets `object[key]` to `default` when it is missing, or null where null
+/// is not allowed, and records the repair.
+fn fill(
+    object: &mut serde_json::Map<String, Value>,
+    path: &str,
+    key: &str,
+    default: Value,
+    repaired: &mut Vec<Violation>,
+) {
+    let message = match object.get(key) {
+        None => format!("added missing `{key}` as {default}"),
+        Some(Value::Null) if !default.is_null() => format!("replaced null `{key}` with {default}"),
+        Some(_) => return,
+    };
+    object.insert(key.to_string(), default);
+    repaired.push(Violation {
+        path: path.to_string(),
+        message,
+    });
+}
+
+/// Fixes common, harmless problems in notebook JSON in place: missing or
+/// null `metadata`, `source`, `outputs`, `execution_count`, output `data`
+/// and `traceback`.
+///
+/// Returns the violations that were repaired.
+pub fn repair(notebook: &mut Value) -> Vec<Violation> {
+    let mut repaired = vec![];
+    let Some(root) = notebook.as_object_mut() else {
+        return repaired;
+    };
+
+    fill(root, "", "metadata", json!({}), &mut repaired);
+    fill(root, "", "cells", json!([]), &mut repaired);
+    let Some(cells) = root.get_mut("cells").and_then(Value::as_array_mut) else {
+        return repaired;
+    };
+    for (i, cell) in cells.iter_mut().enumerate() {
+        let Some(cell) = cell.as_object_mut() else {
+            continue;
+        };
+        let path = format!("cells[{i}]");
+        fill(cell, &path, "metadata", json!({}), &mut repaired);
+        fill(cell, &path, "source", json!(""), &mut repaired);
+        if cell.get("cell_type").and_then(Value::as_str) != Some("code") {
+            continue;
+        }
+        fill(cell, &path, "execution_count", Value::Null, &mut repaired);
+        fill(cell, &path, "outputs", json!([]), &mut repaired);
+        let Some(outputs) = cell.get_mut("outputs").and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for (j, output) in outputs.iter_mut().enumerate() {
+            let Some(output) = output.as_object_mut() else {
+                continue;
+            };
+            let path = format!("{path}.outputs[{j}]");
+            match output.get("output_type").and_then(Value::as_str) {
+                Some("execute_result") => {
+                    fill(output, &path, "data", json!({}), &mut repaired);
+                    fill(output, &path, "metadata", json!({}), &mut repaired);
+                    fill(output, &path, "execution_count", Value::Null, &mut repaired);
+                }
+                Some("display_data") => {
+                    fill(output, &path, "data", json!({}), &mut repaired);
+                    fill(output, &path, "metadata", json!({}), &mut repaired);
+                }
+                Some("stream") => fill(output, &path, "text", json!(""), &mut repaired),
+                Some("error") => fill(output, &path, "traceback", json!([]), &mut repaired),
+                _ => {}
+            }
+        }
+    }
+    repaired
+}
@@ -0,0 +1,159 @@
+//! JSON writer that reproduces the on-disk layout of `nbformat.write`.
+//!
+//! Jupyter serializes notebooks with `json.dumps(nb, sort_keys=True,
+//! indent=1, ensure_ascii=False, separators=(",", ": "))` and appends a
+//! trailing newline. Matching that byte for byte keeps untouched notebooks
+//! from producing diffs when saved from nbterm.
+
+use serde_json::{Number, Value};
+use std::fmt::Write;
+
+/// Renders a JSON value the way `nbformat.write` does, including the
+/// trailing newline.
+pub fn to_nbformat_string(value: &Value) -> String {
+    to_nbformat_string_with(value, &|_| None)
+}
+
+/// Renders a JSON value like [`to_nbformat_string`], writing the value
+/// `replace` returns for a node instead of the node when there is one.
+pub(crate) fn to_nbformat_string_with(
+    value: &Value,
+    replace: &dyn Fn(&Value) -> Option<Value>,
+) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value, 0, replace);
+    out.push('\n');
+    out
+}
+
+fn write_value(
+    out: &mut String,
+    value: &Value,
+    depth: usize,
+    replace: &dyn Fn(&Value) -> Option<Value>,
+) {
+    if let Some(replacement) = replace(value) {
+        write_value(out, &replacement, depth, &|_| None);
+        return;
+    }
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(out, n),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => {
+            if items.is_empty() {
+                out.push_str("[]");
+                return;
+            }
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                write_value(out, item, depth + 1, replace);
+            }
+            newline(out, depth);
+            out.push(']');
+        }
+        Value::Object(map) => {
+            if map.is_empty() {
+                out.push_str("{}");
+                return;
+            }
+            // Python sorts by code point, which is the same as UTF-8 byte order.
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                write_string(out, key);
+                out.push_str(": ");
+                write_value(out, item, depth + 1, replace);
+            }
+            newline(out, depth);
+            out.push('}');
+        }
+    }
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    out.extend(std::iter::repeat_n(' ', depth));
+}
+
+/// Escapes a string like Python's `json` module with `ensure_ascii=False`:
+/// only quotes, backslashes and control characters are escaped.
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if c < '\u{20}' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(out: &mut String, n: &Number) {
+    match n.as_f64() {
+        Some(f) if n.is_f64() => write_float(out, f),
+        _ => out.push_str(&n.to_string()),
+    }
+}
+
+/// Formats a float like Python's `repr`: shortest round-tripping digits,
+/// positional notation for exponents in `-4..16`, scientific otherwise.
+fn write_float(out: &mut String, f: f64) {
+    // `{:e}` yields the shortest round-tripping digits, e.g. `-1.25e-7`.
+    let sci = format!("{f:e}");
+    let (mantissa, exponent) = sci.split_once('e').unwrap_or((&sci, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", mantissa),
+    };
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+
+    out.push_str(sign);
+    if (-4..16).contains(&exponent) {
+        let point = exponent + 1;
+        if point <= 0 {
+            out.push_str("0.");
+            out.extend(std::iter::repeat_n('0', (-point) as usize));
+            out.push_str(&digits);
+        } else if point as usize >= digits.len() {
+            out.push_str(&digits);
+            out.extend(std::iter::repeat_n('0', point as usize - digits.len()));
+            out.push_str(".0");
+        } else {
+            let (int, frac) = digits.split_at(point as usize);
+            let _ = write!(out, "{int}.{frac}");
+        }
+    } else {
+        let (first, rest) = digits.split_at(1);
+        out.push_str(first);
+        if !rest.is_empty() {
+            let _ = write!(out, ".{rest}");
+        }
+        let _ = write!(
+            out,
+            "e{}{:02}",
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        );
+    }
+}
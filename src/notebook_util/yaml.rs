@@ -0,0 +1,168 @@
+//! The small subset of YAML used in text notebook headers.
+//!
+//! Headers written by Jupytext and similar tools only hold nested mappings
+//! of scalars, so that is all this handles: block mappings, block lists of
+//! scalars, plain and quoted scalars, and JSON-style flow values. Anything
+//! else is read as a plain string rather than rejected.
+
+use serde_json::{Map, Value};
+
+/// Parses a YAML document into JSON.
+pub(crate) fn parse(text: &str) -> Value {
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|line| {
+            let content = line.trim_start();
+            (line.len() - content.len(), content.trim_end())
+        })
+        .collect();
+    let mut pos = 0;
+    match lines.first() {
+        Some(&(indent, _)) => parse_block(&lines, &mut pos, indent),
+        None => Value::Object(Map::new()),
+    }
+}
+
+/// Renders a JSON object as a YAML block mapping. Nested objects become
+/// nested mappings; lists and other values are written in flow style.
+pub(crate) fn render(map: &Map<String, Value>) -> String {
+    let mut out = String::new();
+    render_map(&mut out, map, 0);
+    out
+}
+
+fn parse_block(lines: &[(usize, &str)], pos: &mut usize, indent: usize) -> Value {
+    let mut map = Map::new();
+    let mut list = vec![];
+    while let Some(&(line_indent, text)) = lines.get(*pos) {
+        if line_indent < indent {
+            break;
+        }
+        *pos += 1;
+        if let Some(item) = text.strip_prefix("- ").or((text == "-").then_some("")) {
+            list.push(match item.trim() {
+                "" => parse_nested(lines, pos, line_indent),
+                item => scalar(item),
+            });
+        } else if let Some((key, value)) = split_key(text) {
+            let value = match value {
+                "" => parse_nested(lines, pos, line_indent),
+                value => scalar(value),
+            };
+            let key = match scalar(key) {
+                Value::String(key) => key,
+                key => key.to_string(),
+            };
+            map.insert(key, value);
+        }
+    }
+    if map.is_empty() && !list.is_empty() {
+        Value::Array(list)
+    } else {
+        Value::Object(map)
+    }
+}
+
+/// Parses the value of a `key:` line with nothing after the colon, which is
+/// either an indented block or a list at the key's own indentation.
+fn parse_nested(lines: &[(usize, &str)], pos: &mut usize, parent_indent: usize) -> Value {
+    match lines.get(*pos) {
+        Some(&(indent, _)) if indent > parent_indent => parse_block(lines, pos, indent),
+        Some(&(indent, text)) if indent == parent_indent && text.starts_with('-') => {
+            let mut list = vec![];
+            while let Some(&(indent, text)) = lines.get(*pos) {
+                let Some(item) = text.strip_prefix('-').filter(|_| indent == parent_indent) else {
+                    break;
+                };
+                *pos += 1;
+                list.push(scalar(item.trim()));
+            }
+            Value::Array(list)
+        }
+        _ => Value::Null,
+    }
+}
+
+/// Splits `key: value` (or a bare `key:`) at the separating colon.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if let Some(key) = text.strip_suffix(':') {
+        return Some((key.trim(), ""));
+    }
+    let (key, value) = text.split_once(": ")?;
+    Some((key.trim(), value.trim()))
+}
+
+/// Parses a single YAML value written on one line.
+pub(crate) fn scalar(text: &str) -> Value {
+    if let Some(inner) = text
+        .strip_prefix('\'')
+        .and_then(|text| text.strip_suffix('\''))
+    {
+        return Value::String(inner.replace("''", "'"));
+    }
+    match text {
+        "~" | "null" | "Null" | "NULL" => Value::Null,
+        "True" | "TRUE" => Value::Bool(true),
+        "False" | "FALSE" => Value::Bool(false),
+        _ => serde_json::from_str(text).unwrap_or_else(|_| flow_list(text)),
+    }
+}
+
+/// Parses a flow list of plain scalars such as `[hide-input, remove-output]`,
+/// which is not JSON.
+fn flow_list(text: &str) -> Value {
+    match text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        Some(items) if !items.contains(['[', '{']) => Value::Array(
+            items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(scalar)
+                .collect(),
+        ),
+        _ => Value::String(text.to_string()),
+    }
+}
+
+fn render_map(out: &mut String, map: &Map<String, Value>, depth: usize) {
+    for (key, value) in map {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&render_scalar(&Value::String(key.clone())));
+        match value {
+            Value::Object(inner) if !inner.is_empty() => {
+                out.push_str(":\n");
+                render_map(out, inner, depth + 1);
+            }
+            value => {
+                out.push_str(": ");
+                out.push_str(&render_scalar(value));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+/// Writes strings plain where YAML reads them back unchanged, and
+/// everything else as JSON, which YAML accepts in flow style.
+pub(crate) fn render_scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) if is_plain(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn is_plain(s: &str) -> bool {
+    let Some(first) = s.chars().next() else {
+        return false;
+    };
+    !"-?:,[]{}#&*!|>'\"%@`~ ".contains(first)
+        && !s.ends_with([' ', ':'])
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.contains(['\n', '\r', '\t'])
+        && scalar(s) == Value::String(s.to_string())
+}
@@ -1,12 +1,29 @@
 use super::{
-    editor_tab::EditorTab, file_picker::FilePicker, input_mode::InputMode, outliner::Outliner,
-    settings::Settings, variables_viewer::VariablesViewer,
+    completion_menu::CompletionMenu,
+    diff_view::DiffView,
+    editor_commands::EditorCommand,
+    editor_tab::EditorTab,
+    event_translator::{EventTranslator, Overlay},
+    file_picker::FilePicker,
+    input_mode::InputMode,
+    input_prompt::InputPrompt,
+    kernel_picker::KernelPicker,
+    outliner::Outliner,
+    output_view::OutputView,
+    settings::Settings,
+    variables_viewer::VariablesViewer,
 };
 
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    terminal::{
+        EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+        supports_keyboard_enhancement,
+    },
 };
 
 use ratatui::{
@@ -15,7 +32,15 @@ use ratatui::{
     prelude::{CrosstermBackend, Widget},
 };
 
+use crate::kernel::KernelManager;
+use crate::notebook_util::format::FormatRegistry;
 use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How long a cell may wait for an answer to `input()` before the kernel
+/// is interrupted.
+const INPUT_TIMEOUT: Duration = Duration::from_secs(300);
 
 pub struct NotebookApp {
     pub(crate) left_pane_mode: Option<LeftPaneMode>,
@@ -25,9 +50,25 @@ pub struct NotebookApp {
     pub(crate) outliner: Outliner,
     pub(crate) variables: VariablesViewer,
     pub(crate) settings: Settings,
+    pub(crate) formats: FormatRegistry,
     pub(crate) tabs: Vec<EditorTab>,
     pub(crate) tab_selected: usize,
+    /// Changes of the current tab since it was saved, shown instead of the
+    /// editor while open.
+    pub(crate) diff_view: Option<DiffView>,
     pub(crate) input_mode: InputMode,
+    pub(crate) translator: EventTranslator,
+    /// The answer being typed for the current tab's running cell, while it
+    /// waits for input.
+    pub(crate) input_prompt: Option<InputPrompt>,
+    /// The filter of the variables pane, while it is being typed.
+    pub(crate) filter_prompt: Option<InputPrompt>,
+    /// The kernels to switch the current tab to, while picking one.
+    pub(crate) kernel_picker: Option<KernelPicker>,
+    /// The completions offered at the cursor, while typing in a code cell.
+    pub(crate) completion_menu: Option<CompletionMenu>,
+    /// Result of the last command, shown in the status bar.
+    pub(crate) status_message: Option<String>,
     pub(crate) leaving: bool,
 }
 
@@ -41,9 +82,17 @@ impl Default for NotebookApp {
             outliner: Outliner::default(),
             variables: VariablesViewer::default(),
             settings: Settings::default(),
+            formats: FormatRegistry::default(),
             tabs: vec![EditorTab::default()],
             tab_selected: 0,
+            diff_view: None,
             input_mode: InputMode::default(),
+            translator: EventTranslator::default(),
+            input_prompt: None,
+            filter_prompt: None,
+            kernel_picker: None,
+            completion_menu: None,
+            status_message: None,
             leaving: false,
         }
     }
@@ -64,16 +113,78 @@ pub enum RightPaneMode {
 }
 
 impl NotebookApp {
+    /// An app showing the notebook at `path`, or a new notebook to be
+    /// saved there if the file doesn't exist yet.
+    pub fn with_file(path: PathBuf) -> anyhow::Result<Self> {
+        let mut app = Self::default();
+        let tab = if path.exists() {
+            EditorTab::open(path, &app.formats)?
+        } else {
+            EditorTab::new(path, &app.formats)
+        };
+        app.tabs = vec![tab];
+        Ok(app)
+    }
+
+    /// The tab being edited.
+    pub fn current_tab(&self) -> Option<&EditorTab> {
+        self.tabs.get(self.tab_selected)
+    }
+
+    pub fn current_tab_mut(&mut self) -> Option<&mut EditorTab> {
+        self.tabs.get_mut(self.tab_selected)
+    }
+
+    /// The changes of the current tab, while the diff view is open.
+    pub fn diff_view(&self) -> Option<&DiffView> {
+        self.diff_view.as_ref()
+    }
+
+    /// The variables pane, whether it is shown or not.
+    pub fn variables(&self) -> &VariablesViewer {
+        &self.variables
+    }
+
+    pub fn input_mode(&self) -> &InputMode {
+        &self.input_mode
+    }
+
+    /// The installed kernels offered, while picking one for the current tab.
+    pub fn kernel_picker(&self) -> Option<&KernelPicker> {
+        self.kernel_picker.as_ref()
+    }
+
     pub fn run(&mut self) -> io::Result<()> {
+        // the settings of the notebook's project, wherever nbterm started
+        let dir = match self.tabs.get(self.tab_selected) {
+            Some(tab) => std::path::absolute(tab.working_dir())?,
+            None => std::env::current_dir()?,
+        };
+        match Settings::discover(&dir) {
+            Ok(settings) => self.settings = settings,
+            Err(err) => self.status_message = Some(format!("{err:#}")),
+        }
+
         enable_raw_mode()?;
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        // tells Ctrl-Enter and Shift-Enter from Enter, where the terminal can
+        let enhanced = supports_keyboard_enhancement().unwrap_or(false);
+        if enhanced {
+            execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )?;
+        }
 
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
 
         let res = self.ui_loop(&mut terminal);
 
+        if enhanced {
+            execute!(terminal.backend_mut(), PopKeyboardEnhancementFlags)?;
+        }
         disable_raw_mode()?;
         execute!(
             terminal.backend_mut(),
@@ -85,16 +196,144 @@ impl NotebookApp {
         res
     }
 
+    /// Shuts down the kernels started for the tabs, all at once.
     fn cleanup(&mut self) -> io::Result<()> {
-        // TODO: Implement any necessary cleanup logic here
+        let shutdowns: Vec<_> = self
+            .tabs
+            .iter_mut()
+            .filter_map(|tab| tab.kernel.take())
+            .map(KernelManager::shutdown_in_background)
+            .collect();
+        for shutdown in shutdowns {
+            let _ = shutdown.join();
+        }
         Ok(())
     }
 
+    /// Lets the kernels of all tabs make progress on their queued cells,
+    /// shows the current tab's variables and completions, and opens the
+    /// input prompt when its running cell asks for input.
+    fn poll_kernels(&mut self) {
+        for tab in &mut self.tabs {
+            if let Err(err) = tab.poll_kernel() {
+                tab.execution.reset();
+                self.status_message = Some(format!("{err:#}"));
+            }
+            if tab
+                .execution
+                .input_request()
+                .is_some_and(|request| request.since.elapsed() > INPUT_TIMEOUT)
+            {
+                self.status_message = Some(match tab.cancel_input() {
+                    Ok(()) => "Input timed out, interrupted the kernel".to_string(),
+                    Err(err) => format!("{err:#}"),
+                });
+            }
+        }
+        if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+            self.variables
+                .set_variables(&tab.variables.variables, tab.variables.error.as_deref());
+            // completions for code that was edited since are stale
+            if let Some(reply) = tab.completer.take_reply()
+                && tab
+                    .content
+                    .cells
+                    .get(tab.current_cell)
+                    .map(|cell| cell.source().as_str())
+                    == Some(reply.code.as_str())
+                && tab.cursor_in_cell() == reply.cursor
+            {
+                self.completion_menu = CompletionMenu::new(reply.completions);
+            }
+        }
+        let request = self
+            .tabs
+            .get(self.tab_selected)
+            .and_then(|tab| tab.execution.input_request());
+        match (request, &self.input_prompt) {
+            (Some(request), None) => {
+                self.input_prompt = Some(InputPrompt::new(&request.prompt, request.password));
+            }
+            (None, Some(_)) => self.input_prompt = None,
+            _ => {}
+        }
+    }
+
+    /// Handles a terminal event: a key runs the command it is bound to in
+    /// the overlay open over the editor or else in the input mode. Keys the
+    /// completion menu has no use for close it on their way to the editor.
+    pub fn handle_event(&mut self, event: Event) {
+        let overlay = self.overlay();
+        let command = self
+            .translator
+            .translate_event(event, overlay, &self.input_mode);
+        if overlay == Some(Overlay::Completions)
+            && !matches!(
+                command,
+                Some(
+                    EditorCommand::AcceptCompletion
+                        | EditorCommand::ToNextCompletion
+                        | EditorCommand::ToPreviousCompletion
+                        | EditorCommand::CloseCompletions
+                )
+            )
+        {
+            self.completion_menu = None;
+        }
+        if let Some(command) = command {
+            self.execute_command(command);
+        }
+    }
+
+    /// What gets the keys before the editor: a prompt, then the
+    /// kernel picker, then the completion menu, then the diff view outside
+    /// of insert mode.
+    fn overlay(&self) -> Option<Overlay> {
+        if self.input_prompt.is_some() || self.filter_prompt.is_some() {
+            Some(Overlay::Prompt)
+        } else if self.kernel_picker.is_some() {
+            Some(Overlay::Picker)
+        } else if self.completion_menu.is_some() {
+            Some(Overlay::Completions)
+        } else if self.diff_view.is_some() && !matches!(self.input_mode, InputMode::Insert) {
+            Some(Overlay::Diff)
+        } else {
+            None
+        }
+    }
+
+    /// What the kernel of the current tab is doing, e.g.
+    /// `Python 3: running cell 4, 2 queued`.
+    fn kernel_status(&self) -> Option<String> {
+        let tab = self.tabs.get(self.tab_selected)?;
+        let kernel = tab.kernel.as_ref()?;
+        let name = &kernel.spec.display_name;
+        if kernel.is_connecting() {
+            return Some(format!("{name}: starting"));
+        }
+        let Some(running) = tab.execution.running() else {
+            return Some(format!("{name}: idle"));
+        };
+        let index = tab
+            .content
+            .iter()
+            .position(|cell| cell.id() == Some(running))
+            .map_or_else(
+                || "a deleted cell".to_string(),
+                |index| format!("cell {index}"),
+            );
+        Some(match tab.execution.queued() {
+            0 => format!("{name}: running {index}"),
+            queued => format!("{name}: running {index}, {queued} queued"),
+        })
+    }
+
     fn ui_loop<B: ratatui::backend::Backend>(
         &mut self,
         terminal: &mut Terminal<B>,
     ) -> io::Result<()> {
         while !self.leaving {
+            self.poll_kernels();
             terminal.draw(|f| {
                 let area = f.area();
 
@@ -198,13 +437,75 @@ impl NotebookApp {
                 } else {
                     main_content_layout[0]
                 };
-                let editor_widget = ratatui::widgets::Paragraph::new("Editor Content Placeholder")
-                    .block(
-                        ratatui::widgets::Block::default()
-                            .borders(ratatui::widgets::Borders::ALL)
-                            .title("Editor"),
-                    );
-                editor_widget.render(editor_area, f.buffer_mut());
+                // the input prompt goes below the running cell's output, as
+                // does the variables filter
+                let prompt = self.input_prompt.as_ref().or(self.filter_prompt.as_ref());
+                let editor_area = match prompt {
+                    Some(prompt) => {
+                        let [editor_area, prompt_area] = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Min(0), Constraint::Length(3)])
+                            .areas(editor_area);
+                        prompt.render(prompt_area, f.buffer_mut());
+                        editor_area
+                    }
+                    None => editor_area,
+                };
+                // and so does the kernel picker
+                let editor_area = match &self.kernel_picker {
+                    Some(picker) => {
+                        let [editor_area, picker_area] = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Min(0), Constraint::Length(picker.height())])
+                            .areas(editor_area);
+                        picker.render(picker_area, f.buffer_mut());
+                        editor_area
+                    }
+                    None => editor_area,
+                };
+                // the completion menu goes below the code being typed
+                let editor_area = match &self.completion_menu {
+                    Some(menu) => {
+                        let [editor_area, menu_area] = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([Constraint::Min(0), Constraint::Length(menu.height())])
+                            .areas(editor_area);
+                        menu.render(menu_area, f.buffer_mut());
+                        editor_area
+                    }
+                    None => editor_area,
+                };
+                // the outputs go below the editor, at most half of it
+                let output_view = self
+                    .tabs
+                    .get(self.tab_selected)
+                    .filter(|_| self.diff_view.is_none())
+                    .and_then(OutputView::new);
+                let editor_area = match &output_view {
+                    Some(view) => {
+                        let [editor_area, output_area] = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([
+                                Constraint::Min(0),
+                                Constraint::Length(view.height(editor_area.height / 2)),
+                            ])
+                            .areas(editor_area);
+                        view.render(output_area, f.buffer_mut());
+                        editor_area
+                    }
+                    None => editor_area,
+                };
+                if let Some(diff_view) = &self.diff_view {
+                    diff_view.render(editor_area, f.buffer_mut());
+                } else {
+                    let editor_widget =
+                        ratatui::widgets::Paragraph::new("Editor Content Placeholder").block(
+                            ratatui::widgets::Block::default()
+                                .borders(ratatui::widgets::Borders::ALL)
+                                .title("Editor"),
+                        );
+                    editor_widget.render(editor_area, f.buffer_mut());
+                }
 
                 // Draw the right pane if it is enabled
                 if let Some(right_mode) = &self.right_pane_mode {
@@ -215,7 +516,6 @@ impl NotebookApp {
                                 .render(*main_section_layout.last().unwrap(), f.buffer_mut());
                         }
                         RightPaneMode::Variables => {
-                            // Placeholder for variables pane
                             self.variables
                                 .render(*main_section_layout.last().unwrap(), f.buffer_mut());
                         }
@@ -225,28 +525,34 @@ impl NotebookApp {
                 // Draw the status bar at the bottom
                 // This is a simple status bar showing the current input mode
                 let status_bar_area = terminal_layout[1];
-                let status_bar_widget = ratatui::widgets::Paragraph::new(format!(
-                    "Input Mode: {} | Press 'q' to quit",
-                    self.input_mode
-                ))
-                .style(
-                    ratatui::style::Style::default()
-                        .fg(ratatui::style::Color::White)
-                        .bg(ratatui::style::Color::DarkGray),
-                );
+                let mut status = format!("Input Mode: {}", self.input_mode);
+                if let Some(kernel) = self.kernel_status() {
+                    status = format!("{status} | {kernel}");
+                }
+                let status_bar_widget =
+                    ratatui::widgets::Paragraph::new(match &self.status_message {
+                        Some(message) => format!("{status} | {message}"),
+                        None => format!("{status} | Press 'q' to quit"),
+                    })
+                    .style(
+                        ratatui::style::Style::default()
+                            .fg(ratatui::style::Color::White)
+                            .bg(ratatui::style::Color::DarkGray),
+                    );
                 status_bar_widget.render(status_bar_area, f.buffer_mut());
             })?;
 
-            // Handle input
-            if event::poll(std::time::Duration::from_millis(250))? {
-                if let Event::Key(key) = event::read()? {
-                    match key.code {
-                        event::KeyCode::Char('q') => break Ok(()),
-                        _ => {}
-                    }
-                }
+            // Handle input, waking up sooner while cells run to show their
+            // progress, or completions are due
+            let busy = self
+                .tabs
+                .iter()
+                .any(|tab| tab.execution.is_busy() || tab.completions_scheduled());
+            let timeout = Duration::from_millis(if busy { 50 } else { 250 });
+            if event::poll(timeout)? {
+                self.handle_event(event::read()?);
             }
-        }?;
+        }
         self.cleanup()
     }
 }
@@ -0,0 +1,72 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, StatefulWidget};
+
+use crate::kernel::Completion;
+
+/// How many completions the menu shows at once.
+pub const MENU_HEIGHT: u16 = 8;
+
+/// The completions offered for the code at the cursor, one of them
+/// selected.
+pub struct CompletionMenu {
+    pub completions: Vec<Completion>,
+    pub selected: usize,
+}
+
+impl CompletionMenu {
+    /// A menu offering `completions`, or none if there aren't any.
+    pub fn new(completions: Vec<Completion>) -> Option<Self> {
+        (!completions.is_empty()).then_some(Self {
+            completions,
+            selected: 0,
+        })
+    }
+
+    pub fn selected(&self) -> &Completion {
+        &self.completions[self.selected]
+    }
+
+    /// Selects the next completion, wrapping around.
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.completions.len();
+    }
+
+    /// Selects the previous completion, wrapping around.
+    pub fn select_previous(&mut self) {
+        self.selected = self
+            .selected
+            .checked_sub(1)
+            .unwrap_or(self.completions.len() - 1);
+    }
+
+    /// The rows the menu needs, borders included.
+    pub fn height(&self) -> u16 {
+        (self.completions.len() as u16).min(MENU_HEIGHT) + 2
+    }
+}
+
+impl ratatui::widgets::Widget for &CompletionMenu {
+    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
+        let hint = Style::default().fg(Color::DarkGray);
+        let items = self.completions.iter().map(|completion| {
+            let mut spans = vec![Span::raw(completion.text.as_str())];
+            if let Some(kind) = &completion.kind {
+                spans.push(Span::styled(format!("  {kind}"), hint));
+            }
+            if let Some(signature) = &completion.signature {
+                spans.push(Span::styled(format!("  {signature}"), hint));
+            }
+            ListItem::new(Line::from(spans))
+        });
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Completions (Tab to insert, Esc to close)"),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        StatefulWidget::render(list, area, buf, &mut state);
+    }
+}
@@ -0,0 +1,95 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+
+use crate::notebook_util::diff::{CellStatus, LineDiff, NotebookDiff};
+
+/// Shows a [`NotebookDiff`] in place of the editor, one changed cell after
+/// the other.
+pub struct DiffView {
+    /// What the notebook is compared with, e.g. `saved`.
+    pub title: String,
+    pub diff: NotebookDiff,
+    /// First line shown.
+    pub scroll: u16,
+}
+
+impl DiffView {
+    pub fn new(title: impl Into<String>, diff: NotebookDiff) -> Self {
+        Self {
+            title: title.into(),
+            diff,
+            scroll: 0,
+        }
+    }
+
+    /// Scrolls `lines` further down, stopping at the last line.
+    pub fn scroll_down(&mut self, lines: u16) {
+        let last = self.lines().len().saturating_sub(1);
+        let last = u16::try_from(last).unwrap_or(u16::MAX);
+        self.scroll = self.scroll.saturating_add(lines).min(last);
+    }
+
+    /// Scrolls `lines` back up, stopping at the first line.
+    pub fn scroll_up(&mut self, lines: u16) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    fn lines(&self) -> Vec<Line<'_>> {
+        if self.diff.is_empty() {
+            return vec![Line::from("No changes")];
+        }
+        let mut lines = vec![];
+        if self.diff.metadata_changed {
+            lines.push(Line::styled(
+                "notebook metadata changed",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        }
+        for cell in self.diff.changes() {
+            let color = match cell.status {
+                CellStatus::Added => Color::Green,
+                CellStatus::Removed => Color::Red,
+                CellStatus::Modified | CellStatus::Unchanged => Color::Yellow,
+            };
+            lines.push(Line::styled(
+                cell.summary(),
+                Style::default().fg(color).add_modifier(Modifier::BOLD),
+            ));
+            if cell.status != CellStatus::Unchanged {
+                for line in &cell.source {
+                    lines.push(match line {
+                        LineDiff::Same(text) => Line::from(format!("  {text}")),
+                        LineDiff::Added(text) => {
+                            Line::styled(format!("+ {text}"), Style::default().fg(Color::Green))
+                        }
+                        LineDiff::Removed(text) => {
+                            Line::styled(format!("- {text}"), Style::default().fg(Color::Red))
+                        }
+                    });
+                }
+            }
+            for output in &cell.outputs {
+                lines.push(Line::from(vec![
+                    Span::styled("  output ", Style::default().fg(Color::DarkGray)),
+                    Span::raw(format!("{} {}", output.kind, output.change)),
+                ]));
+            }
+            lines.push(Line::default());
+        }
+        lines
+    }
+}
+
+impl Widget for &DiffView {
+    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
+        Paragraph::new(self.lines())
+            .scroll((self.scroll, 0))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(format!("Diff against {}", self.title)),
+            )
+            .render(area, buf);
+    }
+}
@@ -1,7 +1,22 @@
+use crate::kernel::spec::{find_kernelspecs, match_kernelspec};
+use crate::kernel::{KernelManager, VariableInspector};
+use crate::notebook_util::merge::Resolution;
+use crate::notebook_util::{Cell, CellType};
+use crate::notebook_util::{CellEdit, Notebook, export::HtmlOptions, sanitize::SanitizeRules};
+use anyhow::Context;
 use regex::Regex;
-use std::{path::PathBuf, slice::Iter};
+use serde_json::json;
+use std::ops::Range;
+use std::path::PathBuf;
 
-use super::NotebookApp;
+use super::{
+    NotebookApp, app::RightPaneMode, diff_view::DiffView, editor_tab::EditorTab,
+    input_mode::InputMode, input_prompt::InputPrompt, kernel_picker::KernelPicker,
+    variables_viewer::VariableSort,
+};
+
+/// What Complete inserts where there is no word to complete.
+const INDENT: &str = "    ";
 
 #[derive(Debug, Clone)]
 pub enum EditorCommand {
@@ -18,6 +33,10 @@ pub enum EditorCommand {
     ToggleStatusBar,
     ToggleDiff,
 
+    // Diff view actions, while it replaces the editor
+    ScrollDiffDown(u16), // by this many lines
+    ScrollDiffUp(u16),
+
     // Setting toggle actions
     ToggleLineNumbers,
     ToggleWordWrap,
@@ -33,6 +52,9 @@ pub enum EditorCommand {
     SaveFileAs(PathBuf),
     CloseFile,
     NewFile,
+    ExportHtml(PathBuf, HtmlOptions), // write an HTML report, next to the notebook for an empty path
+    ExportScript(PathBuf),            // write the code as a script, markdown as comments
+    ExportMarkdown(PathBuf),          // write Markdown with outputs, images in `<name>_files/`
 
     // Primitive actions
     Undo,
@@ -70,6 +92,62 @@ pub enum EditorCommand {
     Paste,
     Concatenate,
 
+    // Output and metadata cleanup actions, undoable as a single step each
+    ClearOutputs(CellScope),
+    ResetExecutionCounts(CellScope),
+    StripMetadata(CellScope),
+    StripLargeOutputs(CellScope),
+    Sanitize(CellScope), // run every step enabled in the project's sanitize rules
+
+    // Merge conflict actions, for notebooks merged with conflict cells
+    ToNextConflict,
+    ToPreviousConflict,
+    ResolveConflict(Resolution), // replace the conflict cell under the cursor
+
+    // Kernel actions, for the kernel of the current tab
+    StartKernel,          // start the kernel named in the notebook's metadata
+    SelectKernel(String), // switch to this kernelspec and record it in the metadata
+    PickKernel,           // choose the kernelspec to select among the installed ones
+    ToNextKernel,
+    ToPreviousKernel,
+    InterruptKernel,
+    RestartKernel,
+    ShutdownKernel,
+
+    // Execution actions, queued on the kernel of the current tab
+    RunCell,
+    RunCellAndAdvance, // run the current cell and move to the next, adding one at the end
+    RunAll,
+    RunAbove, // the cells before the current one
+    RunBelow, // the current cell and the ones after it
+    RunSelection,
+    AnswerInput(String), // reply to the running cell's input() prompt
+    CancelInput,         // leave the input() prompt unanswered, interrupting the kernel
+
+    // Prompt actions, for the line typed or the kernel picked below the editor
+    PromptInput(String),
+    PromptBackspace,
+    SubmitPrompt,
+    CancelPrompt,
+
+    // Completion actions, for the code cell being edited
+    Complete, // ask the kernel how to complete the word before the cursor, else indent
+    AcceptCompletion,
+    ToNextCompletion,
+    ToPreviousCompletion,
+    CloseCompletions,
+
+    // Variables pane actions, for the kernel of the current tab
+    ToggleVariables,
+    RefreshVariables,
+    FilterVariables(String), // only list the variables whose name contains this
+    EditVariablesFilter,     // type the filter in a prompt
+    SortVariables(VariableSort),
+    ToNextVariable,
+    ToPreviousVariable,
+    ToggleVariableExpanded, // show or hide the items of the selected container
+    ToggleVariablePinned,   // keep the selected variable at the top, whatever the filter
+
     // Selection actions (for visual modes)
     Skip,     // move cursor without selecting, resulting in multiple selections
     Deselect, // remove selections
@@ -111,14 +189,512 @@ pub enum NavigationCommand {
     ToNextBookmark,
 }
 
+// Which cells a cell-level command applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellScope {
+    Notebook,
+    Selection,
+}
+
 impl NotebookApp {
     pub fn execute_command(&mut self, command: EditorCommand) {
         match command {
-            EditorCommand::Quit => {
-                self.leaving = true;
+            EditorCommand::Quit => self.leaving = true,
+            EditorCommand::Undo => {
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    tab.undo();
+                }
+            }
+            EditorCommand::Redo => {
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    tab.redo();
+                }
+            }
+            EditorCommand::ToggleDiff => {
+                let result = match self.diff_view.take() {
+                    Some(_) => Ok(()),
+                    None => self.show_diff(),
+                };
+                self.report(result);
+            }
+            EditorCommand::ScrollDiffDown(lines) => {
+                if let Some(diff_view) = &mut self.diff_view {
+                    diff_view.scroll_down(lines);
+                }
+            }
+            EditorCommand::ScrollDiffUp(lines) => {
+                if let Some(diff_view) = &mut self.diff_view {
+                    diff_view.scroll_up(lines);
+                }
+            }
+            EditorCommand::ToNextTab if !self.tabs.is_empty() => {
+                self.select_tab((self.tab_selected + 1) % self.tabs.len());
+            }
+            EditorCommand::ToPreviousTab if !self.tabs.is_empty() => {
+                let index = self.tab_selected.checked_sub(1);
+                self.select_tab(index.unwrap_or(self.tabs.len() - 1));
+            }
+            EditorCommand::ToTab(index) if index < self.tabs.len() => self.select_tab(index),
+            EditorCommand::OpenFile(path) => self.open_file(path),
+            EditorCommand::SaveFile => {
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let result = tab.save();
+                    self.report(result);
+                }
+            }
+            EditorCommand::SaveFileAs(path) => {
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let result = tab.save_as(path, &self.formats);
+                    self.report(result);
+                }
+            }
+            EditorCommand::ExportHtml(path, options) => {
+                if let Some(tab) = self.tabs.get(self.tab_selected) {
+                    let path = if path.as_os_str().is_empty() {
+                        tab.path.with_extension("html")
+                    } else {
+                        path
+                    };
+                    let result = tab.content.export_html(path, &options);
+                    self.report(result);
+                }
+            }
+            EditorCommand::ExportScript(path) => {
+                if let Some(tab) = self.tabs.get(self.tab_selected) {
+                    let result = tab.content.export_script(path);
+                    self.report(result);
+                }
+            }
+            EditorCommand::ExportMarkdown(path) => {
+                if let Some(tab) = self.tabs.get(self.tab_selected) {
+                    let result = tab.content.export_markdown_document(path);
+                    self.report(result);
+                }
+            }
+            EditorCommand::ClearOutputs(scope) => {
+                self.edit_cells(scope, |notebook, range, _| notebook.clear_outputs(range));
+            }
+            EditorCommand::ResetExecutionCounts(scope) => {
+                self.edit_cells(scope, |notebook, range, _| {
+                    notebook.reset_execution_counts(range)
+                });
+            }
+            EditorCommand::StripMetadata(scope) => {
+                self.edit_cells(scope, |notebook, range, rules| {
+                    notebook.strip_metadata(range, &rules.drop_cell_metadata)
+                });
+            }
+            EditorCommand::StripLargeOutputs(scope) => {
+                self.edit_cells(scope, |notebook, range, rules| {
+                    notebook.strip_large_outputs(range, rules.max_output_bytes?)
+                });
+            }
+            EditorCommand::Sanitize(scope) => {
+                self.edit_cells(scope, |notebook, range, rules| {
+                    notebook.sanitize(range, rules)
+                });
+            }
+            EditorCommand::ToNextConflict => self.jump_to_conflict(true),
+            EditorCommand::ToPreviousConflict => self.jump_to_conflict(false),
+            EditorCommand::ResolveConflict(resolution) => {
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let index = tab.current_cell;
+                    if tab.edit(|notebook| notebook.resolve_conflict(index, resolution)) {
+                        tab.current_cell = index.min(tab.content.len().saturating_sub(1));
+                    }
+                }
+            }
+            EditorCommand::StartKernel => {
+                let result = self.start_kernel(None);
+                self.report(result);
+            }
+            EditorCommand::SelectKernel(name) => {
+                let result = self.start_kernel(Some(&name));
+                self.report(result);
+            }
+            EditorCommand::PickKernel => {
+                let current = self
+                    .tabs
+                    .get(self.tab_selected)
+                    .and_then(|tab| match &tab.kernel {
+                        Some(kernel) => Some(kernel.spec.name.clone()),
+                        None => Some(tab.content.metadata.kernelspec.as_ref()?.name.clone()),
+                    });
+                self.kernel_picker = KernelPicker::new(find_kernelspecs(), current.as_deref());
+                if self.kernel_picker.is_none() {
+                    self.status_message = Some("No kernels are installed".to_string());
+                }
+            }
+            EditorCommand::ToNextKernel => {
+                if let Some(picker) = &mut self.kernel_picker {
+                    picker.select_next();
+                }
+            }
+            EditorCommand::ToPreviousKernel => {
+                if let Some(picker) = &mut self.kernel_picker {
+                    picker.select_previous();
+                }
+            }
+            EditorCommand::InterruptKernel => {
+                let result = self.with_kernel(|kernel| kernel.interrupt());
+                self.report(result);
+            }
+            EditorCommand::RestartKernel => {
+                let result = self.with_kernel(|kernel| kernel.restart());
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    tab.execution.reset();
+                    tab.variables = VariableInspector::default();
+                }
+                self.report(result);
+            }
+            EditorCommand::ShutdownKernel => {
+                if let Some(kernel) = self
+                    .tabs
+                    .get_mut(self.tab_selected)
+                    .and_then(|tab| tab.kernel.take())
+                {
+                    let tab = &mut self.tabs[self.tab_selected];
+                    tab.execution.reset();
+                    tab.variables = VariableInspector::default();
+                    kernel.shutdown_in_background();
+                }
+            }
+            EditorCommand::RunCell => self.run_cells(|tab| tab.current_cell..tab.current_cell + 1),
+            EditorCommand::RunCellAndAdvance => {
+                self.run_cells(|tab| tab.current_cell..tab.current_cell + 1);
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let next = tab.current_cell + 1;
+                    if next == tab.content.len() {
+                        tab.edit(|notebook| {
+                            let mut cell = Cell::from_parts(CellType::Code, "".into(), json!({}));
+                            *cell.id_mut() = Some(notebook.new_cell_id());
+                            notebook.apply(CellEdit::Insert {
+                                index: next,
+                                cells: vec![cell],
+                            })
+                        });
+                    }
+                    tab.current_cell = next.min(tab.content.len().saturating_sub(1));
+                    tab.selection_anchor = None;
+                }
+            }
+            EditorCommand::RunAll => self.run_cells(|tab| 0..tab.content.len()),
+            EditorCommand::RunAbove => self.run_cells(|tab| 0..tab.current_cell),
+            EditorCommand::RunBelow => self.run_cells(|tab| tab.current_cell..tab.content.len()),
+            EditorCommand::RunSelection => self.run_cells(EditorTab::selected_cells),
+            EditorCommand::AnswerInput(value) => {
+                self.input_prompt = None;
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let result = tab.answer_input(&value);
+                    self.report(result);
+                }
+            }
+            EditorCommand::CancelInput => {
+                self.input_prompt = None;
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let result = tab.cancel_input();
+                    self.report(result);
+                }
+            }
+            EditorCommand::PromptInput(text) => {
+                if let Some(prompt) = self.prompt_mut() {
+                    prompt.value.push_str(&text);
+                }
+            }
+            EditorCommand::PromptBackspace => {
+                if let Some(prompt) = self.prompt_mut() {
+                    prompt.value.pop();
+                }
+            }
+            EditorCommand::SubmitPrompt => {
+                if let Some(prompt) = &mut self.input_prompt {
+                    let value = std::mem::take(&mut prompt.value);
+                    self.execute_command(EditorCommand::AnswerInput(value));
+                } else if let Some(prompt) = self.filter_prompt.take() {
+                    self.execute_command(EditorCommand::FilterVariables(prompt.value));
+                } else if let Some(picker) = self.kernel_picker.take() {
+                    let name = picker.selected().name.clone();
+                    self.execute_command(EditorCommand::SelectKernel(name));
+                }
+            }
+            EditorCommand::CancelPrompt => {
+                if self.input_prompt.is_some() {
+                    self.execute_command(EditorCommand::CancelInput);
+                } else {
+                    self.filter_prompt = None;
+                    self.kernel_picker = None;
+                }
+            }
+            EditorCommand::SwitchToInsertMode => self.input_mode = InputMode::Insert,
+            EditorCommand::SwitchToNormalMode => {
+                self.close_completions();
+                self.input_mode = InputMode::Normal;
             }
+            EditorCommand::ToggleAutoComplete => {
+                self.settings.auto_complete = !self.settings.auto_complete;
+                if !self.settings.auto_complete {
+                    self.close_completions();
+                }
+            }
+            EditorCommand::Input(text) => {
+                let Some(tab) = self.tabs.get_mut(self.tab_selected) else {
+                    return;
+                };
+                if !tab.insert_text(&text) {
+                    return;
+                }
+                if self.settings.auto_complete && tab.cursor_after_word() {
+                    tab.schedule_completions();
+                } else {
+                    self.close_completions();
+                }
+            }
+            EditorCommand::Complete => {
+                let Some(tab) = self.tabs.get_mut(self.tab_selected) else {
+                    return;
+                };
+                if tab.cursor_after_word() {
+                    let result = tab.request_completions();
+                    self.report(result);
+                } else {
+                    self.execute_command(EditorCommand::Input(INDENT.to_string()));
+                }
+            }
+            EditorCommand::AcceptCompletion => {
+                if let (Some(menu), Some(tab)) = (
+                    self.completion_menu.take(),
+                    self.tabs.get_mut(self.tab_selected),
+                ) {
+                    tab.apply_completion(menu.selected());
+                }
+            }
+            EditorCommand::ToNextCompletion => {
+                if let Some(menu) = &mut self.completion_menu {
+                    menu.select_next();
+                }
+            }
+            EditorCommand::ToPreviousCompletion => {
+                if let Some(menu) = &mut self.completion_menu {
+                    menu.select_previous();
+                }
+            }
+            EditorCommand::CloseCompletions => self.close_completions(),
+            EditorCommand::ToggleVariables => {
+                self.right_pane_mode = match self.right_pane_mode {
+                    Some(RightPaneMode::Variables) => None,
+                    _ => Some(RightPaneMode::Variables),
+                };
+                if self.right_pane_mode.is_none() && matches!(self.input_mode, InputMode::UICursor)
+                {
+                    self.input_mode = InputMode::Normal;
+                }
+            }
+            // the keys of the variables pane work while the cursor is in it
+            EditorCommand::ToRightPane => {
+                if matches!(self.right_pane_mode, Some(RightPaneMode::Variables)) {
+                    self.input_mode = InputMode::UICursor;
+                }
+            }
+            EditorCommand::ToLeftPane => {
+                if matches!(self.input_mode, InputMode::UICursor) {
+                    self.input_mode = InputMode::Normal;
+                }
+            }
+            EditorCommand::RefreshVariables => {
+                if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+                    let result = tab.refresh_variables();
+                    self.report(result);
+                }
+            }
+            EditorCommand::FilterVariables(filter) => self.variables.set_filter(filter),
+            EditorCommand::EditVariablesFilter => {
+                let prompt = InputPrompt::new("Filter: ", false).for_value(
+                    "Variables (Enter to filter, Esc to cancel)",
+                    self.variables.filter.as_str(),
+                );
+                self.filter_prompt = Some(prompt);
+            }
+            EditorCommand::SortVariables(sort) => self.variables.set_sort(sort),
+            EditorCommand::ToNextVariable => self.variables.select_next(),
+            EditorCommand::ToPreviousVariable => self.variables.select_previous(),
+            EditorCommand::ToggleVariableExpanded => self.variables.toggle_expanded(),
+            EditorCommand::ToggleVariablePinned => self.variables.toggle_pinned(),
             // Handle other commands...
             _ => {}
         }
     }
+
+    /// Switches to the tab showing `path`, or opens it in a new one.
+    fn open_file(&mut self, path: PathBuf) {
+        if let Some(index) = self.tabs.iter().position(|tab| tab.path == path) {
+            self.select_tab(index);
+            return;
+        }
+        match EditorTab::open(path, &self.formats) {
+            Ok(tab) => {
+                self.tabs.push(tab);
+                self.select_tab(self.tabs.len() - 1);
+            }
+            Err(err) => self.report(Err(err)),
+        }
+    }
+
+    /// Switches to the tab at `index`, closing the diff view: it shows the
+    /// changes of the tab it was opened in.
+    fn select_tab(&mut self, index: usize) {
+        if index != self.tab_selected {
+            self.diff_view = None;
+        }
+        self.tab_selected = index;
+    }
+
+    /// Opens the diff view with the changes of the current tab since it
+    /// was last saved, or all of it if it never was.
+    fn show_diff(&mut self) -> anyhow::Result<()> {
+        let Some(tab) = self.tabs.get(self.tab_selected) else {
+            return Ok(());
+        };
+        let saved = if tab.path.exists() {
+            tab.format.read_file(&tab.path)?
+        } else {
+            Notebook::default()
+        };
+        self.diff_view = Some(DiffView::new("saved", saved.diff(&tab.content)));
+        Ok(())
+    }
+
+    /// Moves the cursor to the next (or previous) conflict cell, wrapping
+    /// around.
+    fn jump_to_conflict(&mut self, forward: bool) {
+        let Some(tab) = self.tabs.get_mut(self.tab_selected) else {
+            return;
+        };
+        let conflicts = tab.content.conflicts();
+        let current = tab.current_cell;
+        let target = if forward {
+            conflicts
+                .iter()
+                .find(|&&index| index > current)
+                .or(conflicts.first())
+        } else {
+            conflicts
+                .iter()
+                .rev()
+                .find(|&&index| index < current)
+                .or(conflicts.last())
+        };
+        match target {
+            Some(&index) => {
+                tab.current_cell = index;
+                tab.selection_anchor = None;
+            }
+            None => self.status_message = Some("No merge conflicts".to_string()),
+        }
+    }
+
+    /// Starts a kernel for the current tab, replacing the one running: the
+    /// kernelspec called `name`, which is then recorded in the notebook's
+    /// metadata, or else the one the metadata asks for. It connects in the
+    /// background; queued cells run once it has.
+    fn start_kernel(&mut self, name: Option<&str>) -> anyhow::Result<()> {
+        let Some(tab) = self.tabs.get_mut(self.tab_selected) else {
+            return Ok(());
+        };
+        let specs = find_kernelspecs();
+        let spec = match name {
+            Some(name) => specs
+                .iter()
+                .find(|spec| spec.name == name)
+                .with_context(|| format!("No kernel named {name} is installed"))?,
+            None => match_kernelspec(&specs, &tab.content.metadata)
+                .context("No installed kernel matches this notebook, select one")?,
+        };
+        if let Some(kernel) = tab.kernel.take() {
+            tab.execution.reset();
+            tab.variables = VariableInspector::default();
+            kernel.shutdown_in_background();
+        }
+        let kernel = tab.kernel.insert(KernelManager::start_in_background(
+            spec.clone(),
+            &tab.working_dir(),
+        )?);
+        let metadata = Some(kernel.spec.to_metadata());
+        if name.is_some() && tab.content.metadata.kernelspec != metadata {
+            tab.content.metadata.kernelspec = metadata;
+            tab.is_dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Queues the cells `range` picks in the current tab, starting the
+    /// notebook's kernel first if none is running.
+    fn run_cells(&mut self, range: impl FnOnce(&EditorTab) -> Range<usize>) {
+        let Some(tab) = self.tabs.get(self.tab_selected) else {
+            return;
+        };
+        let range = range(tab);
+        if tab.kernel.is_none()
+            && let Err(err) = self.start_kernel(None)
+        {
+            self.report(Err(err));
+            return;
+        }
+        let tab = &mut self.tabs[self.tab_selected];
+        tab.execution.enqueue(&mut tab.content, range);
+        let result = tab.poll_kernel().map(|_| ());
+        self.report(result);
+    }
+
+    /// Runs `action` on the kernel of the current tab.
+    fn with_kernel(
+        &mut self,
+        action: impl FnOnce(&mut KernelManager) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        match self
+            .tabs
+            .get_mut(self.tab_selected)
+            .and_then(|tab| tab.kernel.as_mut())
+        {
+            Some(kernel) => action(kernel),
+            None => anyhow::bail!("No kernel is running for this notebook"),
+        }
+    }
+
+    /// Closes the completion menu and forgets the completions still on
+    /// their way.
+    fn close_completions(&mut self) {
+        self.completion_menu = None;
+        if let Some(tab) = self.tabs.get_mut(self.tab_selected) {
+            tab.cancel_completions();
+        }
+    }
+
+    /// The prompt being typed in: the running cell's input, or else the
+    /// filter of the variables pane.
+    fn prompt_mut(&mut self) -> Option<&mut InputPrompt> {
+        self.input_prompt.as_mut().or(self.filter_prompt.as_mut())
+    }
+
+    /// Shows the error of a failed command in the status bar.
+    fn report(&mut self, result: anyhow::Result<()>) {
+        self.status_message = result.err().map(|err| format!("{err:#}"));
+    }
+
+    /// Runs a cell operation on the `scope` of the current tab, recording
+    /// it for undo.
+    fn edit_cells(
+        &mut self,
+        scope: CellScope,
+        operation: impl FnOnce(&mut Notebook, Range<usize>, &SanitizeRules) -> Option<CellEdit>,
+    ) {
+        let rules = &self.settings.sanitize;
+        let Some(tab) = self.tabs.get_mut(self.tab_selected) else {
+            return;
+        };
+        let range = match scope {
+            CellScope::Notebook => 0..tab.content.len(),
+            CellScope::Selection => tab.selected_cells(),
+        };
+        tab.edit(|notebook| operation(notebook, range, rules));
+    }
 }
@@ -1,12 +1,49 @@
-use crate::notebook_util::Notebook;
-use std::path::PathBuf;
+use crate::kernel::{Completer, Completion, ExecutionQueue, KernelManager, VariableInspector};
+use crate::notebook_util::format::{FormatRegistry, Ipynb, NotebookFormat};
+use crate::notebook_util::{Cell, CellEdit, Notebook};
+use anyhow::{Context, Result, bail};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long typing has to pause before completions are asked for.
+const COMPLETION_DELAY: Duration = Duration::from_millis(150);
+
+/// At most this many kernel messages are handled per poll, so that a
+/// chatty kernel can't keep the UI from drawing.
+const POLL_BATCH: usize = 256;
 
 pub struct EditorTab {
     pub name: String,
     pub path: PathBuf,
     pub content: Notebook,
+    /// The format the notebook was loaded from and is saved in.
+    pub format: Arc<dyn NotebookFormat>,
     pub is_dirty: bool,
     pub is_read_only: bool,
+    /// Index of the cell the cursor is in.
+    pub current_cell: usize,
+    /// The other end of the cell selection, if cells are selected.
+    pub selection_anchor: Option<usize>,
+    /// Byte offset of the cursor in the current cell's source.
+    pub cursor: usize,
+    /// The kernel running the notebook's code, once started.
+    pub kernel: Option<KernelManager>,
+    /// Cells waiting to run on the kernel and how the last runs went.
+    pub execution: ExecutionQueue,
+    /// The kernel's variables, refreshed after each cell runs.
+    pub variables: VariableInspector,
+    /// Completions asked of the kernel while typing.
+    pub completer: Completer,
+    /// When to ask for completions of what was typed, see
+    /// [`EditorTab::schedule_completions`].
+    completion_due: Option<Instant>,
+    /// The cell being typed into. What is typed in a row is a single edit
+    /// on the undo stack.
+    typing: Option<usize>,
+    undo_stack: Vec<CellEdit>,
+    redo_stack: Vec<CellEdit>,
 }
 
 impl Default for EditorTab {
@@ -18,8 +55,317 @@ impl Default for EditorTab {
             path: std::env::temp_dir().join("nbterm_tmp.ipynb"),
             // a new notebook instance
             content: Notebook::default(),
+            format: Arc::new(Ipynb),
             is_dirty: false,
             is_read_only: false,
+            current_cell: 0,
+            selection_anchor: None,
+            cursor: 0,
+            kernel: None,
+            execution: ExecutionQueue::interactive(),
+            variables: VariableInspector::default(),
+            completer: Completer::default(),
+            completion_due: None,
+            typing: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+}
+
+impl EditorTab {
+    /// A tab with an empty notebook, saved to `path` in the format
+    /// registered for its extension.
+    pub fn new(path: PathBuf, formats: &FormatRegistry) -> Self {
+        Self {
+            name: tab_name(&path),
+            format: formats.for_path(&path, None).unwrap_or(Arc::new(Ipynb)),
+            path,
+            ..Self::default()
+        }
+    }
+
+    /// Opens the notebook at `path` in a new tab, in the format `formats`
+    /// picks for it.
+    pub fn open(path: PathBuf, formats: &FormatRegistry) -> Result<Self> {
+        let (content, format) = formats.read_file(&path)?;
+        Ok(Self {
+            name: tab_name(&path),
+            path,
+            content,
+            format,
+            ..Self::default()
+        })
+    }
+
+    /// Saves the notebook to the tab's path, in the format it was loaded
+    /// from.
+    pub fn save(&mut self) -> Result<()> {
+        self.format.write_file(&self.content, &self.path)?;
+        self.is_dirty = false;
+        Ok(())
+    }
+
+    /// Saves the notebook to `path` and makes it the tab's path, converting
+    /// it to the format registered for the new extension, if any. The tab
+    /// is left as it was if saving fails.
+    pub fn save_as(&mut self, path: PathBuf, formats: &FormatRegistry) -> Result<()> {
+        let format = formats
+            .for_path(&path, None)
+            .unwrap_or_else(|| Arc::clone(&self.format));
+        format.write_file(&self.content, &path)?;
+        self.format = format;
+        self.name = tab_name(&path);
+        self.path = path;
+        self.is_dirty = false;
+        Ok(())
+    }
+
+    /// The selected cells, or just the current one without a selection.
+    pub fn selected_cells(&self) -> Range<usize> {
+        let anchor = self.selection_anchor.unwrap_or(self.current_cell);
+        let start = anchor.min(self.current_cell);
+        let end = (anchor.max(self.current_cell) + 1).min(self.content.len());
+        start.min(end)..end
+    }
+
+    /// The directory the tab's kernel runs in: the notebook's own.
+    pub fn working_dir(&self) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+
+    /// Feeds the kernel's messages to the execution queue and sends it the
+    /// next cell, without blocking. The variables are refreshed once the
+    /// last queued cell finished, and completions are asked for once they
+    /// are due.
+    ///
+    /// A kernel that fails to connect is dropped.
+    ///
+    /// Returns whether the notebook changed.
+    pub fn poll_kernel(&mut self) -> Result<bool> {
+        let completions_due = self
+            .completion_due
+            .take_if(|due| *due <= Instant::now())
+            .is_some();
+        if let Some(kernel) = &mut self.kernel
+            && let Err(err) = kernel.poll_connect()
+        {
+            self.kernel = None;
+            return Err(err);
+        }
+        let Some(kernel) = &self.kernel else {
+            return Ok(false);
+        };
+        let Some(client) = kernel.client() else {
+            return Ok(false);
+        };
+        let mut changed = false;
+        let mut finished = false;
+        for _ in 0..POLL_BATCH {
+            let Some((channel, message)) = client.try_recv() else {
+                break;
+            };
+            let running = self.execution.running().is_some();
+            changed |= self.execution.handle(&mut self.content, channel, &message);
+            finished |= running && self.execution.running().is_none();
+            self.variables.handle(channel, &message);
+            self.completer.handle(channel, &message);
+        }
+        if finished && !self.execution.is_busy() {
+            self.variables.refresh(client, &kernel.spec.language)?;
+        }
+        self.execution.start_next(&mut self.content, client)?;
+        self.is_dirty |= changed;
+        if completions_due {
+            self.request_completions()?;
+        }
+        Ok(changed)
+    }
+
+    /// Sends `value` to the running cell that asked for input.
+    pub fn answer_input(&mut self, value: &str) -> Result<()> {
+        let Some(client) = self.kernel.as_ref().and_then(|kernel| kernel.client()) else {
+            return Ok(());
+        };
+        self.execution
+            .answer_input(&mut self.content, client, value)?;
+        self.is_dirty = true;
+        Ok(())
+    }
+
+    /// Asks the kernel for its variables again.
+    pub fn refresh_variables(&mut self) -> Result<()> {
+        let Some(kernel) = &self.kernel else {
+            bail!("No kernel is running for this notebook");
+        };
+        let client = kernel.client().context("The kernel hasn't connected yet")?;
+        self.variables.refresh(client, &kernel.spec.language)
+    }
+
+    /// The cursor, moved into the current cell's source if it was past its
+    /// end or inside a character.
+    pub fn cursor_in_cell(&self) -> usize {
+        let Some(cell) = self.content.cells.get(self.current_cell) else {
+            return 0;
+        };
+        let source = cell.source().as_str();
+        let mut cursor = self.cursor.min(source.len());
+        while !source.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        cursor
+    }
+
+    /// Types `text` into the current cell at the cursor, which ends up
+    /// after it. Text typed in a row into the same cell is undone at once.
+    pub fn insert_text(&mut self, text: &str) -> bool {
+        let index = self.current_cell;
+        let cursor = self.cursor_in_cell();
+        let Some(cell) = self.content.cells.get(index) else {
+            return false;
+        };
+        let mut source = cell.source().as_str().to_string();
+        source.insert_str(cursor, text);
+        let typing = self.typing == Some(index);
+        if !self.set_source(source, cursor + text.len()) {
+            return false;
+        }
+        if typing {
+            // the edit that undoes the whole run is already on the stack
+            self.undo_stack.pop();
+        }
+        self.typing = Some(index);
+        true
+    }
+
+    /// Whether the cursor is right after a word or a `.`, where Tab
+    /// completes rather than indents.
+    pub fn cursor_after_word(&self) -> bool {
+        let Some(cell) = self.content.cells.get(self.current_cell) else {
+            return false;
+        };
+        cell.source().as_str()[..self.cursor_in_cell()]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.')
+    }
+
+    /// Asks for completions once typing pauses for [`COMPLETION_DELAY`],
+    /// instead of for every character typed.
+    pub fn schedule_completions(&mut self) {
+        self.completion_due = Some(Instant::now() + COMPLETION_DELAY);
+    }
+
+    /// Drops the completions asked for or scheduled.
+    pub fn cancel_completions(&mut self) {
+        self.completion_due = None;
+        self.completer.cancel();
+    }
+
+    /// Whether completions are scheduled, see
+    /// [`EditorTab::schedule_completions`].
+    pub fn completions_scheduled(&self) -> bool {
+        self.completion_due.is_some()
+    }
+
+    /// Asks the kernel how to complete the code at the cursor, if the
+    /// current cell is a code cell.
+    pub fn request_completions(&mut self) -> Result<()> {
+        let cursor = self.cursor_in_cell();
+        let (Some(Cell::Code(cell)), Some(kernel)) =
+            (self.content.cells.get(self.current_cell), &self.kernel)
+        else {
+            return Ok(());
+        };
+        let client = kernel.client().context("The kernel hasn't connected yet")?;
+        self.completer.request(client, cell.source.as_str(), cursor)
+    }
+
+    /// Replaces the code `completion` was made for with it.
+    pub fn apply_completion(&mut self, completion: &Completion) -> bool {
+        let Some(cell) = self.content.cells.get(self.current_cell) else {
+            return false;
+        };
+        let (source, cursor) = completion.apply(cell.source().as_str());
+        self.set_source(source, cursor)
+    }
+
+    /// Replaces the current cell's source, as an undoable edit, and moves
+    /// the cursor to `cursor`.
+    fn set_source(&mut self, source: String, cursor: usize) -> bool {
+        let index = self.current_cell;
+        if !self.edit(|notebook| notebook.set_source(index, source)) {
+            return false;
+        }
+        self.cursor = cursor;
+        true
+    }
+
+    /// Gives up on the running cell's request for input and interrupts the
+    /// kernel, which would otherwise wait for the answer forever.
+    pub fn cancel_input(&mut self) -> Result<()> {
+        if self.execution.cancel_input().is_some()
+            && let Some(kernel) = &mut self.kernel
+        {
+            kernel.interrupt()?;
+        }
+        Ok(())
+    }
+
+    /// Edits the notebook with `edit`, which gets the notebook and returns
+    /// the inverse edit as the [`Notebook`] operations do, and records it
+    /// for undo.
+    ///
+    /// Returns whether anything changed.
+    pub fn edit(&mut self, edit: impl FnOnce(&mut Notebook) -> Option<CellEdit>) -> bool {
+        if self.is_read_only {
+            return false;
         }
+        let Some(inverse) = edit(&mut self.content) else {
+            return false;
+        };
+        self.typing = None;
+        self.undo_stack.push(inverse);
+        self.redo_stack.clear();
+        self.is_dirty = true;
+        true
     }
+
+    /// Reverts the last edit.
+    pub fn undo(&mut self) -> bool {
+        self.replay(false)
+    }
+
+    /// Re-applies the last undone edit.
+    pub fn redo(&mut self) -> bool {
+        self.replay(true)
+    }
+
+    /// Pops an edit from the undo (or redo) stack, applies it and pushes
+    /// its inverse onto the other stack.
+    fn replay(&mut self, redo: bool) -> bool {
+        self.typing = None;
+        let (from, to) = if redo {
+            (&mut self.redo_stack, &mut self.undo_stack)
+        } else {
+            (&mut self.undo_stack, &mut self.redo_stack)
+        };
+        let Some(inverse) = from.pop().and_then(|edit| self.content.apply(edit)) else {
+            return false;
+        };
+        to.push(inverse);
+        self.is_dirty = true;
+        self.current_cell = self.current_cell.min(self.content.len().saturating_sub(1));
+        true
+    }
+}
+
+fn tab_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
 }
@@ -3,9 +3,25 @@ use crossterm::event::{
     KeyCode, KeyEvent, KeyModifiers,
 };
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use super::input_mode::InputMode;
+use crate::notebook_util::export::HtmlOptions;
+use crate::notebook_util::merge::Resolution;
 use crate::tui::editor_commands::EditorCommand;
+use crate::tui::variables_viewer::VariableSort;
+
+/// How many lines PageDown and PageUp scroll the diff view.
+const DIFF_PAGE: u16 = 20;
+
+/// What is open over the editor and gets the keys first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    Prompt,      // the input or variables filter prompt, takes every key
+    Picker,      // the kernel picker, takes every key
+    Completions, // keys it has no use for go on to the editor
+    Diff,        // keys it has no use for go on to the editor
+}
 
 pub struct EventTranslator {
     normal_mode_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
@@ -15,6 +31,10 @@ pub struct EventTranslator {
     visual_block_mode_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
     command_mode_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
     ui_cursor_mode_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
+    prompt_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
+    picker_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
+    completions_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
+    diff_event_map: HashMap<(KeyCode, KeyModifiers), EditorCommand>,
 }
 
 impl Default for EventTranslator {
@@ -25,11 +45,15 @@ impl Default for EventTranslator {
 
         let mut normal_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
         let mut insert_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
-        let mut visual_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
-        let mut visual_line_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
-        let mut visual_block_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
-        let mut command_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let visual_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let visual_line_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let visual_block_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let command_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
         let mut ui_cursor_mode_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let mut prompt_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let mut picker_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let mut completions_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
+        let mut diff_event_map: HashMap<(K, M), EditorCommand> = HashMap::new();
 
         // Initialize the event maps with default commands for each mode
         normal_mode_event_map.insert((K::Char('q'), M::CONTROL), C::Quit);
@@ -41,11 +65,106 @@ impl Default for EventTranslator {
         normal_mode_event_map.insert((K::Char('v'), M::CONTROL), C::SwitchToVisualMode);
         normal_mode_event_map.insert((K::Char('V'), M::NONE), C::SwitchToVisualLineMode);
         normal_mode_event_map.insert((K::Char('v'), M::CONTROL), C::SwitchToVisualBlockMode);
+        normal_mode_event_map.insert((K::Char('q'), M::NONE), C::Quit);
+        normal_mode_event_map.insert((K::Char('i'), M::NONE), C::SwitchToInsertMode);
+        normal_mode_event_map.insert((K::Enter, M::CONTROL), C::RunCell);
+        normal_mode_event_map.insert((K::Enter, M::SHIFT), C::RunCellAndAdvance);
+        normal_mode_event_map.insert((K::Char('r'), M::NONE), C::RunCell);
+        normal_mode_event_map.insert((K::Char('R'), M::NONE), C::RunAll);
+        normal_mode_event_map.insert((K::Char('A'), M::NONE), C::RunAbove);
+        normal_mode_event_map.insert((K::Char('B'), M::NONE), C::RunBelow);
+        normal_mode_event_map.insert((K::Char('S'), M::NONE), C::RunSelection);
+        normal_mode_event_map.insert((K::Char('K'), M::NONE), C::StartKernel);
+        normal_mode_event_map.insert((K::Char('P'), M::NONE), C::PickKernel);
+        normal_mode_event_map.insert((K::Char('D'), M::NONE), C::ToggleDiff);
+        normal_mode_event_map.insert((K::Char(']'), M::NONE), C::ToNextConflict);
+        normal_mode_event_map.insert((K::Char('['), M::NONE), C::ToPreviousConflict);
+        normal_mode_event_map.insert((K::Char('o'), M::ALT), C::ResolveConflict(Resolution::Ours));
+        normal_mode_event_map.insert(
+            (K::Char('t'), M::ALT),
+            C::ResolveConflict(Resolution::Theirs),
+        );
+        normal_mode_event_map.insert((K::Char('b'), M::ALT), C::ResolveConflict(Resolution::Both));
+        normal_mode_event_map.insert(
+            (K::Char('e'), M::ALT),
+            C::ResolveConflict(Resolution::Edited),
+        );
+        normal_mode_event_map.insert((K::Char('v'), M::ALT), C::ToggleVariables);
+        // an empty path exports next to the notebook
+        normal_mode_event_map.insert(
+            (K::Char('E'), M::NONE),
+            C::ExportHtml(PathBuf::new(), HtmlOptions::default()),
+        );
 
         insert_mode_event_map.insert((K::Char('i'), M::CONTROL), C::Input("\t".to_string()));
         insert_mode_event_map.insert((K::Char('c'), M::CONTROL), C::SwitchToNormalMode);
         insert_mode_event_map.insert((K::Char('w'), M::CONTROL), C::DeletePreviousWord);
         insert_mode_event_map.insert((K::Esc, M::NONE), C::SwitchToNormalMode);
+        insert_mode_event_map.insert((K::Enter, M::NONE), C::Input("\n".to_string()));
+        insert_mode_event_map.insert((K::Enter, M::CONTROL), C::RunCell);
+        insert_mode_event_map.insert((K::Enter, M::SHIFT), C::RunCellAndAdvance);
+        // completes after a word, indents elsewhere
+        insert_mode_event_map.insert((K::Tab, M::NONE), C::Complete);
+
+        // in the variables pane, once Ctrl-l moved the cursor there
+        ui_cursor_mode_event_map.insert((K::Char('q'), M::CONTROL), C::Quit);
+        ui_cursor_mode_event_map.insert((K::Char('h'), M::CONTROL), C::ToLeftPane);
+        ui_cursor_mode_event_map.insert((K::Esc, M::NONE), C::SwitchToNormalMode);
+        ui_cursor_mode_event_map.insert((K::Char('v'), M::ALT), C::ToggleVariables);
+        ui_cursor_mode_event_map.insert((K::Char('j'), M::NONE), C::ToNextVariable);
+        ui_cursor_mode_event_map.insert((K::Down, M::NONE), C::ToNextVariable);
+        ui_cursor_mode_event_map.insert((K::Char('k'), M::NONE), C::ToPreviousVariable);
+        ui_cursor_mode_event_map.insert((K::Up, M::NONE), C::ToPreviousVariable);
+        ui_cursor_mode_event_map.insert((K::Enter, M::NONE), C::ToggleVariableExpanded);
+        ui_cursor_mode_event_map.insert((K::Char(' '), M::NONE), C::ToggleVariableExpanded);
+        ui_cursor_mode_event_map.insert((K::Char('p'), M::NONE), C::ToggleVariablePinned);
+        ui_cursor_mode_event_map.insert((K::Char('/'), M::NONE), C::EditVariablesFilter);
+        ui_cursor_mode_event_map.insert((K::Char('r'), M::NONE), C::RefreshVariables);
+        ui_cursor_mode_event_map.insert(
+            (K::Char('n'), M::NONE),
+            C::SortVariables(VariableSort::Name),
+        );
+        ui_cursor_mode_event_map.insert(
+            (K::Char('t'), M::NONE),
+            C::SortVariables(VariableSort::Type),
+        );
+        ui_cursor_mode_event_map.insert(
+            (K::Char('s'), M::NONE),
+            C::SortVariables(VariableSort::Size),
+        );
+
+        prompt_event_map.insert((K::Enter, M::NONE), C::SubmitPrompt);
+        prompt_event_map.insert((K::Esc, M::NONE), C::CancelPrompt);
+        prompt_event_map.insert((K::Char('c'), M::CONTROL), C::CancelPrompt);
+        prompt_event_map.insert((K::Backspace, M::NONE), C::PromptBackspace);
+
+        picker_event_map.insert((K::Enter, M::NONE), C::SubmitPrompt);
+        picker_event_map.insert((K::Esc, M::NONE), C::CancelPrompt);
+        picker_event_map.insert((K::Char('c'), M::CONTROL), C::CancelPrompt);
+        picker_event_map.insert((K::Down, M::NONE), C::ToNextKernel);
+        picker_event_map.insert((K::Char('j'), M::NONE), C::ToNextKernel);
+        picker_event_map.insert((K::Char('n'), M::CONTROL), C::ToNextKernel);
+        picker_event_map.insert((K::Up, M::NONE), C::ToPreviousKernel);
+        picker_event_map.insert((K::Char('k'), M::NONE), C::ToPreviousKernel);
+        picker_event_map.insert((K::Char('p'), M::CONTROL), C::ToPreviousKernel);
+
+        completions_event_map.insert((K::Tab, M::NONE), C::AcceptCompletion);
+        completions_event_map.insert((K::Enter, M::NONE), C::AcceptCompletion);
+        completions_event_map.insert((K::Down, M::NONE), C::ToNextCompletion);
+        completions_event_map.insert((K::Char('n'), M::CONTROL), C::ToNextCompletion);
+        completions_event_map.insert((K::Up, M::NONE), C::ToPreviousCompletion);
+        completions_event_map.insert((K::BackTab, M::NONE), C::ToPreviousCompletion);
+        completions_event_map.insert((K::BackTab, M::SHIFT), C::ToPreviousCompletion);
+        completions_event_map.insert((K::Char('p'), M::CONTROL), C::ToPreviousCompletion);
+        completions_event_map.insert((K::Esc, M::NONE), C::CloseCompletions);
+
+        diff_event_map.insert((K::Char('j'), M::NONE), C::ScrollDiffDown(1));
+        diff_event_map.insert((K::Down, M::NONE), C::ScrollDiffDown(1));
+        diff_event_map.insert((K::Char('k'), M::NONE), C::ScrollDiffUp(1));
+        diff_event_map.insert((K::Up, M::NONE), C::ScrollDiffUp(1));
+        diff_event_map.insert((K::PageDown, M::NONE), C::ScrollDiffDown(DIFF_PAGE));
+        diff_event_map.insert((K::PageUp, M::NONE), C::ScrollDiffUp(DIFF_PAGE));
+        diff_event_map.insert((K::Esc, M::NONE), C::ToggleDiff);
 
         // return
         Self {
@@ -56,14 +175,25 @@ impl Default for EventTranslator {
             visual_block_mode_event_map,
             command_mode_event_map,
             ui_cursor_mode_event_map,
+            prompt_event_map,
+            picker_event_map,
+            completions_event_map,
+            diff_event_map,
         }
     }
 }
 
 impl EventTranslator {
-    pub fn translate_event(&self, event: Event, input_mode: InputMode) -> Option<EditorCommand> {
+    /// The command `event` stands for: what `overlay` binds it to, if one
+    /// is open, or else what it is bound to in `input_mode`.
+    pub fn translate_event(
+        &self,
+        event: Event,
+        overlay: Option<Overlay>,
+        input_mode: &InputMode,
+    ) -> Option<EditorCommand> {
         match event {
-            Key(key_event) => self.translate_key_event(key_event, input_mode),
+            Key(key_event) => self.translate_key_event(key_event, overlay, input_mode),
             _ => None,
         }
     }
@@ -71,12 +201,37 @@ impl EventTranslator {
     fn translate_key_event(
         &self,
         key_event: KeyEvent,
-        input_mode: InputMode,
+        overlay: Option<Overlay>,
+        input_mode: &InputMode,
     ) -> Option<EditorCommand> {
-        let key = (key_event.code, key_event.modifiers);
+        let mut key = (key_event.code, key_event.modifiers);
+        // the case of a character already tells whether Shift was down
+        if let KeyCode::Char(_) = key.0 {
+            key.1.remove(KeyModifiers::SHIFT);
+        }
+        let overlay_command = match overlay {
+            Some(Overlay::Prompt) => {
+                return self
+                    .prompt_event_map
+                    .get(&key)
+                    .cloned()
+                    .or_else(|| typed(key).map(|c| EditorCommand::PromptInput(c.to_string())));
+            }
+            Some(Overlay::Picker) => return self.picker_event_map.get(&key).cloned(),
+            Some(Overlay::Completions) => self.completions_event_map.get(&key),
+            Some(Overlay::Diff) => self.diff_event_map.get(&key),
+            None => None,
+        };
+        if let Some(command) = overlay_command {
+            return Some(command.clone());
+        }
         match input_mode {
             InputMode::Normal => self.normal_mode_event_map.get(&key).cloned(),
-            InputMode::Insert => self.insert_mode_event_map.get(&key).cloned(),
+            InputMode::Insert | InputMode::Replace => self
+                .insert_mode_event_map
+                .get(&key)
+                .cloned()
+                .or_else(|| typed(key).map(|c| EditorCommand::Input(c.to_string()))),
             InputMode::Visual => self.visual_mode_event_map.get(&key).cloned(),
             InputMode::VisualLine => self.visual_line_mode_event_map.get(&key).cloned(),
             InputMode::VisualBlock => self.visual_block_mode_event_map.get(&key).cloned(),
@@ -85,3 +240,11 @@ impl EventTranslator {
         }
     }
 }
+
+/// The character `key` types where no binding takes it.
+fn typed(key: (KeyCode, KeyModifiers)) -> Option<char> {
+    match key {
+        (KeyCode::Char(c), KeyModifiers::NONE) => Some(c),
+        _ => None,
+    }
+}
@@ -0,0 +1,55 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+
+/// The line a running cell asked for with `input()`, being typed below its
+/// output.
+pub struct InputPrompt {
+    pub prompt: String,
+    /// Show the answer as bullets, for `getpass()`.
+    pub password: bool,
+    pub value: String,
+    /// What Enter and Esc do, shown above the line.
+    pub title: String,
+}
+
+impl InputPrompt {
+    pub fn new(prompt: impl Into<String>, password: bool) -> Self {
+        Self {
+            prompt: prompt.into(),
+            password,
+            value: String::new(),
+            title: "Input (Enter to send, Esc to interrupt)".to_string(),
+        }
+    }
+
+    /// The same prompt for another purpose than `input()`, starting from
+    /// `value`.
+    pub fn for_value(mut self, title: impl Into<String>, value: impl Into<String>) -> Self {
+        self.title = title.into();
+        self.value = value.into();
+        self
+    }
+}
+
+impl Widget for &InputPrompt {
+    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
+        let value = if self.password {
+            "•".repeat(self.value.chars().count())
+        } else {
+            self.value.clone()
+        };
+        let line = Line::from(vec![
+            Span::raw(self.prompt.as_str()),
+            Span::styled(value, Style::default().fg(Color::Yellow)),
+            Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)),
+        ]);
+        Paragraph::new(line)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.title.as_str()),
+            )
+            .render(area, buf);
+    }
+}
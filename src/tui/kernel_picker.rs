@@ -0,0 +1,67 @@
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, StatefulWidget};
+
+use crate::kernel::spec::KernelSpec;
+
+/// How many kernels the picker shows at once.
+pub const PICKER_HEIGHT: u16 = 8;
+
+/// The installed kernels to switch the current tab to, one of them
+/// selected.
+pub struct KernelPicker {
+    pub specs: Vec<KernelSpec>,
+    pub selected: usize,
+}
+
+impl KernelPicker {
+    /// A picker offering `specs` with the one called `current` selected,
+    /// or none if there aren't any.
+    pub fn new(specs: Vec<KernelSpec>, current: Option<&str>) -> Option<Self> {
+        let selected = specs
+            .iter()
+            .position(|spec| Some(spec.name.as_str()) == current)
+            .unwrap_or(0);
+        (!specs.is_empty()).then_some(Self { specs, selected })
+    }
+
+    pub fn selected(&self) -> &KernelSpec {
+        &self.specs[self.selected]
+    }
+
+    /// Selects the next kernel, wrapping around.
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.specs.len();
+    }
+
+    /// Selects the previous kernel, wrapping around.
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.checked_sub(1).unwrap_or(self.specs.len() - 1);
+    }
+
+    /// The rows the picker needs, borders included.
+    pub fn height(&self) -> u16 {
+        (self.specs.len() as u16).min(PICKER_HEIGHT) + 2
+    }
+}
+
+impl ratatui::widgets::Widget for &KernelPicker {
+    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
+        let hint = Style::default().fg(Color::DarkGray);
+        let items = self.specs.iter().map(|spec| {
+            ListItem::new(Line::from(vec![
+                Span::raw(spec.display_name.as_str()),
+                Span::styled(format!("  {}", spec.name), hint),
+            ]))
+        });
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Kernel (Enter to start, Esc to cancel)"),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        StatefulWidget::render(list, area, buf, &mut state);
+    }
+}
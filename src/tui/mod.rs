@@ -1,11 +1,16 @@
 pub mod app;
 pub use app::NotebookApp;
+pub mod completion_menu;
+pub mod diff_view;
 pub mod editor_commands;
 pub mod editor_tab;
 pub mod event_translator;
 pub mod file_picker;
 pub mod input_mode;
+pub mod input_prompt;
+pub mod kernel_picker;
 pub mod outliner;
+pub mod output_view;
 pub mod settings;
 pub mod variables_viewer;
 
@@ -0,0 +1,81 @@
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+
+use crate::notebook_util::export::TextOptions;
+use crate::notebook_util::{Cell, CodeCell};
+use crate::tui::editor_tab::EditorTab;
+
+/// The outputs of a code cell, below the editor. A running cell's outputs
+/// are shown as they arrive, scrolled to the latest.
+pub struct OutputView {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+impl OutputView {
+    /// The outputs of the tab's running cell, or else of its current cell,
+    /// if it is a code cell with any.
+    pub fn new(tab: &EditorTab) -> Option<Self> {
+        let code = |index: usize| match tab.content.cells.get(index) {
+            Some(Cell::Code(cell)) => Some((index, cell)),
+            _ => None,
+        };
+        let running = tab.execution.running().and_then(|id| {
+            let index = tab
+                .content
+                .cells
+                .iter()
+                .position(|cell| cell.id() == Some(id))?;
+            code(index)
+        });
+        let (index, cell) = match running {
+            Some(running) => running,
+            None => code(tab.current_cell).filter(|(_, cell)| !cell.outputs.is_empty())?,
+        };
+        let state = if running.is_some() { ", running" } else { "" };
+        Some(Self {
+            title: format!("Output of cell {}{state}", index + 1),
+            lines: output_lines(cell),
+        })
+    }
+
+    /// The rows the view needs, borders included, at most `max`.
+    pub fn height(&self, max: u16) -> u16 {
+        (self.lines.len() as u16).saturating_add(2).min(max)
+    }
+}
+
+/// The cell's outputs as plain text, one line per entry.
+fn output_lines(cell: &CodeCell) -> Vec<String> {
+    let options = TextOptions::default();
+    cell.outputs
+        .iter()
+        .flat_map(|output| {
+            let text = output.to_text(&options);
+            text.trim_end()
+                .lines()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+impl Widget for &OutputView {
+    fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
+        // follow the latest output, leaving the borders out
+        let shown = area.height.saturating_sub(2) as usize;
+        let scroll = self.lines.len().saturating_sub(shown) as u16;
+        let lines: Vec<_> = self
+            .lines
+            .iter()
+            .map(|line| ratatui::text::Line::from(line.as_str()))
+            .collect();
+        Paragraph::new(lines)
+            .scroll((scroll, 0))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.title.as_str()),
+            )
+            .render(area, buf);
+    }
+}
@@ -1,5 +1,50 @@
-#[derive(Default)]
+use crate::notebook_util::sanitize::SanitizeRules;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Name of the per-project configuration file.
+pub const PROJECT_CONFIG_FILE: &str = ".nbterm.json";
+
+#[derive(Deserialize)]
+#[serde(default)]
 pub struct Settings {
     theme: String,
     font_size: u8,
+    pub sanitize: SanitizeRules,
+    /// Ask the kernel for completions while typing in code cells. On by
+    /// default.
+    pub auto_complete: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: String::new(),
+            font_size: 0,
+            sanitize: SanitizeRules::default(),
+            auto_complete: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings of the project containing `start`, i.e. from the
+    /// nearest `.nbterm.json` in `start` or one of its ancestors.
+    ///
+    /// Without such a file, the settings are the default ones. Returns an
+    /// error if the file can't be read or parsed.
+    pub fn discover(start: &Path) -> Result<Self> {
+        let Some(path) = start
+            .ancestors()
+            .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file())
+        else {
+            return Ok(Self::default());
+        };
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read settings: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse settings: {}", path.display()))
+    }
 }
@@ -1,28 +1,249 @@
-use ratatui::widgets::Widget;
+use std::collections::HashSet;
 
+use ratatui::widgets::{ListState, StatefulWidget};
+
+use crate::kernel::Variable;
 use crate::tui::title_padding;
 
+/// The order of the variables pane. Names and types sort alphabetically,
+/// sizes largest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VariableSort {
+    #[default]
+    Name,
+    Type,
+    Size,
+}
+
+/// The variables of the current tab's kernel, with the pane's sorting,
+/// filtering, expanded containers and pinned variables.
 #[derive(Default)]
 pub struct VariablesViewer {
     variables: Vec<Variable>,
+    /// Why the variables couldn't be listed, shown instead of them.
+    error: Option<String>,
+    pub sort: VariableSort,
+    /// Only variables whose name contains this are listed, ignoring case.
+    /// Pinned variables always are.
+    pub filter: String,
+    /// Names of the containers whose items are shown.
+    expanded: HashSet<String>,
+    /// Names of the variables watched at the top of the pane, in the order
+    /// they were pinned. They stay there while undefined.
+    pinned: Vec<String>,
+    /// Index of the selected row.
+    selected: usize,
+}
+
+/// A line of the variables pane.
+pub struct VariableRow<'a> {
+    pub name: &'a str,
+    /// `None` for a pinned variable that is no longer defined.
+    pub variable: Option<&'a Variable>,
+    /// 0 for variables, 1 for the items of an expanded container.
+    pub depth: usize,
+    /// The variable the row belongs to, which pinning and expanding act on.
+    pub root: &'a str,
+    pub pinned: bool,
+    pub expanded: bool,
+}
+
+impl VariablesViewer {
+    /// Shows `variables`, or `error` when listing them failed, keeping the
+    /// selection on the same variable if it is still listed.
+    pub fn set_variables(&mut self, variables: &[Variable], error: Option<&str>) {
+        if self.variables == variables && self.error.as_deref() == error {
+            return;
+        }
+        let selected = self.selected_root().map(str::to_string);
+        self.variables = variables.to_vec();
+        self.error = error.map(str::to_string);
+        self.reselect(selected.as_deref());
+    }
+
+    /// The rows of the pane: the pinned variables, then the others that
+    /// match the filter, each followed by its items if expanded.
+    pub fn rows(&self) -> Vec<VariableRow<'_>> {
+        let filter = self.filter.to_lowercase();
+        let mut others: Vec<&Variable> = self
+            .variables
+            .iter()
+            .filter(|variable| {
+                !self.pinned.contains(&variable.name)
+                    && variable.name.to_lowercase().contains(&filter)
+            })
+            .collect();
+        match self.sort {
+            VariableSort::Name => others.sort_by(|a, b| a.name.cmp(&b.name)),
+            VariableSort::Type => {
+                others.sort_by(|a, b| (&a.type_name, &a.name).cmp(&(&b.type_name, &b.name)))
+            }
+            VariableSort::Size => {
+                others.sort_by(|a, b| b.size().cmp(&a.size()).then(a.name.cmp(&b.name)))
+            }
+        }
+
+        let pinned = self.pinned.iter().map(|name| {
+            let variable = self
+                .variables
+                .iter()
+                .find(|variable| &variable.name == name);
+            (name.as_str(), variable)
+        });
+        let others = others
+            .into_iter()
+            .map(|variable| (variable.name.as_str(), Some(variable)));
+        let mut rows = vec![];
+        for (name, variable) in pinned.chain(others) {
+            let expanded = self.expanded.contains(name);
+            rows.push(VariableRow {
+                name,
+                variable,
+                depth: 0,
+                root: name,
+                pinned: self.pinned.iter().any(|pinned| pinned == name),
+                expanded,
+            });
+            if expanded && let Some(variable) = variable {
+                rows.extend(variable.children.iter().map(|child| VariableRow {
+                    name: &child.name,
+                    variable: Some(child),
+                    depth: 1,
+                    root: name,
+                    pinned: false,
+                    expanded: false,
+                }));
+            }
+        }
+        rows
+    }
+
+    /// The variable of the selected row, or the container it is an item of.
+    pub fn selected_root(&self) -> Option<&str> {
+        self.rows().get(self.selected).map(|row| row.root)
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.rows().len().saturating_sub(1));
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Lists only the variables whose name contains `filter`.
+    pub fn set_filter(&mut self, filter: String) {
+        let selected = self.selected_root().map(str::to_string);
+        self.filter = filter;
+        self.reselect(selected.as_deref());
+    }
+
+    pub fn set_sort(&mut self, sort: VariableSort) {
+        let selected = self.selected_root().map(str::to_string);
+        self.sort = sort;
+        self.reselect(selected.as_deref());
+    }
+
+    /// Shows or hides the items of the selected container.
+    pub fn toggle_expanded(&mut self) {
+        let Some(root) = self.selected_root().map(str::to_string) else {
+            return;
+        };
+        if !self.expanded.remove(&root) {
+            self.expanded.insert(root.clone());
+        }
+        self.reselect(Some(&root));
+    }
+
+    /// Pins the selected variable to the top of the pane, or unpins it.
+    pub fn toggle_pinned(&mut self) {
+        let Some(root) = self.selected_root().map(str::to_string) else {
+            return;
+        };
+        match self.pinned.iter().position(|name| *name == root) {
+            Some(index) => {
+                self.pinned.remove(index);
+            }
+            None => self.pinned.push(root.clone()),
+        }
+        self.reselect(Some(&root));
+    }
+
+    /// Selects the row of the variable `root`, or keeps the selection in
+    /// range if it is gone.
+    fn reselect(&mut self, root: Option<&str>) {
+        let rows = self.rows();
+        self.selected = root
+            .and_then(|root| {
+                rows.iter()
+                    .position(|row| row.depth == 0 && row.root == root)
+            })
+            .unwrap_or(self.selected)
+            .min(rows.len().saturating_sub(1));
+    }
+
+    fn title(&self) -> String {
+        let sort = match self.sort {
+            VariableSort::Name => "name",
+            VariableSort::Type => "type",
+            VariableSort::Size => "size",
+        };
+        match self.filter.as_str() {
+            "" => format!("Variables by {sort}"),
+            filter => format!("Variables by {sort} /{filter}"),
+        }
+    }
 }
 
-pub struct Variable {
-    name: String,
-    value: String,
+/// `name: type shape = repr`, cut to `width` columns.
+fn row_text(row: &VariableRow, width: usize) -> String {
+    let pin = if row.pinned { '*' } else { ' ' };
+    let fold = match row.variable {
+        Some(variable) if row.depth == 0 && !variable.children.is_empty() => {
+            if row.expanded {
+                '▾'
+            } else {
+                '▸'
+            }
+        }
+        _ => ' ',
+    };
+    let text = match row.variable {
+        Some(variable) => {
+            let shape = variable
+                .shape_text()
+                .map(|shape| format!(" {shape}"))
+                .unwrap_or_default();
+            let repr = variable.repr.lines().next().unwrap_or_default();
+            format!("{}: {}{shape} = {repr}", row.name, variable.type_name)
+        }
+        None => format!("{}: not defined", row.name),
+    };
+    let text = format!("{}{pin}{fold}{text}", "  ".repeat(row.depth));
+    if text.chars().count() <= width {
+        return text;
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
 }
 
-impl Widget for &VariablesViewer {
+impl ratatui::widgets::Widget for &VariablesViewer {
     fn render(self, area: ratatui::layout::Rect, buf: &mut ratatui::buffer::Buffer) {
-        const TITLE: &str = "Variables";
-        // a list of variables
-        let variables_widget =
-            ratatui::widgets::List::new(self.variables.iter().map(|var| {
-                ratatui::widgets::ListItem::new(format!("{}: {}", var.name, var.value))
-            }))
+        // the left border takes a column
+        let width = area.width.saturating_sub(1) as usize;
+        let items: Vec<_> = match &self.error {
+            Some(error) => vec![ratatui::widgets::ListItem::new(error.as_str())],
+            None => self
+                .rows()
+                .iter()
+                .map(|row| ratatui::widgets::ListItem::new(row_text(row, width)))
+                .collect(),
+        };
+        let variables_widget = ratatui::widgets::List::new(items)
             .block(
                 ratatui::widgets::Block::default()
-                    .title(title_padding(area, TITLE))
+                    .title(title_padding(area, &self.title()))
                     .title_alignment(ratatui::layout::Alignment::Left)
                     .title_style(
                         ratatui::style::Style::default()
@@ -34,6 +255,8 @@ impl Widget for &VariablesViewer {
             )
             .highlight_style(ratatui::style::Style::default().fg(ratatui::style::Color::Yellow));
 
-        variables_widget.render(area, buf);
+        let mut state =
+            ListState::default().with_selected(self.error.is_none().then_some(self.selected));
+        StatefulWidget::render(variables_widget, area, buf, &mut state);
     }
 }
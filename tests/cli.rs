@@ -0,0 +1,169 @@
+mod common;
+
+use nbterm::notebook_util::{MimeBundle, Notebook, Output};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output as ProcessOutput};
+
+fn nbterm(args: &[&str], dir: &Path) -> ProcessOutput {
+    Command::new(env!("CARGO_BIN_EXE_nbterm"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nbterm-cli-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Title"]);
+    let mut result = MimeBundle::text_plain("2");
+    result.insert("image/png", json!("aGVsbG8="));
+    notebook.push_code_cell(
+        vec!["1 + 1"],
+        Some(3),
+        vec![Output::ExecuteResult {
+            execution_count: Some(3),
+            data: result,
+            metadata: json!({}),
+            other: json!({}),
+        }],
+    );
+    notebook.save_to_file(dir.join("nb.ipynb")).unwrap();
+    dir
+}
+
+#[test]
+fn cat_prints_cells_and_outputs() {
+    let dir = scratch("cat");
+    let output = nbterm(&["cat", "nb.ipynb", "--color", "never"], &dir);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "# Title\n\nIn [3]:\n    1 + 1\n\nOut[3]:\n    2\n"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn convert_writes_next_to_the_input() {
+    let dir = scratch("convert");
+    assert!(
+        nbterm(&["convert", "nb.ipynb", "--to", "py:percent"], &dir)
+            .status
+            .success()
+    );
+    let script = fs::read_to_string(dir.join("nb.py")).unwrap();
+    assert!(script.contains("# %%\n1 + 1\n"));
+
+    let output = nbterm(&["convert", "nb.ipynb", "--to", "docx"], &dir);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown format `docx`"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn strip_check_fails_until_stripped() {
+    let dir = scratch("strip");
+    fs::write(dir.join(".nbterm.json"), "{").unwrap();
+    let output = nbterm(&["strip", "nb.ipynb"], &dir);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to parse settings"));
+    fs::remove_file(dir.join(".nbterm.json")).unwrap();
+
+    assert_eq!(
+        nbterm(&["strip", "--check", "nb.ipynb"], &dir)
+            .status
+            .code(),
+        Some(1)
+    );
+    assert!(nbterm(&["strip", "nb.ipynb"], &dir).status.success());
+    assert!(
+        nbterm(&["strip", "--check", "nb.ipynb"], &dir)
+            .status
+            .success()
+    );
+    let notebook = Notebook::from_file(dir.join("nb.ipynb")).unwrap();
+    assert!(notebook.code_cells().all(|cell| cell.outputs.is_empty()));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn validate_reports_violations() {
+    let dir = scratch("validate");
+    assert!(nbterm(&["validate", "nb.ipynb"], &dir).status.success());
+    fs::write(
+        dir.join("bad.ipynb"),
+        r#"{"cells": [{"cell_type": "code"}], "metadata": {}, "nbformat": 4, "nbformat_minor": 5}"#,
+    )
+    .unwrap();
+    // older versions are checked as loading upgrades them
+    fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/notebooks/v3/ipython2.ipynb"),
+        dir.join("v3.ipynb"),
+    )
+    .unwrap();
+    assert!(nbterm(&["validate", "v3.ipynb"], &dir).status.success());
+    let output = nbterm(&["validate", "nb.ipynb", "bad.ipynb"], &dir);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("nb.ipynb: valid\nbad.ipynb: cells[0]"));
+    assert_eq!(
+        nbterm(&["validate", "missing.ipynb"], &dir).status.code(),
+        Some(2)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Not a test: the fake kernel, run in a process of its own for `run` by
+/// the kernelspec [`run_saves_the_outputs_of_a_dying_kernel`] installs.
+#[test]
+#[ignore]
+fn fake_kernel_process() {
+    if std::env::var_os("NBTERM_FAKE_KERNEL").is_none() {
+        return;
+    }
+    // the connection file comes as a second test name filter
+    let path = std::env::args().find(|arg| arg.ends_with(".json")).unwrap();
+    common::serve_connection_file(Path::new(&path));
+}
+
+#[cfg(unix)]
+#[test]
+fn run_saves_the_outputs_of_a_dying_kernel() {
+    let dir = scratch("run");
+    let kernel = dir.join("data/kernels/fake");
+    fs::create_dir_all(&kernel).unwrap();
+    let exe = std::env::current_exe().unwrap();
+    let spec = json!({
+        "argv": [exe, "fake_kernel_process", "{connection_file}", "--exact", "--ignored"],
+        "display_name": "Fake",
+        "language": "fake",
+        "env": { "NBTERM_FAKE_KERNEL": "1" },
+    });
+    fs::write(kernel.join("kernel.json"), spec.to_string()).unwrap();
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec!["print before"], None, vec![]);
+    notebook.push_code_cell(vec!["exit"], None, vec![]);
+    notebook.push_code_cell(vec!["print after"], None, vec![]);
+    notebook.save_to_file(dir.join("run.ipynb")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nbterm"))
+        .args(["run", "run.ipynb", "--kernel", "fake"])
+        .env("JUPYTER_PATH", dir.join("data"))
+        .env("JUPYTER_RUNTIME_DIR", dir.join("runtime"))
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The kernel died"));
+    let notebook = Notebook::from_file(dir.join("run.ipynb")).unwrap();
+    let outputs: Vec<_> = notebook
+        .code_cells()
+        .map(|cell| cell.outputs.len())
+        .collect();
+    assert_eq!(outputs, [1, 0, 0]);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,458 @@
+//! A fake Jupyter kernel for the kernel tests.
+//!
+//! It runs a tiny line-based language, one statement per line:
+//! `print <text>` writes a line to stdout, `write <text>` writes text with
+//! `\r` and `\n` escapes, `display <text>` shows a display_data that
+//! `update <text>` replaces, `clear [wait]` clears the outputs, `fail <text>`
+//! raises an error, `input <prompt>` and `password <prompt>` ask for a line
+//! on stdin and print it, `exit` ends the process the kernel runs in (only
+//! for [`serve_connection_file`]), and any other line is echoed back as the
+//! cell's result.
+//!
+//! Of the user expressions, `variables` lists some variables like the
+//! introspection snippets do, `fail <text>` raises an error, and any other
+//! is its own value.
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use nbterm::kernel::{Channel, ConnectionInfo, ExecutionQueue, KernelClient, Message, Signer};
+use nbterm::notebook_util::Notebook;
+use serde_json::{Value, json};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use zeromq::prelude::*;
+use zeromq::{Endpoint, PubSocket, RepSocket, RouterSocket, ZmqMessage};
+
+pub const KEY: &str = "fake-kernel-key";
+
+/// Starts a fake kernel on a background thread and returns where it listens.
+pub fn start() -> ConnectionInfo {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(serve(None, tx));
+    });
+    rx.recv().unwrap()
+}
+
+/// Runs a fake kernel on the ports and key of a connection file, as a
+/// kernel process does, until it is shut down.
+pub fn serve_connection_file(path: &std::path::Path) {
+    let info = ConnectionInfo::from_file(path).unwrap();
+    let (tx, _rx) = mpsc::channel();
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(serve(Some(info), tx));
+}
+
+/// How long the tests wait for the fake kernel.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connects to a fake kernel of its own, once it answers.
+pub fn client() -> KernelClient {
+    let client = KernelClient::connect(start()).unwrap();
+    client.kernel_info(TIMEOUT).unwrap();
+    client
+}
+
+/// Feeds the kernel's messages to `state` with `handle` until `done` holds,
+/// failing the test after [`TIMEOUT`].
+pub fn pump<T>(
+    client: &KernelClient,
+    state: &mut T,
+    done: impl Fn(&T) -> bool,
+    mut handle: impl FnMut(&mut T, Channel, &Message),
+) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !done(state) {
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting for the kernel"
+        );
+        if let Some((channel, message)) = client.recv_timeout(Duration::from_millis(100)) {
+            handle(state, channel, &message);
+        }
+    }
+}
+
+/// Runs the cells queued in `queue` until `done` holds.
+pub fn run_until(
+    queue: &mut ExecutionQueue,
+    notebook: &mut Notebook,
+    client: &KernelClient,
+    done: impl Fn(&ExecutionQueue) -> bool,
+) {
+    queue.start_next(notebook, client).unwrap();
+    pump(
+        client,
+        &mut (queue, notebook),
+        |(queue, _)| done(queue),
+        |(queue, notebook), channel, message| {
+            queue.handle(notebook, channel, message);
+            queue.start_next(notebook, client).unwrap();
+        },
+    );
+}
+
+/// Runs the cells queued in `queue` until it has nothing left to run.
+pub fn run(queue: &mut ExecutionQueue, notebook: &mut Notebook, client: &KernelClient) {
+    run_until(queue, notebook, client, |queue| !queue.is_busy());
+}
+
+/// Binds `socket` to `port` on localhost, any free one for 0.
+async fn bind<S: Socket>(socket: &mut S, port: u16) -> u16 {
+    match socket
+        .bind(&format!("tcp://127.0.0.1:{port}"))
+        .await
+        .unwrap()
+    {
+        Endpoint::Tcp(_, port) => port,
+        endpoint => panic!("unexpected endpoint {endpoint}"),
+    }
+}
+
+fn to_zmq(frames: Vec<Vec<u8>>) -> ZmqMessage {
+    ZmqMessage::try_from(frames.into_iter().map(Bytes::from).collect::<Vec<_>>()).unwrap()
+}
+
+fn from_zmq(message: ZmqMessage) -> Vec<Vec<u8>> {
+    message
+        .into_vec()
+        .into_iter()
+        .map(|frame| frame.to_vec())
+        .collect()
+}
+
+struct Kernel {
+    signer: Signer,
+    iopub: PubSocket,
+    stdin: RouterSocket,
+    execution_count: u32,
+}
+
+impl Kernel {
+    async fn publish(&mut self, parent: &Message, msg_type: &str, content: Value) {
+        let mut message = parent.reply(msg_type, content);
+        message.identities = vec![format!("kernel.{msg_type}").into_bytes()];
+        let frames = message.to_frames(&self.signer).unwrap();
+        self.iopub.send(to_zmq(frames)).await.unwrap();
+    }
+
+    async fn status(&mut self, parent: &Message, state: &str) {
+        self.publish(parent, "status", json!({ "execution_state": state }))
+            .await;
+    }
+
+    /// Handles a shell or control request and returns the reply.
+    async fn handle(&mut self, request: &Message) -> Option<Message> {
+        self.status(request, "busy").await;
+        let reply = match request.msg_type() {
+            "kernel_info_request" => request.reply(
+                "kernel_info_reply",
+                json!({
+                    "status": "ok",
+                    "protocol_version": "5.3",
+                    "implementation": "fake",
+                    "language_info": { "name": "fake", "file_extension": ".txt" },
+                }),
+            ),
+            "execute_request" => self.execute(request).await,
+            "complete_request" => complete(request),
+            "shutdown_request" => request.reply("shutdown_reply", request.content.clone()),
+            _ => return None,
+        };
+        self.status(request, "idle").await;
+        Some(reply)
+    }
+
+    async fn execute(&mut self, request: &Message) -> Message {
+        self.execution_count += 1;
+        let count = self.execution_count;
+        let code = request.content["code"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        self.publish(
+            request,
+            "execute_input",
+            json!({ "code": code, "execution_count": count }),
+        )
+        .await;
+        for line in code.lines() {
+            let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+            match command {
+                "print" => {
+                    self.publish(
+                        request,
+                        "stream",
+                        json!({ "name": "stdout", "text": format!("{argument}\n") }),
+                    )
+                    .await
+                }
+                "display" => {
+                    self.publish(
+                        request,
+                        "display_data",
+                        json!({
+                            "data": { "text/plain": argument },
+                            "metadata": {},
+                            "transient": { "display_id": "display" },
+                        }),
+                    )
+                    .await
+                }
+                "write" => {
+                    let text = argument.replace("\\r", "\r").replace("\\n", "\n");
+                    self.publish(request, "stream", json!({ "name": "stdout", "text": text }))
+                        .await
+                }
+                "update" => {
+                    self.publish(
+                        request,
+                        "update_display_data",
+                        json!({
+                            "data": { "text/plain": argument },
+                            "metadata": {},
+                            "transient": { "display_id": "display" },
+                        }),
+                    )
+                    .await
+                }
+                "clear" => {
+                    self.publish(
+                        request,
+                        "clear_output",
+                        json!({ "wait": argument == "wait" }),
+                    )
+                    .await
+                }
+                "input" | "password" if request.content["allow_stdin"] != true => {
+                    return self
+                        .fail(request, "StdinNotImplementedError", "no stdin")
+                        .await;
+                }
+                "input" | "password" => {
+                    let ask = request.reply(
+                        "input_request",
+                        json!({ "prompt": argument, "password": command == "password" }),
+                    );
+                    let frames = ask.to_frames(&self.signer).unwrap();
+                    self.stdin.send(to_zmq(frames)).await.unwrap();
+                    let value = loop {
+                        let frames = from_zmq(self.stdin.recv().await.unwrap());
+                        let reply = Message::from_frames(frames, &self.signer).unwrap();
+                        if reply.msg_type() == "input_reply" {
+                            break reply.content["value"].as_str().unwrap().to_string();
+                        }
+                    };
+                    self.publish(
+                        request,
+                        "stream",
+                        json!({ "name": "stdout", "text": format!("got {value}\n") }),
+                    )
+                    .await
+                }
+                "fail" => return self.fail(request, "Error", argument).await,
+                "exit" => std::process::exit(1),
+                "" => {}
+                _ => {
+                    self.publish(
+                        request,
+                        "execute_result",
+                        json!({
+                            "execution_count": count,
+                            "data": { "text/plain": line },
+                            "metadata": {},
+                        }),
+                    )
+                    .await
+                }
+            }
+        }
+        let user_expressions: serde_json::Map<String, Value> = request.content["user_expressions"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, expression)| {
+                (
+                    name.clone(),
+                    user_expression(expression.as_str().unwrap_or_default()),
+                )
+            })
+            .collect();
+        request.reply(
+            "execute_reply",
+            json!({
+                "status": "ok",
+                "execution_count": count,
+                "user_expressions": user_expressions,
+            }),
+        )
+    }
+
+    /// Raises `ename` in the cell being run and returns the error reply.
+    async fn fail(&mut self, request: &Message, ename: &str, evalue: &str) -> Message {
+        let error = json!({
+            "ename": ename,
+            "evalue": evalue,
+            "traceback": [format!("{ename}: {evalue}")],
+        });
+        self.publish(request, "error", error.clone()).await;
+        let mut content = error;
+        content["status"] = json!("error");
+        content["execution_count"] = json!(self.execution_count);
+        request.reply("execute_reply", content)
+    }
+}
+
+/// Completes the word before the cursor with the names of the fake
+/// language's statements, with IPython's type hints.
+fn complete(request: &Message) -> Message {
+    let code: Vec<char> = request.content["code"]
+        .as_str()
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    let cursor = request.content["cursor_pos"].as_u64().unwrap_or_default() as usize;
+    let start = code[..cursor]
+        .iter()
+        .rposition(|c| !c.is_alphanumeric())
+        .map_or(0, |index| index + 1);
+    let prefix: String = code[start..cursor].iter().collect();
+    let hints: Vec<Value> = ["clear", "display", "fail", "input", "print"]
+        .into_iter()
+        .filter(|name| name.starts_with(&prefix))
+        .map(|name| {
+            json!({
+                "start": start,
+                "end": cursor,
+                "text": name,
+                "type": "statement",
+                "signature": format!("{name} <text>"),
+            })
+        })
+        .collect();
+    request.reply(
+        "complete_reply",
+        json!({
+            "status": "ok",
+            "matches": hints.iter().map(|hint| hint["text"].clone()).collect::<Vec<_>>(),
+            "cursor_start": start,
+            "cursor_end": cursor,
+            "metadata": { "_jupyter_types_experimental": hints },
+        }),
+    )
+}
+
+/// Serves a fake kernel on the ports and key of `info`, or on free ports
+/// with [`KEY`], and sends where it listens to `ready`.
+async fn serve(info: Option<ConnectionInfo>, ready: mpsc::Sender<ConnectionInfo>) {
+    let (mut shell, mut control, mut stdin) = (
+        RouterSocket::new(),
+        RouterSocket::new(),
+        RouterSocket::new(),
+    );
+    let (mut iopub, mut hb) = (PubSocket::new(), RepSocket::new());
+    let port = |port: fn(&ConnectionInfo) -> u16| info.as_ref().map_or(0, port);
+    let key = info.as_ref().map_or(KEY, |info| info.key.as_str());
+    let info = ConnectionInfo {
+        transport: "tcp".to_string(),
+        ip: "127.0.0.1".to_string(),
+        shell_port: bind(&mut shell, port(|info| info.shell_port)).await,
+        iopub_port: bind(&mut iopub, port(|info| info.iopub_port)).await,
+        stdin_port: bind(&mut stdin, port(|info| info.stdin_port)).await,
+        control_port: bind(&mut control, port(|info| info.control_port)).await,
+        hb_port: bind(&mut hb, port(|info| info.hb_port)).await,
+        key: key.to_string(),
+        signature_scheme: "hmac-sha256".to_string(),
+        other: Default::default(),
+    };
+    let signer = Signer::new(&info.key);
+    let _ = ready.send(info);
+
+    let mut kernel = Kernel {
+        signer: signer.clone(),
+        iopub,
+        stdin,
+        execution_count: 0,
+    };
+    loop {
+        tokio::select! {
+            request = shell.recv() => {
+                let Ok(request) = request else { break };
+                let Ok(request) = Message::from_frames(from_zmq(request), &signer) else {
+                    continue;
+                };
+                if let Some(reply) = kernel.handle(&request).await {
+                    shell.send(to_zmq(reply.to_frames(&signer).unwrap())).await.unwrap();
+                }
+            }
+            request = control.recv() => {
+                let Ok(request) = request else { break };
+                let Ok(request) = Message::from_frames(from_zmq(request), &signer) else {
+                    continue;
+                };
+                let shutdown = request.msg_type() == "shutdown_request";
+                if let Some(reply) = kernel.handle(&request).await {
+                    control.send(to_zmq(reply.to_frames(&signer).unwrap())).await.unwrap();
+                }
+                if shutdown {
+                    break;
+                }
+            }
+            ping = hb.recv() => {
+                let Ok(ping) = ping else { break };
+                hb.send(ping).await.unwrap();
+            }
+        }
+    }
+}
+
+/// Evaluates a user expression into its reply.
+fn user_expression(expression: &str) -> Value {
+    let (command, argument) = expression.split_once(' ').unwrap_or((expression, ""));
+    match command {
+        "variables" => {
+            let variables = json!([
+                {
+                    "name": "frame",
+                    "type": "DataFrame",
+                    "shape": [3, 2],
+                    "repr": "   a  b\n0  1  2",
+                },
+                {
+                    "name": "items",
+                    "type": "list",
+                    "shape": [2],
+                    "repr": "[1, 'two']",
+                    "children": [
+                        { "name": "0", "type": "int", "shape": null, "repr": "1" },
+                        { "name": "1", "type": "str", "shape": [3], "repr": "'two'" },
+                    ],
+                },
+                { "name": "answer", "type": "int", "shape": null, "repr": "42" },
+            ]);
+            json!({
+                "status": "ok",
+                "data": { "application/json": variables, "text/plain": "<3 variables>" },
+                "metadata": {},
+            })
+        }
+        "fail" => json!({
+            "status": "error",
+            "ename": "Error",
+            "evalue": argument,
+            "traceback": [format!("Error: {argument}")],
+        }),
+        _ => json!({
+            "status": "ok",
+            "data": { "text/plain": expression },
+            "metadata": {},
+        }),
+    }
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use nbterm::kernel::completion::parse_complete_reply;
+use nbterm::kernel::{Completer, Completion};
+use serde_json::json;
+
+fn completion(text: &str, range: std::ops::Range<usize>) -> Completion {
+    Completion {
+        text: text.to_string(),
+        range,
+        kind: None,
+        signature: None,
+    }
+}
+
+#[test]
+fn completes_at_the_cursor_with_type_hints() {
+    let client = common::client();
+    let mut completer = Completer::default();
+    // the kernel counts code points, the completions bytes
+    let code = "é = pr\nprint done";
+    completer.request(&client, code, 7).unwrap();
+    common::pump(
+        &client,
+        &mut completer,
+        |completer| !completer.is_busy(),
+        |completer, channel, message| {
+            completer.handle(channel, message);
+        },
+    );
+
+    let reply = completer.take_reply().unwrap();
+    assert_eq!((reply.code.as_str(), reply.cursor), (code, 7));
+    let [print] = reply.completions.as_slice() else {
+        panic!("unexpected completions {:?}", reply.completions);
+    };
+    assert_eq!(print.range, 5..7);
+    assert_eq!(print.kind.as_deref(), Some("statement"));
+    assert_eq!(print.signature.as_deref(), Some("print <text>"));
+    assert_eq!(print.apply(code), ("é = print\nprint done".to_string(), 10));
+}
+
+#[test]
+fn falls_back_to_plain_matches() {
+    let reply = json!({
+        "status": "ok",
+        "matches": ["np.array", "np.arange"],
+        "cursor_start": 2,
+        "cursor_end": 6,
+        "metadata": {},
+    });
+    let completions = parse_complete_reply(&reply, "x=np.a");
+    assert_eq!(
+        completions,
+        [completion("np.array", 2..6), completion("np.arange", 2..6),]
+    );
+    assert_eq!(
+        completions[1].apply("x=np.a"),
+        ("x=np.arange".to_string(), 11)
+    );
+    assert!(parse_complete_reply(&json!({ "status": "error" }), "x").is_empty());
+}
@@ -0,0 +1,114 @@
+use nbterm::notebook_util::validation;
+use nbterm::notebook_util::{Cell, Notebook, Output};
+use serde_json::Value;
+use std::path::Path;
+
+fn v3_notebook() -> Notebook {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/notebooks/v3/ipython2.ipynb");
+    Notebook::from_file(path).unwrap()
+}
+
+#[test]
+fn v3_notebooks_are_upgraded_on_load() {
+    let notebook = v3_notebook();
+    assert_eq!((notebook.nbformat, notebook.nbformat_minor), (4, 5));
+    assert_eq!(notebook.original_version, Some((3, 0)));
+    assert_eq!(notebook.len(), 5);
+    assert!(notebook.iter().all(|cell| cell.id().is_some()));
+    assert!(notebook.validate().unwrap().is_empty());
+
+    let Cell::Markdown(heading) = &notebook.cells[0] else {
+        panic!("heading cell should become markdown");
+    };
+    assert_eq!(heading.source.as_str(), "# Old notebook");
+
+    let Cell::Code(code) = &notebook.cells[3] else {
+        panic!("expected a code cell");
+    };
+    assert_eq!(code.source.as_str(), "print('hi')\n1/0");
+    assert_eq!(code.execution_count, Some(2));
+    assert_eq!(code.metadata["collapsed"], true);
+    assert!(matches!(&code.outputs[0], Output::Stream { name, .. } if name == "stdout"));
+    assert!(matches!(
+        &code.outputs[1],
+        Output::DisplayData { data, metadata, .. }
+            if data.png().is_some_and(|png| png.is_ok()) && metadata["image/png"]["width"] == 10
+    ));
+    assert!(matches!(&code.outputs[2], Output::Error { .. }));
+}
+
+#[test]
+fn older_v4_minors_are_upgraded_and_downgraded_on_request() {
+    let content = r#"{
+ "cells": [
+  {
+   "cell_type": "markdown",
+   "metadata": {},
+   "source": [
+    "hi"
+   ]
+  }
+ ],
+ "metadata": {},
+ "nbformat": 4,
+ "nbformat_minor": 2
+}
+"#;
+    let notebook = Notebook::from_str(content).unwrap();
+    assert_eq!(notebook.nbformat_minor, 5);
+    assert_eq!(notebook.original_version, Some((4, 2)));
+    assert!(notebook.cells[0].id().is_some());
+    assert!(notebook.validate().unwrap().is_empty());
+
+    let saved: Value = serde_json::from_str(&notebook.save_to_str().unwrap()).unwrap();
+    assert_eq!(saved["nbformat_minor"], 5);
+    assert_eq!(saved["cells"][0]["id"], notebook.cells[0].id().unwrap());
+    assert_eq!(
+        notebook.save_to_str_as_version(4, 2).unwrap(),
+        content,
+        "downgrading to the original version gives the file back"
+    );
+}
+
+#[test]
+fn downgrade_to_v4_4_drops_cell_ids() {
+    let saved: Value =
+        serde_json::from_str(&v3_notebook().save_to_str_as_version(4, 4).unwrap()).unwrap();
+    assert_eq!(saved["nbformat_minor"], 4);
+    assert!(saved["cells"][0].get("id").is_none());
+    assert_eq!(validation::validate(&saved), vec![]);
+}
+
+#[test]
+fn downgrade_to_v3_roundtrips() {
+    let mut notebook = v3_notebook();
+    let saved: Value = serde_json::from_str(&notebook.save_to_str().unwrap()).unwrap();
+    assert_eq!(saved["cells"][0]["metadata"], serde_json::json!({}));
+
+    let v3 = notebook.save_to_str_as_version(3, 0).unwrap();
+    let value: Value = serde_json::from_str(&v3).unwrap();
+    assert_eq!(value["nbformat"], 3);
+    let cells = &value["worksheets"][0]["cells"];
+    assert_eq!(cells[0]["cell_type"], "heading");
+    assert_eq!(cells[0]["metadata"], serde_json::json!({}));
+    assert_eq!(cells[1]["cell_type"], "markdown");
+    assert_eq!(cells[2]["outputs"][0]["output_type"], "pyout");
+
+    let again = Notebook::from_str(&v3).unwrap();
+    let sources = |nb: &Notebook| -> Vec<String> {
+        nb.iter()
+            .map(|cell| match cell {
+                Cell::Code(c) => c.source.to_string(),
+                Cell::Markdown(c) => c.source.to_string(),
+                Cell::Raw(c) => c.source.to_string(),
+            })
+            .collect()
+    };
+    assert_eq!(sources(&notebook), sources(&again));
+
+    // only cells that were headings in v3 become heading cells again
+    notebook.push_markdown_cell(vec!["# Added later"]);
+    let value: Value =
+        serde_json::from_str(&notebook.save_to_str_as_version(3, 0).unwrap()).unwrap();
+    assert_eq!(value["worksheets"][0]["cells"][5]["cell_type"], "markdown");
+}
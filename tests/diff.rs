@@ -0,0 +1,140 @@
+use nbterm::notebook_util::diff::{CellStatus, ChangeKind, LineDiff, OutputChange, diff_lines};
+use nbterm::notebook_util::{Cell, MimeBundle, Notebook, Output};
+use nbterm::tui::diff_view::DiffView;
+use serde_json::json;
+
+fn notebook() -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Title"]);
+    notebook.push_code_cell(
+        vec![
+            "import pandas as pd\n",
+            "df = pd.read_csv('data.csv')\n",
+            "df.head()",
+        ],
+        Some(1),
+        vec![Output::execute_result(1, "   a  b")],
+    );
+    notebook.push_code_cell(vec!["print('done')"], Some(2), vec![]);
+    notebook
+}
+
+fn source_mut(cell: &mut Cell) -> &mut String {
+    &mut cell.source_mut().0
+}
+
+#[test]
+fn aligns_cells_by_id() {
+    let old = notebook();
+    let mut new = old.clone();
+    *source_mut(&mut new.cells[1]) =
+        "import pandas as pd\ndf = pd.read_csv('other.csv')\ndf.head()".to_string();
+    new.cells.remove(2);
+    new.push_markdown_cell(vec!["Notes"]);
+
+    let diff = old.diff(&new);
+    let statuses: Vec<_> = diff.cells.iter().map(|cell| cell.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            CellStatus::Unchanged,
+            CellStatus::Modified,
+            CellStatus::Removed,
+            CellStatus::Added
+        ]
+    );
+    assert_eq!(
+        diff.cells[1].source,
+        [
+            LineDiff::Same("import pandas as pd".to_string()),
+            LineDiff::Removed("df = pd.read_csv('data.csv')".to_string()),
+            LineDiff::Added("df = pd.read_csv('other.csv')".to_string()),
+            LineDiff::Same("df.head()".to_string()),
+        ]
+    );
+    assert_eq!((diff.cells[2].old, diff.cells[2].new), (Some(2), None));
+    assert_eq!((diff.cells[3].old, diff.cells[3].new), (None, Some(2)));
+}
+
+#[test]
+fn falls_back_to_similarity_and_detects_moves() {
+    let old = notebook();
+    let mut new = old.clone();
+    for cell in &mut new.cells {
+        if let Cell::Code(code) = cell {
+            code.id = None;
+        }
+    }
+    let first = new.cells.remove(0);
+    new.cells.push(first);
+    source_mut(&mut new.cells[0]).push_str(".tail()");
+
+    let diff = old.diff(&new);
+    let changes: Vec<_> = diff.changes().map(|cell| cell.summary()).collect();
+    assert_eq!(
+        changes,
+        ["cell 1 -> 0 modified", "cell 0 -> 2 unchanged, moved"]
+    );
+    assert!(old.diff(&old.clone()).is_empty());
+}
+
+#[test]
+fn summarizes_outputs_by_mime_type() {
+    let old = notebook();
+    let mut new = old.clone();
+    let Cell::Code(code) = &mut new.cells[1] else {
+        unreachable!()
+    };
+    let mut data = MimeBundle::text_plain("   a  b\n0  1  2");
+    data.insert("image/png", json!("aGVsbG8="));
+    code.outputs = vec![
+        Output::stream_stderr("warning\n"),
+        Output::DisplayData {
+            data,
+            metadata: json!({}),
+            other: json!({}),
+        },
+    ];
+    code.execution_count = Some(7);
+
+    let diff = old.diff(&new);
+    assert_eq!(
+        diff.cells[1].outputs,
+        [
+            OutputChange {
+                kind: "image/png".to_string(),
+                change: ChangeKind::Added
+            },
+            OutputChange {
+                kind: "stderr".to_string(),
+                change: ChangeKind::Added
+            },
+            OutputChange {
+                kind: "text/plain".to_string(),
+                change: ChangeKind::Modified
+            },
+        ]
+    );
+    assert_eq!(diff_lines("a\nb\n", "a\nb\n").len(), 2);
+    assert!(
+        diff.to_string()
+            .contains("outputs: image/png added, stderr added")
+    );
+}
+
+#[test]
+fn diff_view_scrolls_within_its_lines() {
+    let old = notebook();
+    let mut new = old.clone();
+    new.push_code_cell(vec!["x = 1"], None, vec![]);
+    let mut view = DiffView::new("saved", old.diff(&new));
+    view.scroll_up(3);
+    assert_eq!(view.scroll, 0);
+    view.scroll_down(1);
+    assert_eq!(view.scroll, 1);
+    // a heading, the added line and a blank line
+    view.scroll_down(u16::MAX);
+    assert_eq!(view.scroll, 2);
+    view.scroll_up(1);
+    assert_eq!(view.scroll, 1);
+}
@@ -0,0 +1,206 @@
+use nbterm::notebook_util::{Cell, CellType, MimeBundle, Notebook, Output};
+use nbterm::tui::editor_tab::EditorTab;
+use serde_json::json;
+
+fn notebook() -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Title"]);
+    notebook.push_code_cell(
+        vec!["a = 1\nb = 2"],
+        Some(1),
+        vec![Output::stream_stdout("x\n")],
+    );
+    notebook.push_code_cell(
+        vec!["print(a + b)"],
+        Some(2),
+        vec![Output::stream_stdout("3\n")],
+    );
+    notebook.push_raw_cell(vec!["raw"]);
+    notebook
+}
+
+fn sources(notebook: &Notebook) -> Vec<&str> {
+    notebook.iter().map(|cell| cell.source().as_str()).collect()
+}
+
+/// Applies `edit` through `op`, checks the result, then checks that undoing
+/// restores the original notebook exactly.
+fn check(
+    op: impl FnOnce(&mut Notebook) -> Option<nbterm::notebook_util::CellEdit>,
+    expected: &[&str],
+) {
+    let original = notebook();
+    let mut edited = original.clone();
+    let undo = op(&mut edited).expect("edit applies");
+    assert_eq!(sources(&edited), expected);
+    edited.apply(undo).expect("undo applies");
+    assert_eq!(edited, original);
+}
+
+#[test]
+fn structural_edits_are_reversible() {
+    check(|nb| nb.delete_cell(1), &["# Title", "print(a + b)", "raw"]);
+    check(|nb| nb.delete_cells(1..3), &["# Title", "raw"]);
+    check(
+        |nb| nb.move_cell_up(2),
+        &["# Title", "print(a + b)", "a = 1\nb = 2", "raw"],
+    );
+    check(
+        |nb| nb.move_cell_down(0),
+        &["a = 1\nb = 2", "# Title", "print(a + b)", "raw"],
+    );
+    check(
+        |nb| nb.swap_cells(0, 3),
+        &["raw", "a = 1\nb = 2", "print(a + b)", "# Title"],
+    );
+    check(
+        |nb| nb.duplicate_cell(3),
+        &["# Title", "a = 1\nb = 2", "print(a + b)", "raw", "raw"],
+    );
+    check(
+        |nb| nb.split_cell(1, 6),
+        &["# Title", "a = 1", "b = 2", "print(a + b)", "raw"],
+    );
+    check(
+        |nb| nb.merge_cells(1),
+        &["# Title", "a = 1\nb = 2\nprint(a + b)", "raw"],
+    );
+    check(
+        |nb| nb.change_cell_type(1, CellType::Markdown),
+        &["# Title", "a = 1\nb = 2", "print(a + b)", "raw"],
+    );
+    check(
+        |nb| nb.set_source(2, "print(a)"),
+        &["# Title", "a = 1\nb = 2", "print(a)", "raw"],
+    );
+}
+
+#[test]
+fn typing_is_undone_at_once() {
+    let mut tab = EditorTab::default();
+    tab.content = notebook();
+    tab.current_cell = 2;
+    let original = tab.content.clone();
+    for c in ["x", " ", "=", " ", "1"] {
+        tab.insert_text(c);
+    }
+    assert_eq!(tab.content.cells[2].source().as_str(), "x = 1print(a + b)");
+    let Cell::Code(cell) = &tab.content.cells[2] else {
+        unreachable!()
+    };
+    assert_eq!(cell.outputs.len(), 1);
+
+    tab.current_cell = 1;
+    tab.insert_text("#");
+    assert!(tab.undo());
+    assert_eq!(tab.content.cells[1].source().as_str(), "a = 1\nb = 2");
+    assert!(tab.undo());
+    assert_eq!(tab.content, original);
+}
+
+#[test]
+fn out_of_bounds_edits_leave_the_notebook_untouched() {
+    let original = notebook();
+    let mut nb = original.clone();
+    assert!(nb.move_cell_up(0).is_none());
+    assert!(nb.move_cell_down(3).is_none());
+    assert!(nb.move_cell_down(usize::MAX).is_none());
+    assert!(nb.delete_cell(usize::MAX).is_none());
+    assert!(nb.duplicate_cell(usize::MAX).is_none());
+    assert!(nb.merge_cells(3).is_none());
+    assert!(nb.delete_cells(2..9).is_none());
+    assert!(nb.split_cell(0, 100).is_none());
+    assert_eq!(nb, original);
+}
+
+#[test]
+fn merged_code_cells_keep_all_outputs() {
+    let mut nb = notebook();
+    nb.merge_cells(1).unwrap();
+    let Cell::Code(code) = &nb.cells[1] else {
+        panic!("expected a code cell");
+    };
+    assert_eq!(code.outputs.len(), 2);
+    assert_eq!(code.execution_count, None);
+}
+
+#[test]
+fn merges_that_would_lose_outputs_or_attachments_are_refused() {
+    let attach = |nb: &mut Notebook, index: usize, name: &str, png: &str| {
+        let Cell::Markdown(cell) = &mut nb.cells[index] else {
+            panic!("expected a markdown cell");
+        };
+        let bundle: MimeBundle = [("image/png", json!(png))].into_iter().collect();
+        cell.attachments
+            .get_or_insert_with(Default::default)
+            .insert(name.to_string(), bundle);
+    };
+    let mut nb = notebook();
+    nb.insert_markdown_cell(1, vec!["![b](attachment:b.png)"]);
+    attach(&mut nb, 0, "a.png", "AAAA");
+    attach(&mut nb, 1, "b.png", "BBBB");
+    let original = nb.clone();
+
+    // the markdown cell has no room for the code cell's outputs
+    assert!(nb.merge_cells(1).is_none());
+    // nor the code cell for attachments
+    nb.change_cell_type(0, CellType::Code).unwrap();
+    assert!(nb.merge_cells(0).is_none());
+    nb = original.clone();
+    attach(&mut nb, 1, "a.png", "CCCC");
+    assert!(nb.merge_cells(0).is_none());
+
+    nb = original;
+    nb.merge_cells(0).unwrap();
+    let names: Vec<_> = nb.cells[0].attachments().unwrap().keys().collect();
+    assert_eq!(names, ["a.png", "b.png"]);
+}
+
+#[test]
+fn change_cell_type_keeps_id_and_metadata() {
+    let mut nb = notebook();
+    *nb.cells[1].metadata_mut() = serde_json::json!({"tags": ["keep"]});
+    let id = nb.cells[1].id().map(str::to_string);
+    nb.change_cell_type(1, CellType::Raw).unwrap();
+    assert_eq!(nb.cells[1].cell_type(), CellType::Raw);
+    assert_eq!(nb.cells[1].id().map(str::to_string), id);
+    assert_eq!(nb.cells[1].metadata()["tags"][0], "keep");
+}
+
+#[test]
+fn pasted_cells_get_unique_ids() {
+    let mut source = notebook();
+    let mut target = notebook();
+    let clipboard = source.copy_cells(1..3).unwrap();
+    target.paste_cells(4, clipboard.clone()).unwrap();
+    source.paste_cells(0, clipboard).unwrap();
+
+    for nb in [&source, &target] {
+        let mut ids: Vec<_> = nb.iter().map(|cell| cell.id().unwrap()).collect();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), nb.len());
+    }
+    assert_eq!(sources(&target)[4..], ["a = 1\nb = 2", "print(a + b)"]);
+}
+
+#[test]
+fn missing_ids_are_filled_with_unique_ones() {
+    let mut nb = Notebook::default();
+    for _ in 0..5000 {
+        nb.push_raw_cell(vec!["raw"]);
+    }
+    let kept = nb.cells[0].id().unwrap().to_string();
+    for cell in &mut nb.cells[1..] {
+        if let Cell::Raw(raw) = cell {
+            raw.id = None;
+        }
+    }
+    nb.ensure_cell_ids();
+
+    assert_eq!(nb.cells[0].id(), Some(kept.as_str()));
+    let mut ids: Vec<_> = nb.iter().map(|cell| cell.id().unwrap()).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), nb.len());
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use nbterm::kernel::{CellState, ExecutionQueue};
+use nbterm::notebook_util::{Cell, Notebook, Output};
+
+fn notebook() -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec!["print one"], None, vec![]);
+    notebook.push_markdown_cell(vec!["# Notes"]);
+    notebook.push_code_cell(vec!["two"], Some(9), vec![Output::stream_stdout("old\n")]);
+    notebook.push_code_cell(vec!["print three"], None, vec![]);
+    notebook
+}
+
+fn states(queue: &ExecutionQueue, notebook: &Notebook) -> Vec<Option<CellState>> {
+    notebook
+        .iter()
+        .map(|cell| cell.id().and_then(|id| queue.state(id)))
+        .collect()
+}
+
+fn outputs(notebook: &Notebook, index: usize) -> &[Output] {
+    let Cell::Code(cell) = &notebook.cells[index] else {
+        unreachable!()
+    };
+    &cell.outputs
+}
+
+#[test]
+fn runs_queued_cells_in_order() {
+    let client = common::client();
+    let mut notebook = notebook();
+    let mut queue = ExecutionQueue::default();
+    queue.enqueue(&mut notebook, 1..3);
+    queue.enqueue(&mut notebook, 0..4);
+    assert_eq!(queue.queued(), 3);
+    assert_eq!(
+        states(&queue, &notebook),
+        [
+            Some(CellState::Queued),
+            None,
+            Some(CellState::Queued),
+            Some(CellState::Queued)
+        ]
+    );
+    queue.start_next(&mut notebook, &client).unwrap();
+    assert_eq!(queue.running(), notebook.cells[2].id());
+    assert!(outputs(&notebook, 2).is_empty());
+
+    common::run(&mut queue, &mut notebook, &client);
+    assert_eq!(
+        states(&queue, &notebook),
+        [
+            Some(CellState::Done),
+            None,
+            Some(CellState::Done),
+            Some(CellState::Done)
+        ]
+    );
+    assert_eq!(outputs(&notebook, 2), [Output::execute_result(1, "two")]);
+    assert_eq!(outputs(&notebook, 0), [Output::stream_stdout("one\n")]);
+    let Cell::Code(last) = &notebook.cells[3] else {
+        unreachable!()
+    };
+    assert_eq!(last.execution_count, Some(3));
+}
+
+#[test]
+fn stops_at_the_first_error() {
+    let client = common::client();
+    let mut notebook = notebook();
+    notebook.cells[2].source_mut().0 = "fail boom".to_string();
+    let mut queue = ExecutionQueue::default();
+    queue.enqueue(&mut notebook, 0..4);
+    common::run(&mut queue, &mut notebook, &client);
+    assert_eq!(
+        states(&queue, &notebook),
+        [Some(CellState::Done), None, Some(CellState::Errored), None]
+    );
+    assert!(matches!(outputs(&notebook, 2), [Output::Error { .. }]));
+
+    queue.stop_on_error = false;
+    queue.enqueue(&mut notebook, 2..4);
+    common::run(&mut queue, &mut notebook, &client);
+    assert_eq!(
+        queue.state(notebook.cells[3].id().unwrap()),
+        Some(CellState::Done)
+    );
+}
+
+#[test]
+fn skips_cells_deleted_while_queued() {
+    let client = common::client();
+    let mut notebook = notebook();
+    let mut queue = ExecutionQueue::default();
+    queue.enqueue(&mut notebook, 0..4);
+    queue.start_next(&mut notebook, &client).unwrap();
+    notebook.cells.remove(2);
+    notebook.cells.insert(0, notebook.cells[2].clone());
+    notebook.cells.remove(3);
+
+    common::run(&mut queue, &mut notebook, &client);
+    assert!(!queue.is_busy());
+    assert_eq!(outputs(&notebook, 0), [Output::stream_stdout("three\n")]);
+    assert_eq!(outputs(&notebook, 1), [Output::stream_stdout("one\n")]);
+}
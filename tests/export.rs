@@ -0,0 +1,100 @@
+use nbterm::notebook_util::{Cell, MimeBundle, Notebook, Output};
+use serde_json::json;
+use std::fs;
+
+fn notebook(language: serde_json::Value) -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Load\n", "\n", "Reads the data."]);
+    let mut image = MimeBundle::text_plain("<Figure>");
+    image.insert("image/png", json!("aGVsbG8=\n"));
+    notebook.push_code_cell(
+        vec!["%matplotlib inline\n", "x = 1\n", "print(x)"],
+        Some(1),
+        vec![
+            Output::stream_stdout("1\n"),
+            Output::DisplayData {
+                data: image,
+                metadata: json!({}),
+                other: json!({}),
+            },
+        ],
+    );
+    notebook.metadata.language_info = serde_json::from_value(language).ok();
+    notebook
+}
+
+#[test]
+fn script_comments_markdown_and_magics() {
+    let script = notebook(json!({"name": "python", "file_extension": ".py"})).to_script();
+    assert_eq!(
+        script,
+        "#!/usr/bin/env python\n\n\
+         # # Load\n#\n# Reads the data.\n\n\
+         # %matplotlib inline\nx = 1\nprint(x)\n"
+    );
+}
+
+#[test]
+fn script_uses_the_language_comment_syntax() {
+    let notebook = notebook(json!({"name": "javascript", "file_extension": ".js"}));
+    assert_eq!(notebook.script_extension(), ".js");
+    let script = notebook.to_script();
+    assert!(script.starts_with("// # Load\n//\n// Reads the data.\n\n%matplotlib inline\n"));
+}
+
+#[test]
+fn markdown_document_links_extracted_images() {
+    let notebook = notebook(json!({"name": "python"}));
+    let document = notebook.to_markdown_document("report_files").unwrap();
+    assert_eq!(
+        document.markdown,
+        "# Load\n\nReads the data.\n\n\
+         ```python\n%matplotlib inline\nx = 1\nprint(x)\n```\n\n\
+         ```\n1\n```\n\n\
+         ![png](report_files/output_1_1.png)\n"
+    );
+    assert_eq!(
+        document.files,
+        [("report_files/output_1_1.png".to_string(), b"hello".to_vec())]
+    );
+
+    let dir = std::env::temp_dir().join(format!("nbterm-export-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    notebook
+        .export_markdown_document(dir.join("report.md"))
+        .unwrap();
+    assert_eq!(
+        fs::read(dir.join("report_files/output_1_1.png")).unwrap(),
+        b"hello"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("report.md")).unwrap(),
+        document.markdown
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn markdown_document_keeps_attachments_in_the_files_dir() {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["![a](attachment:../../a.png) ![b](attachment:..)"]);
+    if let Cell::Markdown(cell) = &mut notebook.cells[0] {
+        let image: MimeBundle = [("image/png", json!("aGVsbG8="))].into_iter().collect();
+        cell.attachments = Some(
+            [
+                ("../../a.png".to_string(), image.clone()),
+                ("..".to_string(), image),
+            ]
+            .into(),
+        );
+    }
+    let document = notebook.to_markdown_document("report_files").unwrap();
+    assert_eq!(
+        document.markdown,
+        "![a](report_files/0_a.png) ![b](attachment:..)\n"
+    );
+    assert_eq!(
+        document.files,
+        [("report_files/0_a.png".to_string(), b"hello".to_vec())]
+    );
+}
@@ -0,0 +1,82 @@
+use nbterm::notebook_util::Notebook;
+use nbterm::notebook_util::format::{FormatRegistry, NotebookFormat};
+use nbterm::tui::editor_tab::EditorTab;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn scratch_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nbterm-format-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn name(format: Option<Arc<dyn NotebookFormat>>) -> Option<&'static str> {
+    format.map(|format| format.name())
+}
+
+#[test]
+fn formats_are_picked_by_extension_then_content() {
+    let registry = FormatRegistry::default();
+    let pick =
+        |path: &str, content: Option<&str>| name(registry.for_path(Path::new(path), content));
+
+    assert_eq!(pick("a.ipynb", None), Some("ipynb"));
+    assert_eq!(pick("a.py", None), Some("py:percent"));
+    assert_eq!(pick("a.myst", None), Some("myst"));
+    assert_eq!(pick("a.md", None), Some("md"));
+    assert_eq!(pick("a.md", Some("# Title\n")), Some("md"));
+    assert_eq!(
+        pick("a.md", Some("```{code-cell} python\nx\n```\n")),
+        Some("myst")
+    );
+    assert_eq!(pick("a.txt", Some("{\"cells\": []}")), Some("ipynb"));
+    assert_eq!(pick("a.txt", Some("# %%\nx = 1\n")), Some("py:percent"));
+    assert_eq!(pick("a.txt", Some("plain text")), None);
+}
+
+#[test]
+fn save_as_converts_between_formats() {
+    let registry = FormatRegistry::default();
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Title"]);
+    notebook.push_code_cell(vec!["x = 1"], None, vec![]);
+
+    let ipynb = scratch_file("convert.ipynb");
+    registry
+        .by_name("ipynb")
+        .unwrap()
+        .write_file(&notebook, &ipynb)
+        .unwrap();
+
+    let (loaded, format) = registry.read_file(&ipynb).unwrap();
+    assert_eq!(format.name(), "ipynb");
+
+    let script = scratch_file("convert.py");
+    let percent = registry.for_path(&script, None).unwrap();
+    percent.write_file(&loaded, &script).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&script).unwrap(),
+        "# %% [markdown]\n# # Title\n\n# %%\nx = 1\n"
+    );
+
+    let (reloaded, format) = registry.read_file(&script).unwrap();
+    assert_eq!(format.name(), "py:percent");
+    let sources: Vec<_> = reloaded.iter().map(|cell| cell.source().as_str()).collect();
+    assert_eq!(sources, ["# Title", "x = 1"]);
+}
+
+#[test]
+fn failed_save_as_keeps_the_tab() {
+    let registry = FormatRegistry::default();
+    let path = scratch_file("kept.ipynb");
+    let mut tab = EditorTab::new(path.clone(), &registry);
+    tab.is_dirty = true;
+    let blocker = scratch_file("blocker");
+    std::fs::write(&blocker, "").unwrap();
+
+    assert!(tab.save_as(blocker.join("moved.py"), &registry).is_err());
+    assert_eq!(tab.path, path);
+    assert_eq!(tab.name, "kept.ipynb");
+    assert_eq!(tab.format.name(), "ipynb");
+    assert!(tab.is_dirty);
+}
@@ -0,0 +1,100 @@
+use nbterm::notebook_util::export::{HtmlOptions, ansi};
+use nbterm::notebook_util::{MimeBundle, Notebook, Output};
+use serde_json::json;
+
+fn report() -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec![
+        "# Results & *notes*\n",
+        "\n",
+        "- first `x < 1`\n",
+        "- [link](https://example.com)\n",
+        "\n",
+        "| a | b |\n",
+        "|---|--:|\n",
+        "| 1 | 2 |\n",
+    ]);
+    let mut image = MimeBundle::text_plain("<Figure>");
+    image.insert("image/png", json!("iVBORw0K\nGgo=\n"));
+    let mut html = MimeBundle::text_plain("table");
+    html.insert("text/html", json!("<table><tr><td>1</td></tr></table>"));
+    notebook.push_code_cell(
+        vec!["import os  # comment\n", "print('hi')"],
+        Some(1),
+        vec![
+            Output::stream_stdout("\x1b[31mred\x1b[0m plain\n"),
+            Output::DisplayData {
+                data: image,
+                metadata: json!({}),
+                other: json!({}),
+            },
+            Output::DisplayData {
+                data: html,
+                metadata: json!({}),
+                other: json!({}),
+            },
+        ],
+    );
+    notebook.metadata.language_info = serde_json::from_value(json!({"name": "python"})).ok();
+    notebook
+}
+
+#[test]
+fn renders_markdown_code_and_outputs() {
+    let html = report().to_html(&HtmlOptions::default());
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<h1 id=\"Results--notes\">Results &amp; <em>notes</em></h1>"));
+    assert!(html.contains("<li>first <code>x &lt; 1</code></li>"));
+    assert!(html.contains("<a href=\"https://example.com\">link</a>"));
+    assert!(html.contains("<th style=\"text-align: right\">b</th>"));
+    assert!(
+        html.contains("<span class=\"k\">import</span> os  <span class=\"c\"># comment</span>")
+    );
+    assert!(html.contains("<span style=\"color: #cd3131\">red</span> plain"));
+    assert!(html.contains("<img src=\"data:image/png;base64,iVBORw0KGgo=\">"));
+    assert!(html.contains("<table><tr><td>1</td></tr></table>"));
+}
+
+#[test]
+fn hide_input_keeps_outputs_only() {
+    let options = HtmlOptions {
+        hide_input: true,
+        ..HtmlOptions::default()
+    };
+    let html = report().to_html(&options);
+    assert!(!html.contains("print"));
+    assert!(html.contains("plain"));
+}
+
+#[test]
+fn ansi_sequences_become_styled_runs() {
+    let runs = ansi::parse("a\x1b[1;38;5;196mb\x1b[22mc\x1b]0;title\x07d\x1b[2K");
+    let text: Vec<&str> = runs.iter().map(|(_, run)| run.as_str()).collect();
+    assert_eq!(text, ["a", "b", "cd"]);
+    assert!(runs[1].0.bold);
+    assert_eq!(runs[1].0.fg, Some(ansi::Color::Indexed(196)));
+    assert!(!runs[2].0.bold);
+    assert_eq!(ansi::strip("\x1b[32mok\x1b[0m"), "ok");
+}
+
+#[test]
+fn escapes_untrusted_image_metadata_and_placeholders() {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["a \u{0}7\u{0} `b` \u{0}0\u{0}"]);
+    let mut image = MimeBundle::new();
+    image.insert("image/png", json!("iVBORw0K\"><script>"));
+    notebook.push_code_cell(
+        vec!["plot()"],
+        Some(1),
+        vec![Output::DisplayData {
+            data: image,
+            metadata: json!({"image/png": {"width": "1\" onload=\"alert(1)", "height": 40}}),
+            other: json!({}),
+        }],
+    );
+    let html = notebook.to_html(&HtmlOptions::default());
+    assert!(html.contains("<p>a \u{fffd}7\u{fffd} <code>b</code> \u{fffd}0\u{fffd}</p>"));
+    assert!(html.contains(
+        "<img src=\"data:image/png;base64,iVBORw0K&quot;&gt;&lt;script&gt;\" height=\"40\">"
+    ));
+}
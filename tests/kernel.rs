@@ -0,0 +1,85 @@
+mod common;
+
+use nbterm::kernel::{ConnectionInfo, Message, Signer};
+use nbterm::notebook_util::{Cell, MimeBundle, Notebook, Output};
+use serde_json::json;
+use std::fs;
+
+fn code_cell(source: &str) -> nbterm::notebook_util::CodeCell {
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec![source], None, vec![]);
+    let Some(Cell::Code(cell)) = notebook.cells.pop() else {
+        unreachable!()
+    };
+    cell
+}
+
+#[test]
+fn reads_connection_files_and_signs_messages() {
+    let path = std::env::temp_dir().join(format!("nbterm-kernel-{}.json", std::process::id()));
+    fs::write(
+        &path,
+        r#"{"shell_port": 1, "iopub_port": 2, "stdin_port": 3, "control_port": 4,
+            "hb_port": 5, "ip": "127.0.0.1", "key": "secret", "transport": "tcp",
+            "signature_scheme": "hmac-sha256", "kernel_name": "python3"}"#,
+    )
+    .unwrap();
+    let info = ConnectionInfo::from_file(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(info.endpoint(info.iopub_port), "tcp://127.0.0.1:2");
+    assert_eq!(info.other["kernel_name"], "python3");
+
+    let signer = Signer::new(&info.key);
+    let request = Message::new("execute_request", "session", json!({ "code": "1" }));
+    let reply = request.reply("execute_reply", json!({ "status": "ok" }));
+    let frames = reply.to_frames(&signer).unwrap();
+    let decoded = Message::from_frames(frames.clone(), &signer).unwrap();
+    assert_eq!(decoded, reply);
+    assert_eq!(decoded.parent_id(), Some(request.header.msg_id.as_str()));
+    assert!(Message::from_frames(frames, &Signer::new("other")).is_err());
+}
+
+#[test]
+fn collects_outputs_into_the_cell() {
+    let client = common::client();
+    let mut cell = code_cell("print hello\ndisplay chart\n6 * 7");
+    cell.outputs = vec![Output::stream_stdout("stale\n")];
+    let reply = client.execute_cell(&mut cell, common::TIMEOUT).unwrap();
+    assert_eq!(reply.content["status"], "ok");
+    assert_eq!(cell.execution_count, Some(1));
+    assert_eq!(
+        cell.outputs,
+        [
+            Output::stream_stdout("hello\n"),
+            Output::DisplayData {
+                data: MimeBundle::text_plain("chart"),
+                metadata: json!({}),
+                other: json!({}),
+            },
+            Output::execute_result(1, "6 * 7"),
+        ]
+    );
+}
+
+#[test]
+fn reports_errors_and_honors_clear_output() {
+    let client = common::client();
+    let mut cell = code_cell("print lost\nclear\nfail boom\nprint never");
+    let reply = client.execute_cell(&mut cell, common::TIMEOUT).unwrap();
+    assert_eq!(reply.content["status"], "error");
+    assert_eq!(cell.execution_count, Some(1));
+    let [Output::Error { ename, evalue, .. }] = cell.outputs.as_slice() else {
+        panic!("unexpected outputs {:?}", cell.outputs)
+    };
+    assert_eq!((ename.as_str(), evalue.as_str()), ("Error", "boom"));
+
+    let mut cell = code_cell("print again");
+    client.execute_cell(&mut cell, common::TIMEOUT).unwrap();
+    assert_eq!(cell.execution_count, Some(2));
+}
+
+#[test]
+fn answers_heartbeats() {
+    let client = common::client();
+    assert!(client.is_alive(common::TIMEOUT));
+}
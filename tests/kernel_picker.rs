@@ -0,0 +1,62 @@
+//! Picks a kernel from those installed, so it has a test binary of its own:
+//! it points the process's `JUPYTER_PATH` at a kernel it installs.
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use nbterm::notebook_util::Notebook;
+use nbterm::tui::NotebookApp;
+use serde_json::json;
+use std::fs;
+
+fn press(app: &mut NotebookApp, code: KeyCode) {
+    app.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+}
+
+#[cfg(unix)]
+#[test]
+fn picking_a_kernel_starts_it_and_records_it() {
+    let dir = std::env::temp_dir().join(format!("nbterm-picker-{}", std::process::id()));
+    let spec_dir = dir.join("data/kernels/nbterm-silent");
+    fs::create_dir_all(&spec_dir).unwrap();
+    let spec = json!({
+        "argv": ["sh", "-c", "while :; do sleep 0.05; done", "silent", "{connection_file}"],
+        "display_name": "Silent",
+        "language": "fake",
+    });
+    fs::write(spec_dir.join("kernel.json"), spec.to_string()).unwrap();
+    // SAFETY: the only test of this binary, set before anything else runs
+    unsafe {
+        std::env::set_var("JUPYTER_PATH", dir.join("data"));
+        std::env::set_var("JUPYTER_RUNTIME_DIR", dir.join("runtime"));
+    }
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec![""], None, vec![]);
+    notebook.save_to_file(dir.join("nb.ipynb")).unwrap();
+    let mut app = NotebookApp::with_file(dir.join("nb.ipynb")).unwrap();
+
+    press(&mut app, KeyCode::Char('P'));
+    let picker = app.kernel_picker().unwrap();
+    let count = picker.specs.len();
+    // Esc leaves the kernel alone
+    press(&mut app, KeyCode::Esc);
+    assert!(app.kernel_picker().is_none());
+    press(&mut app, KeyCode::Char('P'));
+    // other kernels installed on the machine may come first
+    for _ in 0..count {
+        let picker = app.kernel_picker().unwrap();
+        if picker.selected().name == "nbterm-silent" {
+            break;
+        }
+        press(&mut app, KeyCode::Down);
+    }
+    press(&mut app, KeyCode::Enter);
+    assert!(app.kernel_picker().is_none());
+
+    let tab = app.current_tab_mut().unwrap();
+    assert!(tab.is_dirty);
+    let metadata = tab.content.metadata.kernelspec.as_ref().unwrap();
+    assert_eq!(metadata.name, "nbterm-silent");
+    let kernel = tab.kernel.take().unwrap();
+    assert_eq!(kernel.spec.name, "nbterm-silent");
+    kernel.shutdown().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,149 @@
+use nbterm::kernel::spec::{KernelSpec, kernelspecs_in, match_kernelspec};
+use nbterm::kernel::{ConnectionInfo, KernelManager};
+use nbterm::notebook_util::{Kernelspec, LanguageInfo, NotebookMetadata};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nbterm-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn install(data_dir: &Path, name: &str, spec: serde_json::Value) {
+    let dir = data_dir.join("kernels").join(name);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("kernel.json"), spec.to_string()).unwrap();
+}
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !done() {
+        assert!(Instant::now() < deadline, "timed out waiting for {what}");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn discovers_kernelspecs_in_order_of_precedence() {
+    let dir = temp_dir("kernelspecs");
+    let (user, system) = (dir.join("user"), dir.join("system"));
+    let spec = |display_name: &str, language: &str| {
+        json!({
+            "argv": ["kernel", "-f", "{connection_file}"],
+            "display_name": display_name,
+            "language": language,
+        })
+    };
+    install(&user, "python3", spec("Python 3 (venv)", "python"));
+    install(&system, "python3", spec("Python 3", "python"));
+    install(&system, "ir", spec("R", "R"));
+    fs::create_dir_all(system.join("kernels").join("broken")).unwrap();
+
+    let specs = kernelspecs_in(&[user.clone(), system.clone()]);
+    let names: Vec<_> = specs
+        .iter()
+        .map(|spec| (spec.name.as_str(), spec.display_name.as_str()))
+        .collect();
+    assert_eq!(names, [("ir", "R"), ("python3", "Python 3 (venv)")]);
+    assert_eq!(specs[1].resource_dir, user.join("kernels").join("python3"));
+    assert!(!specs[1].interrupts_by_message());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn matches_notebook_metadata_by_name_then_language() {
+    let spec = |name: &str, language: &str| KernelSpec {
+        name: name.to_string(),
+        resource_dir: PathBuf::new(),
+        argv: vec![],
+        display_name: name.to_string(),
+        language: language.to_string(),
+        interrupt_mode: None,
+        env: Default::default(),
+        metadata: json!({}),
+    };
+    let specs = [
+        spec("ir", "R"),
+        spec("py-analysis", "python"),
+        spec("python3", "python"),
+    ];
+    let mut metadata = NotebookMetadata::default();
+    assert_eq!(match_kernelspec(&specs, &metadata), None);
+
+    metadata.kernelspec = Some(specs[2].to_metadata());
+    assert_eq!(match_kernelspec(&specs, &metadata), Some(&specs[2]));
+    metadata.kernelspec = Some(Kernelspec {
+        name: "conda-env-r".to_string(),
+        display_name: "R (conda)".to_string(),
+        language: Some("r".to_string()),
+        other: json!({}),
+    });
+    assert_eq!(match_kernelspec(&specs, &metadata), Some(&specs[0]));
+    metadata.kernelspec = None;
+    metadata.language_info = Some(LanguageInfo {
+        name: "python".to_string(),
+        version: None,
+        mimetype: None,
+        file_extension: None,
+        other: json!({}),
+    });
+    assert_eq!(match_kernelspec(&specs, &metadata), Some(&specs[1]));
+}
+
+#[cfg(unix)]
+#[test]
+fn launches_interrupts_restarts_and_shuts_down_kernels() {
+    let dir = temp_dir("kernel-process");
+    // A stand-in kernel that logs SIGINTs and copies its connection file. The
+    // trap goes first: the test interrupts as soon as the copy shows up.
+    install(
+        &dir,
+        "fake",
+        json!({
+            "argv": [
+                "sh", "-c",
+                "trap 'echo interrupted >> signals' INT; cp \"$1\" started.json; while :; do sleep 0.05; done",
+                "fake", "{connection_file}"
+            ],
+            "display_name": "Fake",
+            "language": "fake",
+        }),
+    );
+    let spec = kernelspecs_in(std::slice::from_ref(&dir)).remove(0);
+    let mut kernel = KernelManager::launch(spec, &dir).unwrap();
+    let started = dir.join("started.json");
+    wait_for("the kernel to start", || started.exists());
+    assert_eq!(ConnectionInfo::from_file(&started).unwrap(), kernel.info);
+    assert_eq!(kernel.info.other["kernel_name"], "fake");
+
+    kernel.interrupt().unwrap();
+    wait_for("the interrupt", || {
+        fs::read_to_string(dir.join("signals")).is_ok_and(|log| log == "interrupted\n")
+    });
+    assert!(kernel.is_running());
+
+    fs::remove_file(&started).unwrap();
+    let before = kernel.info.clone();
+    kernel.restart().unwrap();
+    wait_for("the restart", || started.exists());
+    assert!(kernel.is_running());
+    // the new process gets its own ports rather than waiting for the old
+    // one to free them, and keeps the key
+    assert_eq!(ConnectionInfo::from_file(&started).unwrap(), kernel.info);
+    assert_eq!(kernel.info.key, before.key);
+
+    let connection_file = kernel.connection_file().to_path_buf();
+    let permissions = fs::metadata(&connection_file).unwrap().permissions();
+    // the connection file holds the kernel's key
+    assert_eq!(
+        std::os::unix::fs::PermissionsExt::mode(&permissions) & 0o777,
+        0o600
+    );
+    kernel.shutdown().unwrap();
+    assert!(!connection_file.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,183 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use nbterm::kernel::spec::kernelspecs_in;
+use nbterm::kernel::{CellState, KernelManager};
+use nbterm::notebook_util::Notebook;
+use nbterm::notebook_util::merge::merge;
+use nbterm::tui::NotebookApp;
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn scratch(name: &str, notebook: &Notebook) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nbterm-keys-{name}-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    notebook.save_to_file(dir.join("nb.ipynb")).unwrap();
+    dir
+}
+
+fn press(app: &mut NotebookApp, code: KeyCode, modifiers: KeyModifiers) {
+    app.handle_event(Event::Key(KeyEvent::new(code, modifiers)));
+}
+
+fn type_text(app: &mut NotebookApp, text: &str) {
+    for c in text.chars() {
+        press(app, KeyCode::Char(c), KeyModifiers::NONE);
+    }
+}
+
+fn source(app: &NotebookApp) -> String {
+    let tab = app.current_tab().unwrap();
+    tab.content.cells[tab.current_cell]
+        .source()
+        .as_str()
+        .to_string()
+}
+
+#[test]
+fn insert_mode_types_into_the_current_cell() {
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec![""], None, vec![]);
+    let dir = scratch("insert", &notebook);
+    let mut app = NotebookApp::with_file(dir.join("nb.ipynb")).unwrap();
+
+    type_text(&mut app, "x");
+    assert_eq!(source(&app), "");
+    press(&mut app, KeyCode::Char('i'), KeyModifiers::NONE);
+    type_text(&mut app, "if x:");
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    // Tab indents where there is no word to complete
+    press(&mut app, KeyCode::Tab, KeyModifiers::NONE);
+    press(&mut app, KeyCode::Char('Y'), KeyModifiers::SHIFT);
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    type_text(&mut app, "x");
+    assert_eq!(source(&app), "if x:\n    Y");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shifted_letters_run_their_binding() {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Title"]);
+    let dir = scratch("export", &notebook);
+    let mut app = NotebookApp::with_file(dir.join("nb.ipynb")).unwrap();
+
+    press(&mut app, KeyCode::Char('E'), KeyModifiers::SHIFT);
+    let html = fs::read_to_string(dir.join("nb.html")).unwrap();
+    assert!(html.contains("Title"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A kernel process that never answers, enough for cells to queue on.
+#[cfg(unix)]
+fn silent_kernel(dir: &Path) -> KernelManager {
+    let spec_dir = dir.join("kernels/silent");
+    fs::create_dir_all(&spec_dir).unwrap();
+    let spec = json!({
+        "argv": ["sh", "-c", "while :; do sleep 0.05; done", "silent", "{connection_file}"],
+        "display_name": "Silent",
+        "language": "fake",
+    });
+    fs::write(spec_dir.join("kernel.json"), spec.to_string()).unwrap();
+    let spec = kernelspecs_in(&[dir.to_path_buf()]).remove(0);
+    KernelManager::launch(spec, dir).unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn run_keys_queue_cells() {
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec!["print a"], None, vec![]);
+    notebook.push_code_cell(vec!["print b"], None, vec![]);
+    let dir = scratch("run", &notebook);
+    let mut app = NotebookApp::with_file(dir.join("nb.ipynb")).unwrap();
+    app.current_tab_mut().unwrap().kernel = Some(silent_kernel(&dir));
+    let state = |app: &NotebookApp, index: usize| {
+        let tab = app.current_tab().unwrap();
+        let id = tab.content.cells[index].id().unwrap();
+        tab.execution.state(id)
+    };
+
+    press(&mut app, KeyCode::Enter, KeyModifiers::CONTROL);
+    assert_eq!(state(&app, 0), Some(CellState::Queued));
+    assert_eq!(state(&app, 1), None);
+    // Shift-Enter moves on, adding a cell after the last one
+    app.current_tab_mut().unwrap().current_cell = 1;
+    press(&mut app, KeyCode::Enter, KeyModifiers::SHIFT);
+    assert_eq!(state(&app, 1), Some(CellState::Queued));
+    let tab = app.current_tab().unwrap();
+    assert_eq!((tab.current_cell, tab.content.len()), (2, 3));
+
+    let kernel = app.current_tab_mut().unwrap().kernel.take().unwrap();
+    kernel.shutdown().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_keys_open_scroll_and_close_the_diff_view() {
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec![""], None, vec![]);
+    let dir = scratch("diff", &notebook);
+    let mut app = NotebookApp::with_file(dir.join("nb.ipynb")).unwrap();
+    press(&mut app, KeyCode::Char('i'), KeyModifiers::NONE);
+    type_text(&mut app, "x = 1");
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+
+    press(&mut app, KeyCode::Char('D'), KeyModifiers::SHIFT);
+    let scroll = |app: &NotebookApp| app.diff_view().map(|view| view.scroll);
+    assert_eq!(scroll(&app), Some(0));
+    press(&mut app, KeyCode::Char('j'), KeyModifiers::NONE);
+    assert_eq!(scroll(&app), Some(1));
+    press(&mut app, KeyCode::PageDown, KeyModifiers::NONE);
+    let bottom = scroll(&app).unwrap();
+    assert!(bottom >= 1);
+    press(&mut app, KeyCode::Char('k'), KeyModifiers::NONE);
+    assert_eq!(scroll(&app), Some(bottom - 1));
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(scroll(&app), None);
+    // once closed, j no longer scrolls and D opens it again at the top
+    press(&mut app, KeyCode::Char('j'), KeyModifiers::NONE);
+    press(&mut app, KeyCode::Char('D'), KeyModifiers::SHIFT);
+    assert_eq!(scroll(&app), Some(0));
+    press(&mut app, KeyCode::Char('D'), KeyModifiers::SHIFT);
+    assert_eq!(scroll(&app), None);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn conflict_keys_move_between_and_resolve_conflicts() {
+    let mut base = Notebook::default();
+    base.push_markdown_cell(vec!["# Title"]);
+    base.push_code_cell(vec!["x = 1"], None, vec![]);
+    base.push_code_cell(vec!["print(x)"], None, vec![]);
+    let (mut ours, mut theirs) = (base.clone(), base.clone());
+    ours.cells[0].source_mut().0 = "# Ours".to_string();
+    theirs.cells[0].source_mut().0 = "# Theirs".to_string();
+    ours.cells[2].source_mut().0 = "print(x + 1)".to_string();
+    theirs.cells[2].source_mut().0 = "print(x + 2)".to_string();
+    let merged = merge(&base, &ours, &theirs);
+    assert_eq!(merged.conflicts(), [0, 2]);
+    let dir = scratch("conflicts", &merged);
+    let mut app = NotebookApp::with_file(dir.join("nb.ipynb")).unwrap();
+    let current = |app: &NotebookApp| app.current_tab().unwrap().current_cell;
+
+    press(&mut app, KeyCode::Char(']'), KeyModifiers::NONE);
+    assert_eq!(current(&app), 2);
+    press(&mut app, KeyCode::Char(']'), KeyModifiers::NONE);
+    assert_eq!(current(&app), 0);
+    press(&mut app, KeyCode::Char('['), KeyModifiers::NONE);
+    assert_eq!(current(&app), 2);
+    press(&mut app, KeyCode::Char('t'), KeyModifiers::ALT);
+    press(&mut app, KeyCode::Char('['), KeyModifiers::NONE);
+    assert_eq!(current(&app), 0);
+    press(&mut app, KeyCode::Char('o'), KeyModifiers::ALT);
+
+    let tab = app.current_tab().unwrap();
+    assert!(tab.content.conflicts().is_empty());
+    let sources: Vec<_> = tab
+        .content
+        .iter()
+        .map(|cell| cell.source().as_str())
+        .collect();
+    assert_eq!(sources, ["# Ours", "x = 1", "print(x + 2)"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
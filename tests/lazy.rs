@@ -0,0 +1,141 @@
+use base64::Engine;
+use nbterm::notebook_util::{Cell, MimeBundle, Notebook, Output};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+
+fn corpus() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = vec![
+        root.join("example.ipynb"),
+        root.join("tests/notebooks/v3/ipython2.ipynb"),
+    ];
+    for entry in std::fs::read_dir(root.join("tests/notebooks")).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "ipynb") {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+fn scratch_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nbterm-lazy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// A notebook with a 1 MB PNG output and a 1 MB attachment.
+fn large_notebook() -> Notebook {
+    let png = base64::engine::general_purpose::STANDARD.encode(vec![7u8; 1 << 20]);
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["![plot](attachment:plot.png)"]);
+    if let Cell::Markdown(cell) = &mut notebook.cells[0] {
+        let bundle: MimeBundle = [("image/png", Value::String(png.clone()))]
+            .into_iter()
+            .collect();
+        cell.attachments = Some([("plot.png".to_string(), bundle)].into());
+    }
+    notebook.push_code_cell(
+        vec!["plot()"],
+        Some(1),
+        vec![Output::DisplayData {
+            data: [
+                ("image/png", Value::String(png)),
+                ("text/plain", json!("<Figure>")),
+            ]
+            .into_iter()
+            .collect(),
+            metadata: json!({}),
+            other: json!({}),
+        }],
+    );
+    notebook
+}
+
+fn display_data(notebook: &Notebook) -> &MimeBundle {
+    match &notebook.cells[1] {
+        Cell::Code(code) => match &code.outputs[0] {
+            Output::DisplayData { data, .. } => data,
+            output => panic!("unexpected output {output:?}"),
+        },
+        cell => panic!("unexpected cell {cell:?}"),
+    }
+}
+
+/// Parses saved JSON without the cell ids, which are random for notebooks
+/// upgraded from before nbformat 4.5.
+fn without_ids(json: &str) -> Value {
+    let mut value: Value = serde_json::from_str(json).unwrap();
+    for cell in value["cells"].as_array_mut().unwrap() {
+        cell.as_object_mut().unwrap().remove("id");
+    }
+    value
+}
+
+#[test]
+fn lazy_load_saves_like_eager_load() {
+    for path in corpus() {
+        let eager = Notebook::from_file(&path).unwrap();
+        // A threshold of 0 leaves every payload in the file.
+        let lazy = Notebook::from_file_lazy(&path, 0).unwrap();
+        assert_eq!(
+            without_ids(&lazy.save_to_str().unwrap()),
+            without_ids(&eager.save_to_str().unwrap()),
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn large_payloads_load_on_access() {
+    let path = scratch_file("large.ipynb");
+    large_notebook().save_to_file(&path).unwrap();
+
+    let notebook = Notebook::from_file_lazy(&path, 64 * 1024).unwrap();
+    let data = display_data(&notebook);
+    assert!(!data.is_loaded("image/png"));
+    assert!(data.is_loaded("text/plain"));
+    assert_eq!(data.plain(), Some("<Figure>"));
+
+    assert_eq!(data.png().unwrap().unwrap(), vec![7u8; 1 << 20]);
+    assert!(data.is_loaded("image/png"));
+    assert_eq!(notebook, Notebook::from_file(&path).unwrap());
+}
+
+#[test]
+fn saving_over_the_mapped_file_keeps_payloads() {
+    let path = scratch_file("overwrite.ipynb");
+    large_notebook().save_to_file(&path).unwrap();
+
+    let mut notebook = Notebook::from_file_lazy(&path, 1024).unwrap();
+    notebook.push_code_cell(vec!["print(1)"], None, vec![]);
+    notebook.save_to_file(&path).unwrap();
+    // Payloads are still read from the old contents of the file.
+    assert_eq!(
+        display_data(&notebook).png().unwrap().unwrap().len(),
+        1 << 20
+    );
+
+    let reloaded = Notebook::from_file(&path).unwrap();
+    assert_eq!(reloaded, notebook);
+    assert_eq!(reloaded.len(), 3);
+}
+
+#[test]
+fn rewriting_the_file_in_place_keeps_payloads() {
+    let path = scratch_file("rewrite.ipynb");
+    large_notebook().save_to_file(&path).unwrap();
+    let expected = Notebook::from_file(&path).unwrap().save_to_str().unwrap();
+
+    let notebook = Notebook::from_file_lazy(&path, 1024).unwrap();
+    // Truncates the file rather than replacing it, as some editors do.
+    std::fs::write(&path, "{}").unwrap();
+    assert_eq!(notebook.save_to_str().unwrap(), expected);
+    // Saving streams the payloads without keeping them.
+    assert!(!display_data(&notebook).is_loaded("image/png"));
+    assert_eq!(
+        display_data(&notebook).png().unwrap().unwrap(),
+        vec![7u8; 1 << 20]
+    );
+}
@@ -0,0 +1,161 @@
+use nbterm::notebook_util::markdown::MarkdownFlavor;
+use nbterm::notebook_util::{Cell, CellType, Notebook};
+use serde_json::json;
+use std::path::Path;
+
+const PLAIN: &str = r#"---
+jupyter:
+  kernelspec:
+    display_name: Python 3
+    language: python
+    name: python3
+---
+
+# Tutorial
+
+Some text.
+
+
+## Second cell
+
+```python tags=["setup"]
+import os
+```
+
+<!-- #raw -->
+raw text
+<!-- #endraw -->
+"#;
+
+const MYST: &str = r#"---
+kernelspec:
+  display_name: Python 3
+  language: python
+  name: python3
+---
+
+# Tutorial
+
+```bash
+echo "not a cell"
+```
+
+```{code-cell} ipython3
+:tags: [hide-input, remove-output]
+
+x = 1
+```
+
++++ {"slideshow": {"slide_type": "slide"}}
+
+More text.
+"#;
+
+fn types(notebook: &Notebook) -> Vec<CellType> {
+    notebook.iter().map(Cell::cell_type).collect()
+}
+
+#[test]
+fn reads_plain_markdown() {
+    let notebook = Notebook::from_markdown_str(PLAIN, MarkdownFlavor::Plain);
+    assert_eq!(
+        types(&notebook),
+        [
+            CellType::Markdown,
+            CellType::Markdown,
+            CellType::Code,
+            CellType::Raw
+        ]
+    );
+    assert_eq!(
+        notebook.cells[0].source().as_str(),
+        "# Tutorial\n\nSome text."
+    );
+    assert_eq!(notebook.cells[2].source().as_str(), "import os");
+    assert_eq!(notebook.cells[2].metadata(), &json!({"tags": ["setup"]}));
+    assert_eq!(notebook.cells[3].source().as_str(), "raw text");
+    assert_eq!(
+        notebook.metadata.kernelspec.as_ref().unwrap().name,
+        "python3"
+    );
+    assert_eq!(notebook.markdown_flavor(), MarkdownFlavor::Plain);
+}
+
+#[test]
+fn reads_myst_markdown() {
+    assert_eq!(
+        MarkdownFlavor::detect(Path::new("tutorial.md"), MYST),
+        MarkdownFlavor::MyST
+    );
+    let notebook = Notebook::from_markdown_str(MYST, MarkdownFlavor::MyST);
+    assert_eq!(
+        types(&notebook),
+        [CellType::Markdown, CellType::Code, CellType::Markdown]
+    );
+    assert!(notebook.cells[0].source().contains("echo \"not a cell\""));
+    assert_eq!(notebook.cells[1].source().as_str(), "x = 1");
+    assert_eq!(
+        notebook.cells[1].metadata(),
+        &json!({"tags": ["hide-input", "remove-output"]})
+    );
+    assert_eq!(
+        notebook.cells[2].metadata(),
+        &json!({"slideshow": {"slide_type": "slide"}})
+    );
+    assert_eq!(notebook.markdown_flavor(), MarkdownFlavor::MyST);
+}
+
+#[test]
+fn markdown_round_trips_are_stable() {
+    for (text, flavor) in [(PLAIN, MarkdownFlavor::Plain), (MYST, MarkdownFlavor::MyST)] {
+        let notebook = Notebook::from_markdown_str(text, flavor);
+        let written = notebook.to_markdown_string(flavor);
+        let reread = Notebook::from_markdown_str(&written, flavor);
+        assert_eq!(reread.to_markdown_string(flavor), written);
+        assert_eq!(types(&reread), types(&notebook));
+        for (a, b) in reread.iter().zip(notebook.iter()) {
+            assert_eq!(a.source(), b.source());
+            assert_eq!(a.metadata(), b.metadata());
+        }
+    }
+}
+
+#[test]
+fn jupytext_raw_cells_end_at_their_own_marker() {
+    // as Jupytext writes them, the end of a region inside a raw cell
+    // included
+    let text = r#"<!-- #raw -->
+<!-- #endregion -->
+<!-- #endraw -->
+
+After the raw cell.
+
+```python
+x = 1
+```
+"#;
+    let notebook = Notebook::from_markdown_str(text, MarkdownFlavor::Plain);
+    assert_eq!(
+        types(&notebook),
+        [CellType::Raw, CellType::Markdown, CellType::Code]
+    );
+    assert_eq!(notebook.cells[0].source().as_str(), "<!-- #endregion -->");
+    assert_eq!(notebook.to_markdown_string(MarkdownFlavor::Plain), text);
+}
+
+#[test]
+fn adjacent_and_tricky_markdown_cells_stay_separate() {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["one"]);
+    notebook.push_markdown_cell(vec!["two\n", "```\n", "sample\n", "```"]);
+    notebook.push_markdown_cell(vec!["three"]);
+    notebook.push_code_cell(vec!["print('```')"], None, vec![]);
+
+    for flavor in [MarkdownFlavor::Plain, MarkdownFlavor::MyST] {
+        let reread = Notebook::from_markdown_str(&notebook.to_markdown_string(flavor), flavor);
+        assert_eq!(types(&reread), types(&notebook), "{flavor:?}");
+        for (a, b) in reread.iter().zip(notebook.iter()) {
+            assert_eq!(a.source(), b.source(), "{flavor:?}");
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use nbterm::notebook_util::merge::{Resolution, merge};
+use nbterm::notebook_util::{Cell, Notebook, Output};
+use std::fs;
+use std::process::Command;
+
+fn base() -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Analysis"]);
+    notebook.push_code_cell(
+        vec!["import numpy as np\n", "x = np.arange(10)\n", "y = x ** 2"],
+        Some(1),
+        vec![],
+    );
+    notebook.push_code_cell(
+        vec!["print(y.sum())"],
+        Some(2),
+        vec![Output::stream_stdout("285\n")],
+    );
+    notebook
+}
+
+fn set_source(notebook: &mut Notebook, index: usize, source: &str) {
+    notebook.cells[index].source_mut().0 = source.to_string();
+}
+
+fn sources(notebook: &Notebook) -> Vec<&str> {
+    notebook.iter().map(|cell| cell.source().as_str()).collect()
+}
+
+#[test]
+fn combines_non_overlapping_edits() {
+    let base = base();
+    let mut ours = base.clone();
+    set_source(
+        &mut ours,
+        1,
+        "import numpy as np  # fast\nx = np.arange(10)\ny = x ** 2",
+    );
+    ours.push_markdown_cell(vec!["Ours"]);
+    let mut theirs = base.clone();
+    set_source(
+        &mut theirs,
+        1,
+        "import numpy as np\nx = np.arange(10)\ny = x ** 3",
+    );
+    theirs.cells.remove(0);
+    if let Cell::Code(code) = &mut theirs.cells[1] {
+        code.outputs = vec![Output::stream_stdout("2025\n")];
+    }
+
+    let merged = merge(&base, &ours, &theirs);
+    assert!(merged.conflicts().is_empty());
+    assert_eq!(
+        sources(&merged),
+        [
+            "import numpy as np  # fast\nx = np.arange(10)\ny = x ** 3",
+            "print(y.sum())",
+            "Ours"
+        ]
+    );
+    let Cell::Code(code) = &merged.cells[1] else {
+        unreachable!()
+    };
+    assert_eq!(code.outputs, [Output::stream_stdout("2025\n")]);
+}
+
+#[test]
+fn overlapping_edits_become_resolvable_conflict_cells() {
+    let base = base();
+    let mut ours = base.clone();
+    set_source(&mut ours, 2, "print(y.mean())");
+    let mut theirs = base.clone();
+    set_source(&mut theirs, 2, "print(y.max())");
+
+    let mut merged = merge(&base, &ours, &theirs);
+    assert_eq!(merged.conflicts(), [2]);
+    assert_eq!(
+        merged.cells[2].source().as_str(),
+        "<<<<<<< ours\nprint(y.mean())\n=======\nprint(y.max())\n>>>>>>> theirs\n"
+    );
+    assert!(merged.validate().unwrap().is_empty());
+
+    let undo = merged.resolve_conflict(2, Resolution::Theirs).unwrap();
+    assert!(merged.conflicts().is_empty());
+    assert_eq!(merged.cells[2].source().as_str(), "print(y.max())");
+    merged.apply(undo).unwrap();
+    merged.resolve_conflict(2, Resolution::Both).unwrap();
+    assert_eq!(
+        &sources(&merged)[2..],
+        ["print(y.mean())", "print(y.max())"]
+    );
+    assert_ne!(merged.cells[2].id(), merged.cells[3].id());
+}
+
+#[test]
+fn deleting_a_modified_cell_conflicts() {
+    let base = base();
+    let mut ours = base.clone();
+    ours.cells.remove(0);
+    let mut theirs = base.clone();
+    set_source(&mut theirs, 0, "# Results");
+
+    let mut merged = merge(&base, &ours, &theirs);
+    assert_eq!(merged.conflicts(), [0]);
+    merged.resolve_conflict(0, Resolution::Ours).unwrap();
+    assert_eq!(merged.len(), 2);
+}
+
+#[test]
+fn merge_driver_writes_ours_and_reports_conflicts() {
+    let dir = std::env::temp_dir().join(format!("nbterm-merge-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let base = base();
+    let mut ours = base.clone();
+    set_source(&mut ours, 0, "# Ours");
+    let mut theirs = base.clone();
+    set_source(&mut theirs, 2, "print(y)");
+    base.save_to_file(dir.join("base")).unwrap();
+    ours.save_to_file(dir.join("ours")).unwrap();
+    theirs.save_to_file(dir.join("theirs")).unwrap();
+
+    let driver = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_nbterm"))
+            .arg("merge-driver")
+            .args(args)
+            .current_dir(&dir)
+            .status()
+            .unwrap()
+    };
+    assert!(driver(&["base", "ours", "theirs", "nb.ipynb"]).success());
+    let merged = Notebook::from_file(dir.join("ours")).unwrap();
+    assert_eq!(sources(&merged)[0], "# Ours");
+    assert_eq!(sources(&merged)[2], "print(y)");
+
+    theirs.save_to_file(dir.join("ours")).unwrap();
+    set_source(&mut theirs, 2, "print(x)");
+    theirs.save_to_file(dir.join("theirs")).unwrap();
+    assert_eq!(
+        driver(&["base", "ours", "theirs", "nb.ipynb"]).code(),
+        Some(1)
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
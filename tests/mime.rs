@@ -0,0 +1,83 @@
+use nbterm::notebook_util::mime::{self, DEFAULT_MIME_PRIORITY};
+use nbterm::notebook_util::{Cell, MimeBundle, Notebook, Output};
+use serde_json::json;
+use std::path::Path;
+
+fn outputs_of(name: &str, cell: usize) -> Vec<Output> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/notebooks")
+        .join(name);
+    match Notebook::from_file(path).unwrap().cells.remove(cell) {
+        Cell::Code(code) => code.outputs,
+        _ => panic!("expected a code cell"),
+    }
+}
+
+#[test]
+fn typed_access_to_common_mime_types() {
+    let outputs = outputs_of("outputs.ipynb", 2);
+    let Output::DisplayData { data, .. } = &outputs[0] else {
+        panic!("expected display_data");
+    };
+    assert_eq!(data.plain(), Some("<Figure size 640x480 with 1 Axes>"));
+    assert!(data.png().unwrap().unwrap().starts_with(b"\x89PNG"));
+
+    let Output::ExecuteResult { data, .. } = &outputs[1] else {
+        panic!("expected execute_result");
+    };
+    assert_eq!(
+        data.html(),
+        Some("<table>\n  <tr><td>1</td></tr>\n</table>")
+    );
+
+    let outputs = outputs_of("widgets.ipynb", 1);
+    let Output::ExecuteResult { data, .. } = &outputs[0] else {
+        panic!("expected execute_result");
+    };
+    assert_eq!(data.json(), Some(&json!({"a": [1, 2, 3], "b": null})));
+    assert_eq!(data.latex(), Some("$$\\alpha + \\beta$$"));
+    assert_eq!(data.markdown(), Some("**bold** café"));
+}
+
+#[test]
+fn richest_representation_follows_priority() {
+    let bundle: MimeBundle = [
+        (mime::TEXT_PLAIN, json!("x")),
+        (mime::TEXT_HTML, json!("<b>x</b>")),
+        ("application/vnd.custom+json", json!({"x": 1})),
+    ]
+    .into_iter()
+    .collect();
+
+    assert_eq!(
+        bundle.richest(DEFAULT_MIME_PRIORITY).unwrap().0,
+        mime::TEXT_HTML
+    );
+    assert_eq!(
+        bundle
+            .richest(&[mime::IMAGE_PNG, mime::TEXT_PLAIN])
+            .unwrap()
+            .0,
+        mime::TEXT_PLAIN
+    );
+    assert_eq!(bundle.richest(&[mime::IMAGE_PNG]), None);
+}
+
+#[test]
+fn vendor_types_survive_a_round_trip() {
+    let bundle: MimeBundle = serde_json::from_value(json!({
+        "application/vnd.jupyter.widget-view+json": {"model_id": "3f2a"},
+        "application/x-custom": ["kept", " as text"],
+        "text/plain": ["a\n", "b"],
+    }))
+    .unwrap();
+    assert_eq!(bundle.text("application/x-custom"), Some("kept as text"));
+    assert_eq!(
+        serde_json::to_value(&bundle).unwrap(),
+        json!({
+            "application/vnd.jupyter.widget-view+json": {"model_id": "3f2a"},
+            "application/x-custom": "kept as text",
+            "text/plain": ["a\n", "b"],
+        })
+    );
+}
@@ -0,0 +1,94 @@
+use nbterm::notebook_util::{Cell, CellType, Notebook};
+use serde_json::json;
+
+const SCRIPT: &str = r#"# ---
+# jupyter:
+#   jupytext:
+#     text_representation:
+#       extension: .py
+#       format_name: percent
+#       format_version: '1.3'
+#   kernelspec:
+#     display_name: Python 3
+#     language: python
+#     name: python3
+# ---
+
+# %% [markdown]
+# # Title
+#
+# Some *text*.
+
+# %% Imports tags=["parameters"] collapsed=true
+import os
+# %matplotlib inline
+
+# %% [raw]
+# raw text
+"#;
+
+#[test]
+fn reads_cells_metadata_and_header() {
+    let notebook = Notebook::from_percent_str(SCRIPT);
+    let types: Vec<_> = notebook.iter().map(Cell::cell_type).collect();
+    assert_eq!(types, [CellType::Markdown, CellType::Code, CellType::Raw]);
+    assert!(notebook.iter().all(|cell| cell.id().is_some()));
+
+    assert_eq!(
+        notebook.cells[0].source().as_str(),
+        "# Title\n\nSome *text*."
+    );
+    assert_eq!(
+        notebook.cells[1].source().as_str(),
+        "import os\n%matplotlib inline"
+    );
+    assert_eq!(
+        notebook.cells[1].metadata(),
+        &json!({"title": "Imports", "tags": ["parameters"], "collapsed": true})
+    );
+    assert_eq!(notebook.cells[2].source().as_str(), "raw text");
+
+    let kernelspec = notebook.metadata.kernelspec.as_ref().unwrap();
+    assert_eq!(kernelspec.name, "python3");
+    assert_eq!(
+        notebook.metadata.other["jupytext"]["text_representation"]["format_version"],
+        "1.3"
+    );
+}
+
+#[test]
+fn percent_round_trip_is_stable() {
+    let notebook = Notebook::from_percent_str(SCRIPT);
+    let written = notebook.to_percent_string();
+    let reread = Notebook::from_percent_str(&written);
+    assert_eq!(reread.to_percent_string(), written);
+    assert_eq!(reread.len(), notebook.len());
+    for (a, b) in reread.iter().zip(notebook.iter()) {
+        assert_eq!(a.source(), b.source());
+        assert_eq!(a.metadata(), b.metadata());
+    }
+}
+
+#[test]
+fn writes_notebook_cells_as_percent_script() {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Heading\n", "\n", "text"]);
+    notebook.push_code_cell(vec!["!pip install x\n", "a = 1"], Some(3), vec![]);
+    *notebook.cells[1].metadata_mut() = json!({"tags": ["a"]});
+
+    assert_eq!(
+        notebook.to_percent_string(),
+        "# %% [markdown]\n# # Heading\n#\n# text\n\n# %% tags=[\"a\"]\n# !pip install x\na = 1\n"
+    );
+}
+
+#[test]
+fn text_before_first_marker_becomes_a_code_cell() {
+    let notebook = Notebook::from_percent_str("import sys\n\n#%% not=metadata here\nx\n");
+    assert_eq!(notebook.len(), 2);
+    assert_eq!(notebook.cells[0].source().as_str(), "import sys");
+    assert_eq!(
+        notebook.cells[1].metadata(),
+        &json!({"title": "not=metadata here"})
+    );
+}
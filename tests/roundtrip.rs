@@ -0,0 +1,108 @@
+//! Load → save round-trips over a corpus of real-world notebooks.
+//!
+//! The saved JSON must be semantically identical to the input: same keys and
+//! values everywhere, with multiline fields compared after joining, since
+//! nbformat treats `"a\nb"` and `["a\n", "b"]` as the same text.
+
+use nbterm::notebook_util::Notebook;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+fn corpus() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths = vec![root.join("example.ipynb")];
+    let mut notebooks: Vec<PathBuf> = std::fs::read_dir(root.join("tests/notebooks"))
+        .expect("corpus directory exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "ipynb"))
+        .collect();
+    notebooks.sort();
+    paths.extend(notebooks);
+    paths
+}
+
+/// Joins a list-of-strings value into a single string, in place.
+fn join_multiline(value: &mut Value) {
+    if let Value::Array(lines) = value
+        && lines.iter().all(Value::is_string)
+    {
+        *value = Value::String(lines.iter().filter_map(Value::as_str).collect());
+    }
+}
+
+/// Rewrites every multiline field of a notebook into its joined form.
+fn normalize(mut notebook: Value) -> Value {
+    for cell in notebook["cells"].as_array_mut().into_iter().flatten() {
+        join_multiline(&mut cell["source"]);
+        for output in cell
+            .get_mut("outputs")
+            .and_then(Value::as_array_mut)
+            .into_iter()
+            .flatten()
+        {
+            if let Some(text) = output.get_mut("text") {
+                join_multiline(text);
+            }
+            if let Some(Value::Object(data)) = output.get_mut("data") {
+                for (mime, value) in data.iter_mut() {
+                    if mime.starts_with("text/") || mime == "image/svg+xml" {
+                        join_multiline(value);
+                    }
+                }
+            }
+        }
+    }
+    notebook
+}
+
+#[test]
+fn roundtrip_is_semantically_lossless() {
+    for path in corpus() {
+        let original = std::fs::read_to_string(&path).unwrap();
+        let notebook =
+            Notebook::from_str(&original).unwrap_or_else(|e| panic!("{}: {e:#}", path.display()));
+        let saved = notebook.save_to_str().unwrap();
+
+        let expected = normalize(serde_json::from_str(&original).unwrap());
+        let actual = normalize(serde_json::from_str(&saved).unwrap());
+        assert_eq!(expected, actual, "{} changed on round-trip", path.display());
+    }
+}
+
+#[test]
+fn roundtrip_keeps_unknown_keys() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/notebooks/outputs.ipynb");
+    let saved: Value =
+        serde_json::from_str(&Notebook::from_file(&path).unwrap().save_to_str().unwrap()).unwrap();
+
+    assert!(saved["cells"][0]["attachments"]["logo.png"]["image/png"].is_string());
+    assert_eq!(
+        saved["cells"][2]["outputs"][0]["transient"]["display_id"],
+        "fig-1"
+    );
+    assert_eq!(saved["metadata"]["kernelspec"]["language"], "python");
+    assert_eq!(saved["metadata"]["toc"]["number_sections"], true);
+}
+
+#[test]
+fn unmodified_notebooks_save_to_identical_bytes() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // These files were written by `nbformat.write`; the others in the corpus
+    // use the non-canonical single-string form on purpose.
+    for path in [
+        root.join("example.ipynb"),
+        root.join("tests/notebooks/outputs.ipynb"),
+        root.join("tests/notebooks/widgets.ipynb"),
+    ] {
+        let original = std::fs::read_to_string(&path).unwrap();
+        let saved = Notebook::from_str(&original)
+            .unwrap()
+            .save_to_str()
+            .unwrap();
+        assert!(
+            saved == original,
+            "{} is not byte-stable:\n{saved}",
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use nbterm::notebook_util::sanitize::SanitizeRules;
+use nbterm::notebook_util::{Cell, Notebook, Output};
+use serde_json::json;
+
+fn notebook() -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_markdown_cell(vec!["# Title"]);
+    notebook.push_code_cell(vec!["a = 1"], Some(1), vec![Output::execute_result(1, "1")]);
+    notebook.push_code_cell(
+        vec!["print('x' * 1000)"],
+        Some(2),
+        vec![
+            Output::stream_stdout("x".repeat(1000)),
+            Output::stream_stdout("small\n"),
+        ],
+    );
+    for cell in &mut notebook.cells {
+        *cell.metadata_mut() = json!({"collapsed": false, "ExecuteTime": {}, "tags": ["keep"]});
+    }
+    notebook
+}
+
+fn code(cell: &Cell) -> &nbterm::notebook_util::CodeCell {
+    match cell {
+        Cell::Code(code) => code,
+        _ => panic!("not a code cell"),
+    }
+}
+
+#[test]
+fn sanitize_whole_notebook_and_undo() {
+    let original = notebook();
+    let mut notebook = original.clone();
+    let undo = notebook
+        .sanitize(0..notebook.len(), &SanitizeRules::default())
+        .expect("something changed");
+
+    for cell in notebook.iter() {
+        assert_eq!(cell.metadata(), &json!({"tags": ["keep"]}));
+        if let Cell::Code(code) = cell {
+            assert!(code.outputs.is_empty());
+            assert_eq!(code.execution_count, None);
+        }
+    }
+    // Already clean: nothing to do.
+    assert!(
+        notebook
+            .sanitize(0..notebook.len(), &SanitizeRules::default())
+            .is_none()
+    );
+
+    notebook.apply(undo).expect("undo applies");
+    assert_eq!(notebook, original);
+}
+
+#[test]
+fn operations_respect_the_range() {
+    let mut notebook = notebook();
+    notebook.clear_outputs(1..2).unwrap();
+    assert!(code(&notebook.cells[1]).outputs.is_empty());
+    assert_eq!(code(&notebook.cells[2]).outputs.len(), 2);
+
+    notebook.reset_execution_counts(2..3).unwrap();
+    assert_eq!(code(&notebook.cells[1]).execution_count, Some(1));
+    assert_eq!(code(&notebook.cells[2]).execution_count, None);
+
+    assert!(notebook.clear_outputs(2..5).is_none());
+}
+
+#[test]
+fn strip_large_outputs_keeps_small_ones() {
+    let mut notebook = notebook();
+    notebook
+        .strip_large_outputs(0..notebook.len(), 500)
+        .unwrap();
+    let outputs = &code(&notebook.cells[2]).outputs;
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0], Output::stream_stdout("small\n"));
+}
+
+#[test]
+fn rules_deserialize_with_defaults() {
+    let rules: SanitizeRules =
+        serde_json::from_value(json!({"clear_outputs": false, "max_output_bytes": 1024})).unwrap();
+    assert!(!rules.clear_outputs);
+    assert!(rules.reset_execution_counts);
+    assert_eq!(rules.max_output_bytes, Some(1024));
+    assert_eq!(
+        rules.drop_cell_metadata,
+        SanitizeRules::default().drop_cell_metadata
+    );
+}
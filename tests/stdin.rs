@@ -0,0 +1,83 @@
+mod common;
+
+use nbterm::kernel::ExecutionQueue;
+use nbterm::notebook_util::{Cell, Notebook, Output};
+
+fn cell(source: &str) -> Notebook {
+    let mut notebook = Notebook::default();
+    notebook.push_code_cell(vec![source], None, vec![]);
+    notebook
+}
+
+fn outputs(notebook: &Notebook) -> &[Output] {
+    let Cell::Code(cell) = &notebook.cells[0] else {
+        unreachable!()
+    };
+    &cell.outputs
+}
+
+#[test]
+fn answers_input_requests_and_echoes_them() {
+    let client = common::client();
+    let mut notebook = cell("input Name: \nprint done");
+    let mut queue = ExecutionQueue::interactive();
+    queue.enqueue(&mut notebook, 0..1);
+    common::run_until(&mut queue, &mut notebook, &client, |queue| {
+        queue.input_request().is_some()
+    });
+    let request = queue.input_request().unwrap();
+    assert_eq!(
+        (request.prompt.as_str(), request.password),
+        ("Name: ", false)
+    );
+
+    queue.answer_input(&mut notebook, &client, "Ada").unwrap();
+    assert!(queue.input_request().is_none());
+    common::run_until(&mut queue, &mut notebook, &client, |queue| !queue.is_busy());
+    assert_eq!(
+        outputs(&notebook),
+        [Output::stream_stdout("Name: Ada\ngot Ada\ndone\n")]
+    );
+}
+
+#[test]
+fn masks_passwords_in_the_echo() {
+    let client = common::client();
+    let mut notebook = cell("password Password: ");
+    let mut queue = ExecutionQueue::interactive();
+    queue.enqueue(&mut notebook, 0..1);
+    common::run_until(&mut queue, &mut notebook, &client, |queue| {
+        queue.input_request().is_some()
+    });
+    assert!(queue.input_request().unwrap().password);
+    queue
+        .answer_input(&mut notebook, &client, "hunter2")
+        .unwrap();
+    common::run_until(&mut queue, &mut notebook, &client, |queue| !queue.is_busy());
+    assert_eq!(
+        outputs(&notebook),
+        [Output::stream_stdout("Password: ········\ngot hunter2\n")]
+    );
+}
+
+#[test]
+fn input_fails_without_stdin_and_can_be_cancelled() {
+    let client = common::client();
+    let mut notebook = cell("input Name: ");
+    let mut queue = ExecutionQueue::default();
+    queue.enqueue(&mut notebook, 0..1);
+    common::run_until(&mut queue, &mut notebook, &client, |queue| !queue.is_busy());
+    assert!(matches!(
+        outputs(&notebook),
+        [Output::Error { ename, .. }] if ename == "StdinNotImplementedError"
+    ));
+
+    let mut queue = ExecutionQueue::interactive();
+    queue.enqueue(&mut notebook, 0..1);
+    common::run_until(&mut queue, &mut notebook, &client, |queue| {
+        queue.input_request().is_some()
+    });
+    assert_eq!(queue.cancel_input().unwrap().prompt, "Name: ");
+    assert!(queue.input_request().is_none());
+    assert!(queue.is_busy());
+}
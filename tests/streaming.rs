@@ -0,0 +1,81 @@
+mod common;
+
+use nbterm::kernel::ExecutionQueue;
+use nbterm::notebook_util::{Cell, MimeBundle, MultilineString, Notebook, Output};
+use nbterm::tui::editor_tab::EditorTab;
+use nbterm::tui::output_view::OutputView;
+
+/// Runs every cell of `sources` and returns the notebook.
+fn run(sources: &[&str]) -> Notebook {
+    let client = common::client();
+    let mut notebook = Notebook::default();
+    for source in sources {
+        notebook.push_code_cell(vec![*source], None, vec![]);
+    }
+    let mut queue = ExecutionQueue::default();
+    queue.enqueue(&mut notebook, 0..sources.len());
+    common::run(&mut queue, &mut notebook, &client);
+    notebook
+}
+
+fn outputs(notebook: &Notebook, index: usize) -> &[Output] {
+    let Cell::Code(cell) = &notebook.cells[index] else {
+        unreachable!()
+    };
+    &cell.outputs
+}
+
+#[test]
+fn coalesces_stream_chunks_and_collapses_progress_bars() {
+    let notebook = run(&["print start\nwrite \\r 10%\nwrite \\r 50%\\r\nwrite \\n\nprint done"]);
+    assert_eq!(
+        outputs(&notebook, 0),
+        [Output::stream_stdout("start\n 50%\ndone\n")]
+    );
+
+    let mut text = MultilineString::from("abc\rd");
+    text.push_stream("e");
+    assert_eq!(text.collapse_carriage_returns(), "dec");
+    text.push_stream("\r\nnext\rN");
+    assert_eq!(text.as_str(), "dec\nnext\rN");
+    assert_eq!(text.collapse_carriage_returns(), "dec\nNext");
+}
+
+#[test]
+fn clear_output_with_wait_clears_on_the_next_output() {
+    let notebook = run(&["print old\nclear wait\nprint new", "print kept\nclear wait"]);
+    assert_eq!(outputs(&notebook, 0), [Output::stream_stdout("new\n")]);
+    assert_eq!(outputs(&notebook, 1), [Output::stream_stdout("kept\n")]);
+}
+
+#[test]
+fn updates_displays_in_other_cells() {
+    let notebook = run(&["display chart", "update new chart\nprint updated"]);
+    assert!(matches!(
+        outputs(&notebook, 0),
+        [Output::DisplayData { data, .. }] if *data == MimeBundle::text_plain("new chart")
+    ));
+    assert_eq!(outputs(&notebook, 1), [Output::stream_stdout("updated\n")]);
+
+    let saved = notebook.save_to_str().unwrap();
+    assert!(!saved.contains("transient") && !saved.contains("display_id"));
+}
+
+#[test]
+fn output_view_shows_the_current_cells_outputs() {
+    let mut tab = EditorTab::default();
+    tab.content = run(&["write 10%\\r50%\\n", "print other"]);
+    if let Cell::Code(cell) = &mut tab.content.cells[0] {
+        let traceback = vec!["\x1b[31mError\x1b[0m: failed"];
+        cell.outputs
+            .push(Output::error("Error", "failed", traceback));
+    }
+    let view = OutputView::new(&tab).unwrap();
+    assert_eq!(view.title, "Output of cell 1");
+    assert_eq!(view.lines, ["50%", "Error: failed"]);
+    assert_eq!((view.height(10), view.height(3)), (4, 3));
+
+    tab.content.push_markdown_cell(vec!["notes"]);
+    tab.current_cell = 2;
+    assert!(OutputView::new(&tab).is_none());
+}
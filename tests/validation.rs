@@ -0,0 +1,75 @@
+use nbterm::notebook_util::Notebook;
+use nbterm::notebook_util::validation::{self, ValidationMode, Violations};
+use serde_json::json;
+use std::path::Path;
+
+#[test]
+fn corpus_notebooks_written_by_jupyter_are_valid() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    for path in [
+        root.join("example.ipynb"),
+        root.join("tests/notebooks/widgets.ipynb"),
+        root.join("tests/notebooks/r_single_strings.ipynb"),
+    ] {
+        let value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(validation::validate(&value), vec![], "{}", path.display());
+    }
+}
+
+#[test]
+fn violations_carry_precise_paths() {
+    let notebook = json!({
+        "cells": [
+            {"cell_type": "markdown", "id": "a", "metadata": {}, "source": ""},
+            {"cell_type": "code", "id": "b", "metadata": {}, "source": "", "execution_count": 1,
+             "outputs": [{"output_type": "display_data", "metadata": {}, "data": {"text/plain": 3}}]},
+            {"cell_type": "code", "id": "bad id!", "metadata": {}, "source": "",
+             "execution_count": null, "outputs": []},
+            {"cell_type": "nonsense", "source": ""},
+        ],
+        "metadata": {},
+        "nbformat": 4,
+        "nbformat_minor": 5,
+    });
+    let paths: Vec<_> = validation::validate(&notebook)
+        .into_iter()
+        .map(|violation| violation.path)
+        .collect();
+    assert_eq!(
+        paths,
+        vec![
+            r#"cells[1].outputs[0].data["text/plain"]"#,
+            "cells[2].id",
+            "cells[3]",
+        ]
+    );
+}
+
+#[test]
+fn lenient_mode_repairs_missing_metadata_and_null_outputs() {
+    let source = json!({
+        "cells": [{"cell_type": "code", "id": "c", "source": "x", "outputs": null}],
+        "nbformat": 4,
+        "nbformat_minor": 5,
+    })
+    .to_string();
+
+    let err = Notebook::from_str_with_mode(&source, ValidationMode::Strict).unwrap_err();
+    let violations = err.downcast_ref::<Violations>().unwrap();
+    assert!(violations.0.iter().any(|v| v.path.is_empty()));
+    assert!(violations.0.iter().any(|v| v.path == "cells[0].outputs"));
+
+    let (notebook, repaired) =
+        Notebook::from_str_with_mode(&source, ValidationMode::Lenient).unwrap();
+    assert_eq!(notebook.validate().unwrap(), vec![]);
+    let repaired: Vec<_> = repaired.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        repaired,
+        [
+            "<root>: added missing `metadata` as {}",
+            "cells[0]: added missing `metadata` as {}",
+            "cells[0]: added missing `execution_count` as null",
+            "cells[0]: replaced null `outputs` with []",
+        ]
+    );
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use nbterm::kernel::{KernelClient, VariableInspector};
+use nbterm::tui::NotebookApp;
+use nbterm::tui::input_mode::InputMode;
+use nbterm::tui::variables_viewer::{VariableSort, VariablesViewer};
+
+/// Feeds the kernel's messages to `inspector` until its refresh is done.
+fn wait(inspector: &mut VariableInspector, client: &KernelClient) {
+    common::pump(
+        client,
+        inspector,
+        |inspector| !inspector.is_busy(),
+        |inspector, channel, message| {
+            inspector.handle(channel, message);
+        },
+    );
+}
+
+fn inspect() -> VariableInspector {
+    let client = common::client();
+    let mut inspector = VariableInspector::default();
+    inspector.refresh_with(&client, "", "variables").unwrap();
+    wait(&mut inspector, &client);
+    inspector
+}
+
+/// The text of the viewer's rows, with their depth.
+fn rows(viewer: &VariablesViewer) -> Vec<(usize, &str)> {
+    viewer
+        .rows()
+        .iter()
+        .map(|row| (row.depth, row.name))
+        .collect()
+}
+
+#[test]
+fn lists_the_kernels_variables() {
+    let inspector = inspect();
+    assert_eq!(inspector.error, None);
+    let names: Vec<_> = inspector
+        .variables
+        .iter()
+        .map(|variable| variable.name.as_str())
+        .collect();
+    assert_eq!(names, ["frame", "items", "answer"]);
+    assert_eq!(inspector.variables[0].shape_text().as_deref(), Some("3×2"));
+    assert_eq!(inspector.variables[1].children[1].repr, "'two'");
+    assert_eq!(inspector.variables[2].shape, None);
+}
+
+#[test]
+fn reports_failed_and_unsupported_refreshes() {
+    let client = common::client();
+    let mut inspector = VariableInspector::default();
+    inspector.refresh_with(&client, "", "fail no json").unwrap();
+    wait(&mut inspector, &client);
+    assert_eq!(inspector.error.as_deref(), Some("Error: no json"));
+    inspector
+        .refresh_with(&client, "fail no helper", "variables")
+        .unwrap();
+    wait(&mut inspector, &client);
+    assert_eq!(inspector.error.as_deref(), Some("Error: no helper"));
+
+    inspector.refresh(&client, "fake").unwrap();
+    assert!(!inspector.is_busy());
+    assert_eq!(
+        inspector.error.as_deref(),
+        Some("Variables aren't supported for fake kernels")
+    );
+}
+
+#[test]
+fn sorts_filters_expands_and_pins_variables() {
+    let inspector = inspect();
+    let mut viewer = VariablesViewer::default();
+    viewer.set_variables(&inspector.variables, None);
+    assert_eq!(rows(&viewer), [(0, "answer"), (0, "frame"), (0, "items")]);
+    viewer.set_sort(VariableSort::Size);
+    assert_eq!(rows(&viewer), [(0, "frame"), (0, "items"), (0, "answer")]);
+    assert_eq!(viewer.selected_root(), Some("answer"));
+
+    viewer.select_previous();
+    viewer.toggle_expanded();
+    assert_eq!(
+        rows(&viewer),
+        [
+            (0, "frame"),
+            (0, "items"),
+            (1, "0"),
+            (1, "1"),
+            (0, "answer")
+        ]
+    );
+    viewer.select_next();
+    assert_eq!(viewer.selected_root(), Some("items"));
+
+    viewer.select_next();
+    viewer.select_next();
+    viewer.toggle_pinned();
+    viewer.set_filter("FR".to_string());
+    assert_eq!(rows(&viewer), [(0, "answer"), (0, "frame")]);
+
+    viewer.set_variables(&inspector.variables[..2], None);
+    let rows = viewer.rows();
+    assert_eq!((rows[0].name, rows[0].pinned), ("answer", true));
+    assert!(rows[0].variable.is_none());
+}
+
+#[test]
+fn pane_keys_work_once_the_cursor_is_in_the_pane() {
+    let press = |app: &mut NotebookApp, code: KeyCode, modifiers: KeyModifiers| {
+        app.handle_event(Event::Key(KeyEvent::new(code, modifiers)));
+    };
+    let mut app = NotebookApp::default();
+    // the cursor only moves to the pane while it is shown
+    press(&mut app, KeyCode::Char('l'), KeyModifiers::CONTROL);
+    assert!(matches!(app.input_mode(), InputMode::Normal));
+    press(&mut app, KeyCode::Char('v'), KeyModifiers::ALT);
+    press(&mut app, KeyCode::Char('l'), KeyModifiers::CONTROL);
+    assert!(matches!(app.input_mode(), InputMode::UICursor));
+
+    press(&mut app, KeyCode::Char('t'), KeyModifiers::NONE);
+    assert_eq!(app.variables().sort, VariableSort::Type);
+    press(&mut app, KeyCode::Char('/'), KeyModifiers::NONE);
+    for c in "dfx".chars() {
+        press(&mut app, KeyCode::Char(c), KeyModifiers::NONE);
+    }
+    press(&mut app, KeyCode::Backspace, KeyModifiers::NONE);
+    // typed into the prompt, not taken as pane keys
+    assert_eq!(app.variables().sort, VariableSort::Type);
+    press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+    assert_eq!(app.variables().filter, "df");
+    // Esc leaves the filter as it was
+    press(&mut app, KeyCode::Char('/'), KeyModifiers::NONE);
+    press(&mut app, KeyCode::Backspace, KeyModifiers::NONE);
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert_eq!(app.variables().filter, "df");
+
+    press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+    assert!(matches!(app.input_mode(), InputMode::Normal));
+    press(&mut app, KeyCode::Char('s'), KeyModifiers::NONE);
+    assert_eq!(app.variables().sort, VariableSort::Type);
+}